@@ -1,7 +1,9 @@
 use std::borrow::Cow;
+use std::sync::{Arc, Mutex};
 
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use indicatif_log_bridge::LogWrapper;
+use stm32cubeprogrammer::{EventKind, ProgrammerEvent};
 
 /// Display handler which wraps a progress bar and a logger
 #[derive(Debug)]
@@ -16,8 +18,10 @@ impl DisplayHandler {
     pub fn new(logger: env_logger::Logger) -> Self {
         let multi = MultiProgress::new();
 
-        // Installs new global logger
-        LogWrapper::new(multi.clone(), logger).try_init().unwrap();
+        // Installs new global logger. Only the first display handler can install it (e.g. if multiple handlers are created in tests)
+        if LogWrapper::new(multi.clone(), logger).try_init().is_err() {
+            log::debug!("Global logger is already installed");
+        }
 
         let progress_bar = multi.add(ProgressBar::new(0));
         progress_bar.set_style(
@@ -41,11 +45,32 @@ impl DisplayHandler {
     pub fn set_finish(&self) {
         self.progress_bar.finish_and_clear();
     }
-}
 
-/// Implement the display callback trait for the display handler
-/// This allows showing progress and log messages from CubeProgrammer API
-impl stm32cubeprogrammer::DisplayCallback for DisplayHandler {
+    /// Show the progress and log messages from the CubeProgrammer API
+    pub fn handle_event(&self, event: &ProgrammerEvent) {
+        match &event.kind {
+            EventKind::ProgressStarted => self.init_progressbar(),
+            EventKind::Progress { current, total, .. } => self.update_progressbar(*current, *total),
+            EventKind::Log {
+                message_type,
+                message,
+            } => self.log_message(*message_type, message),
+            EventKind::ReconnectAttempt {
+                attempt,
+                max_attempts,
+                connection_mode,
+                ..
+            } => log::warn!(
+                "Connection lost. Reconnect attempt {} of {} ({})",
+                attempt,
+                max_attempts,
+                connection_mode
+            ),
+            EventKind::Reconnected { .. } => log::info!("Reconnected"),
+            EventKind::OperationStarted | EventKind::OperationFinished { .. } => {}
+        }
+    }
+
     fn init_progressbar(&self) {
         self.progress_bar.set_message(self.message.clone());
         self.progress_bar.set_length(0);
@@ -92,3 +117,10 @@ impl stm32cubeprogrammer::DisplayCallback for DisplayHandler {
         self.progress_bar.set_position(current_number);
     }
 }
+
+/// Create an event handler for the CubeProgrammer API which forwards the events to the display handler
+pub fn event_handler(
+    display_handler: Arc<Mutex<DisplayHandler>>,
+) -> Arc<dyn stm32cubeprogrammer::EventHandler> {
+    Arc::new(move |event: &ProgrammerEvent| display_handler.lock().unwrap().handle_event(event))
+}
@@ -2,6 +2,7 @@
 //! # Supported commands
 //! - Flashing bin and hex files
 //! - Updating BLE stack
+//! - Activating the anti-rollback of the BLE stack
 //! - Resetting target
 //! - Mass erasing target
 //! - Enabling read protection
@@ -23,16 +24,33 @@
 //! ```sh
 //! STM32_CUBE_PROGRAMMER_DIR=`installation_dir` stm32cubeprogrammer-cli reset
 //! ```
+//! If no directory is passed, the CLI searches the standard installation locations (e.g. `~/STMicroelectronics/STM32Cube/STM32CubeProgrammer`, `/opt/st`, STM32CubeIDE bundles and the `PATH`) and uses the newest installation.
+//! ```sh
+//! stm32cubeprogrammer-cli reset
+//! ```
 //! You can chain multiple commands together.
 //! ```sh
 //! STM32_CUBE_PROGRAMMER_DIR=`installation_dir` stm32cubeprogrammer-cli unprotect reset flash-hex `path_to_hex_file` protect
 //! ```
 //!
-//! Use the `--list` flag to list available probes.
+//! Use the `--list` flag to list available probes. The details of the probes are part of the JSON output.
 //! ```sh
 //! stm32cubeprogrammer-cli --stm32-cube-programmer-dir `installation_dir` --list
 //! ```
 //!
+//! Use the `--min-target-voltage` option to refuse the connection if the target voltage is below the given value in millivolts (e.g. the target is not powered).
+//! ```sh
+//! stm32cubeprogrammer-cli --min-target-voltage 1700 flash-hex `path_to_hex_file`
+//! ```
+//!
+//! Use the `--record` option to record all calls to the STM32CubeProgrammer API to a JSON-lines trace file. The trace can be replayed with the `ReplayBackend` of the `stm32cubeprogrammer` crate to reproduce a failure.
+//! ```sh
+//! stm32cubeprogrammer-cli --stm32-cube-programmer-dir `installation_dir` --record trace.jsonl flash-hex `path_to_hex_file`
+//! ```
+//!
+//! Pressing Ctrl-C cancels the running command (e.g. a BLE stack update). The CLI disconnects from the target, so the probe can be used again right away.
+//! Pressing Ctrl-C a second time terminates the CLI immediately.
+//!
 //! Use `--help` to see all supported commands and options (or see [`crate::parse::Options`])
 //! ```sh
 //! stm32cubeprogrammer-cli --help
@@ -42,7 +60,7 @@
 //! The output is printed to stdout.
 //!
 //! # Requirements
-//! There needs to be a Stm32CubeProgrammer installation on your system. The crates are tested using Stm32CubeProgrammer version 2.18.0.
+//! There needs to be a Stm32CubeProgrammer installation on your system. The crates are tested using Stm32CubeProgrammer version 2.18.0. Versions from 2.17.0 up to 2.19.x are supported; other versions are loaded with a warning.
 //!
 //! # Platform support
 //! Windows and Linux are supported and tested.
@@ -56,26 +74,28 @@ mod parse;
 
 use anyhow::Context;
 use display_handler::DisplayHandler;
-use log::{error, info};
+use log::{debug, error, info, warn};
 use std::sync::Mutex;
 use stm32cubeprogrammer::probe;
 
 #[derive(Default)]
 /// Track the connection state of the programmer
-enum ConnectionState<'a> {
+enum ConnectionState {
     #[default]
     Disconnected,
-    Connected(Option<stm32cubeprogrammer::ConnectedProgrammer<'a>>),
-    ConnectedFus(Option<stm32cubeprogrammer::ConnectedFusProgrammer<'a>>),
+    Connected(Option<stm32cubeprogrammer::ConnectedProgrammer>),
+    ConnectedFus(Option<stm32cubeprogrammer::ConnectedFusProgrammer>),
 }
 
 /// Helper struct to manage the programmer connection
 struct ProgrammerConnection<'a> {
     api: &'a stm32cubeprogrammer::CubeProgrammer,
-    connection_state: ConnectionState<'a>,
+    connection_state: ConnectionState,
     probe_serial: &'a stm32cubeprogrammer::probe::Serial,
     probe_connection_parameters: stm32cubeprogrammer::probe::ConnectionParameters,
     protocol: stm32cubeprogrammer::probe::Protocol,
+    /// Token which is used by all connections, so Ctrl-C cancels the running command
+    cancellation_token: stm32cubeprogrammer::CancellationToken,
 }
 
 impl<'a> ProgrammerConnection<'a> {
@@ -83,14 +103,16 @@ impl<'a> ProgrammerConnection<'a> {
         api: &'a stm32cubeprogrammer::CubeProgrammer,
         probe_serial: &'a stm32cubeprogrammer::probe::Serial,
         protocol: stm32cubeprogrammer::probe::Protocol,
+        probe_connection_parameters: stm32cubeprogrammer::probe::ConnectionParameters,
+        cancellation_token: stm32cubeprogrammer::CancellationToken,
     ) -> Self {
         Self {
             api,
             connection_state: ConnectionState::Disconnected,
             probe_serial,
-            probe_connection_parameters: stm32cubeprogrammer::probe::ConnectionParameters::default(
-            ),
+            probe_connection_parameters,
             protocol,
+            cancellation_token,
         }
     }
 
@@ -99,7 +121,7 @@ impl<'a> ProgrammerConnection<'a> {
         match &mut self.connection_state {
             ConnectionState::Disconnected => {
                 // Connect to the target directly
-                let programmer = self
+                let mut programmer = self
                     .api
                     .connect_to_target(
                         self.probe_serial,
@@ -107,6 +129,7 @@ impl<'a> ProgrammerConnection<'a> {
                         &self.probe_connection_parameters,
                     )
                     .with_context(|| "Failed to connect to target")?;
+                programmer.set_cancellation_token(self.cancellation_token.clone());
 
                 self.connection_state = ConnectionState::Connected(Some(programmer));
             }
@@ -115,7 +138,7 @@ impl<'a> ProgrammerConnection<'a> {
                 let inner = connected_fus_cube_programmer.take().unwrap();
                 inner.disconnect();
 
-                let programmer = self
+                let mut programmer = self
                     .api
                     .connect_to_target(
                         self.probe_serial,
@@ -123,6 +146,7 @@ impl<'a> ProgrammerConnection<'a> {
                         &self.probe_connection_parameters,
                     )
                     .with_context(|| "Failed to connect to target")?;
+                programmer.set_cancellation_token(self.cancellation_token.clone());
 
                 self.connection_state = ConnectionState::Connected(Some(programmer));
             }
@@ -138,14 +162,15 @@ impl<'a> ProgrammerConnection<'a> {
     /// Try to get a reference to a connected FUS programmer no matter the current connection state
     fn fus_connection(
         &mut self,
-    ) -> Result<&stm32cubeprogrammer::ConnectedFusProgrammer, anyhow::Error> {
+    ) -> Result<&mut stm32cubeprogrammer::ConnectedFusProgrammer, anyhow::Error> {
         match &mut self.connection_state {
             ConnectionState::Disconnected => {
                 // Connect to FUS directly
-                let programmer = self
+                let mut programmer = self
                     .api
                     .connect_to_target_fus(self.probe_serial, &probe::Protocol::Swd)
                     .with_context(|| "Failed to connect to fus target")?;
+                programmer.set_cancellation_token(self.cancellation_token.clone());
 
                 self.connection_state = ConnectionState::ConnectedFus(Some(programmer));
             }
@@ -154,18 +179,19 @@ impl<'a> ProgrammerConnection<'a> {
                 let inner = connected_programmer.take().unwrap();
                 inner.disconnect();
 
-                let programmer = self
+                let mut programmer = self
                     .api
                     .connect_to_target_fus(self.probe_serial, &probe::Protocol::Swd)
                     .with_context(|| "Failed to connect to target")?;
+                programmer.set_cancellation_token(self.cancellation_token.clone());
 
                 self.connection_state = ConnectionState::ConnectedFus(Some(programmer));
             }
             _ => {}
         }
 
-        match &self.connection_state {
-            ConnectionState::ConnectedFus(programmer) => Ok(programmer.as_ref().unwrap()),
+        match &mut self.connection_state {
+            ConnectionState::ConnectedFus(programmer) => Ok(programmer.as_mut().unwrap()),
             _ => unreachable!(),
         }
     }
@@ -197,13 +223,50 @@ fn init_display_handler(verbosity: log::LevelFilter) -> std::sync::Arc<Mutex<Dis
     std::sync::Arc::new(Mutex::new(DisplayHandler::new(logger)))
 }
 
+/// Cancel the running command on Ctrl-C, so the connection is closed properly. A second Ctrl-C terminates immediately
+fn install_ctrlc_handler(
+    cancellation_token: stm32cubeprogrammer::CancellationToken,
+) -> Result<(), anyhow::Error> {
+    ctrlc::set_handler(move || {
+        if cancellation_token.is_cancelled() {
+            std::process::exit(130);
+        }
+
+        warn!("Cancelling... Press Ctrl-C again to terminate immediately");
+        cancellation_token.cancel();
+    })
+    .with_context(|| "Failed to install Ctrl-C handler")
+}
+
+/// Use the given STM32CubeProgrammer directory or search for the preferred installation
+fn cube_programmer_dir(
+    dir: Option<std::path::PathBuf>,
+) -> Result<std::path::PathBuf, anyhow::Error> {
+    if let Some(dir) = dir {
+        return Ok(dir);
+    }
+
+    let discovery = stm32cubeprogrammer::discovery::Discovery::builder().search();
+    let installation = discovery.preferred().ok_or_else(|| {
+        anyhow::anyhow!("No STM32CubeProgrammer installation found. Use --stm32-cube-programmer-dir or STM32_CUBE_PROGRAMMER_DIR")
+    })?;
+
+    info!(
+        "Using STM32CubeProgrammer {} in {}",
+        installation
+            .version
+            .map(|x| x.to_string())
+            .unwrap_or_else(|| "(unknown version)".to_string()),
+        installation.path.display()
+    );
+
+    Ok(installation.path.clone())
+}
+
 fn main_inner() -> Result<crate::output::Output, anyhow::Error> {
     // Parse command line arguments
     let options = parse::options().run();
 
-    let mut cli_output =
-        output::Output::new(std::env::args_os(), &options.stm32_cube_programmer_dir);
-
     let verbosity = if options.quiet {
         log::LevelFilter::Error
     } else {
@@ -212,12 +275,55 @@ fn main_inner() -> Result<crate::output::Output, anyhow::Error> {
 
     // Init api
     let display_handler = init_display_handler(verbosity);
-    let api = stm32cubeprogrammer::CubeProgrammer::builder()
-        .cube_programmer_dir(&options.stm32_cube_programmer_dir)
-        .display_callback(display_handler.clone())
-        .build()
-        .with_context(|| "Failed to create CubeProgrammer API instance")?;
 
+    let stm32_cube_programmer_dir = cube_programmer_dir(options.stm32_cube_programmer_dir.clone())?;
+    let cli_output = output::Output::new(std::env::args_os(), &stm32_cube_programmer_dir);
+
+    let api = match &options.record {
+        Some(trace_path) => {
+            let backend =
+                stm32cubeprogrammer::backend::FfiBackend::load(&stm32_cube_programmer_dir)
+                    .with_context(|| "Failed to create CubeProgrammer API instance")?;
+
+            let backend =
+                stm32cubeprogrammer::backend::RecordingBackend::create(backend, trace_path)
+                    .with_context(|| {
+                        format!("Failed to create trace file {}", trace_path.display())
+                    })?;
+
+            stm32cubeprogrammer::CubeProgrammer::with_backend()
+                .backend(Box::new(backend))
+                .event_handler(display_handler::event_handler(display_handler.clone()))
+                .build()
+                .with_context(|| "Failed to create CubeProgrammer API instance")?
+        }
+        None => stm32cubeprogrammer::CubeProgrammer::builder()
+            .cube_programmer_dir(&stm32_cube_programmer_dir)
+            .event_handler(display_handler::event_handler(display_handler.clone()))
+            .build()
+            .with_context(|| "Failed to create CubeProgrammer API instance")?,
+    };
+
+    let cancellation_token = stm32cubeprogrammer::CancellationToken::new();
+    install_ctrlc_handler(cancellation_token.clone())?;
+
+    run(
+        &api,
+        options,
+        &display_handler,
+        cli_output,
+        &cancellation_token,
+    )
+}
+
+/// Run the parsed commands with the given CubeProgrammer API instance
+fn run(
+    api: &stm32cubeprogrammer::CubeProgrammer,
+    options: parse::Options,
+    display_handler: &Mutex<DisplayHandler>,
+    mut cli_output: output::Output,
+    cancellation_token: &stm32cubeprogrammer::CancellationToken,
+) -> Result<crate::output::Output, anyhow::Error> {
     // Scan for probes
     let probes = api
         .list_available_probes()
@@ -227,11 +333,25 @@ fn main_inner() -> Result<crate::output::Output, anyhow::Error> {
 
     // Early return if the list_probes flag is set
     if options.list_probes {
+        let probes = api
+            .list_probes_detailed()
+            .with_context(|| "Failed to list available probes")?;
+
         if probes.is_empty() {
             info!("No ST-Link probes found");
-        } else {
-            for probe in &probes {
-                info!("{}", probe);
+        }
+
+        // The list is part of the output, so it is not suppressed by --quiet
+        cli_output.add_probe_info(&probes);
+
+        for probe in &probes {
+            debug!("{}", probe);
+
+            if probe.is_old_firmware {
+                warn!(
+                    "The firmware of the ST-Link probe {} is outdated. Please upgrade it",
+                    probe.serial
+                );
             }
         }
 
@@ -248,30 +368,48 @@ fn main_inner() -> Result<crate::output::Output, anyhow::Error> {
     cli_output.add_selected_probe(selected_probe);
 
     // Create a managed connection
-    let mut programmer_connection =
-        ProgrammerConnection::new(&api, selected_probe, options.protocol.into());
-
-    // Connect to the target and add target information to the output
-    cli_output.add_general_information(
-        programmer_connection
-            .connection()
-            .map_err(|x| {
-                error!("Failed to connect to target: {:?}", x);
-                x
-            })?
-            .general_information(),
+    let mut programmer_connection = ProgrammerConnection::new(
+        api,
+        selected_probe,
+        options.protocol.into(),
+        stm32cubeprogrammer::probe::ConnectionParameters {
+            min_target_voltage_mv: options.min_target_voltage_mv,
+            shared: options.shared,
+            low_power_debug: options
+                .low_power_debug
+                .then_some(stm32cubeprogrammer::probe::LowPowerDebug::Enabled),
+            speed_mode: options.speed_mode.map(Into::into),
+            frequency_fallback: options.frequency_fallback,
+            reconnect: options.reconnect_attempts.map(|max_attempts| {
+                stm32cubeprogrammer::probe::ReconnectPolicy {
+                    max_attempts,
+                    ..Default::default()
+                }
+            }),
+            ..Default::default()
+        },
+        cancellation_token.clone(),
     );
 
+    // Connect to the target and add target and connection information to the output
+    let connection = programmer_connection.connection().map_err(|x| {
+        error!("Failed to connect to target: {:?}", x);
+        x
+    })?;
+    cli_output.add_general_information(connection.general_information());
+    cli_output.add_connection_info(&connection.connection_info());
+
     // Check if the command list includes a fus command and if so, check if the current target even supports FUS
     // Early return if the target does not support FUS
-    if options
-        .target_commands
-        .iter()
-        .any(|command| matches!(command, parse::TargetCommand::BleUpdate(_)))
-        && !programmer_connection
-            .connection()?
-            .general_information()
-            .fus_support
+    if options.target_commands.iter().any(|command| {
+        matches!(
+            command,
+            parse::TargetCommand::BleUpdate(_) | parse::TargetCommand::BleAntiRollback { .. }
+        )
+    }) && !programmer_connection
+        .connection()?
+        .general_information()
+        .fus_support
     {
         error!("The target does not support FUS commands");
         return Err(anyhow::anyhow!("The target does not support FUS commands"));
@@ -279,6 +417,11 @@ fn main_inner() -> Result<crate::output::Output, anyhow::Error> {
 
     // Handle commands
     for command in options.target_commands {
+        if cancellation_token.is_cancelled() {
+            error!("Cancelled before command: {:?}", command);
+            return Err(anyhow::anyhow!("Cancelled"));
+        }
+
         let command_output = match command {
             parse::TargetCommand::FlashBin(bin_file_info) => {
                 log::info!("Flash binary file: {}", bin_file_info);
@@ -387,6 +530,28 @@ fn main_inner() -> Result<crate::output::Output, anyhow::Error> {
 
                 output::CommandOutput::BleStackInfo(*fus_programmer.fus_info())
             }
+            parse::TargetCommand::BleAntiRollback { confirm } => {
+                log::info!("Activate BLE stack anti-rollback");
+
+                display_handler
+                    .lock()
+                    .unwrap()
+                    .set_message("Activating BLE stack anti-rollback");
+
+                let fus_programmer = programmer_connection.fus_connection()?;
+
+                let fus_info = *fus_programmer
+                    .activate_anti_rollback(confirm.into())
+                    .with_context(|| "Failed to activate BLE stack anti-rollback")?;
+
+                log::info!("FUS info after anti-rollback activation: {}", fus_info);
+
+                fus_programmer
+                    .start_wireless_stack()
+                    .with_context(|| "Failed to start BLE stack")?;
+
+                output::CommandOutput::BleAntiRollback(fus_info)
+            }
             parse::TargetCommand::Reset(reset_mode) => {
                 log::info!("Resetting target: {:?}", reset_mode);
 
@@ -465,3 +630,121 @@ fn main() -> Result<(), anyhow::Error> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use stm32cubeprogrammer::backend::{simulated::SimulatedTarget, SimulatedBackend};
+
+    /// Run a command line against a simulated target
+    fn run_simulated(
+        backend: &SimulatedBackend,
+        args: &[&str],
+    ) -> Result<output::Output, anyhow::Error> {
+        std::env::set_var("STM32_CUBE_PROGRAMMER_DIR", "some/dir");
+
+        let options = parse::options().run_inner(args).unwrap();
+        let cli_output = output::Output::new(
+            std::env::args_os(),
+            &cube_programmer_dir(options.stm32_cube_programmer_dir.clone()).unwrap(),
+        );
+
+        let api = stm32cubeprogrammer::CubeProgrammer::with_backend()
+            .backend(Box::new(backend.clone()))
+            .build()
+            .unwrap();
+
+        run(
+            &api,
+            options,
+            &init_display_handler(log::LevelFilter::Info),
+            cli_output,
+            &stm32cubeprogrammer::CancellationToken::new(),
+        )
+    }
+
+    #[test]
+    fn run_flash_bin_and_protect() {
+        let backend = SimulatedBackend::new(SimulatedTarget::stm32wb55());
+
+        let file = std::env::temp_dir().join(format!(
+            "stm32cubeprogrammer-cli-{}.bin",
+            std::process::id()
+        ));
+        std::fs::write(&file, [0x01, 0x02, 0x03, 0x04]).unwrap();
+
+        let output = run_simulated(
+            &backend,
+            &[
+                "flash-bin",
+                "--file",
+                file.to_str().unwrap(),
+                "--address",
+                "0x08000000",
+                "protect",
+            ],
+        )
+        .unwrap();
+
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(output.command_output.unwrap().len(), 2);
+        assert_eq!(
+            output.general_information.unwrap().name,
+            SimulatedTarget::stm32wb55().name
+        );
+        assert_eq!(backend.flash()[..4], [0x01, 0x02, 0x03, 0x04]);
+        assert_eq!(backend.option_bit("RDP"), Some(0xBB));
+    }
+
+    #[test]
+    fn run_list_probes() {
+        let backend = SimulatedBackend::new(SimulatedTarget::stm32wb55());
+
+        let output = run_simulated(&backend, &["--quiet", "--list"]).unwrap();
+
+        let probe_info = output.probe_info.unwrap();
+        assert_eq!(probe_info.len(), 1);
+        assert_eq!(probe_info[0].serial, output.connected_probes.unwrap()[0]);
+        assert!(output.command_output.is_none());
+    }
+
+    #[test]
+    fn run_cancelled() {
+        let backend = SimulatedBackend::new(SimulatedTarget::stm32wb55());
+
+        std::env::set_var("STM32_CUBE_PROGRAMMER_DIR", "some/dir");
+        let options = parse::options().run_inner(&["mass-erase"]).unwrap();
+        let cli_output = output::Output::new(
+            std::env::args_os(),
+            &cube_programmer_dir(options.stm32_cube_programmer_dir.clone()).unwrap(),
+        );
+
+        let api = stm32cubeprogrammer::CubeProgrammer::with_backend()
+            .backend(Box::new(backend.clone()))
+            .build()
+            .unwrap();
+        let cancellation_token = stm32cubeprogrammer::CancellationToken::new();
+        cancellation_token.cancel();
+
+        assert!(run(
+            &api,
+            options,
+            &init_display_handler(log::LevelFilter::Info),
+            cli_output,
+            &cancellation_token,
+        )
+        .is_err());
+
+        // The connection is closed, so the probe can be used again
+        assert_eq!(backend.connected_probe(), None);
+        assert_eq!(api.list_available_probes().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn run_ble_command_without_fus() {
+        let backend = SimulatedBackend::new(SimulatedTarget::stm32u585());
+
+        assert!(run_simulated(&backend, &["ble-info"]).is_err());
+    }
+}
@@ -8,8 +8,11 @@ pub struct Output {
     pub args: String,
     pub cube_programmer_dir: std::path::PathBuf,
     pub connected_probes: Option<Vec<stm32cubeprogrammer::probe::Serial>>,
+    /// Details of the connected probes. Only set with the `--list` flag
+    pub probe_info: Option<Vec<stm32cubeprogrammer::probe::ProbeInfo>>,
     pub selected_probe: Option<stm32cubeprogrammer::probe::Serial>,
     pub general_information: Option<stm32cubeprogrammer::api_types::GeneralInformation>,
+    pub connection_info: Option<stm32cubeprogrammer::probe::ConnectionInfo>,
     pub command_output: Option<Vec<CommandOutput>>,
 }
 
@@ -29,8 +32,10 @@ impl Output {
             args,
             cube_programmer_dir: cube_programmer_dir.to_path_buf(),
             connected_probes: None,
+            probe_info: None,
             selected_probe: None,
             general_information: None,
+            connection_info: None,
             command_output: None,
         }
     }
@@ -48,11 +53,24 @@ impl Output {
         self.general_information = Some(general_information.clone());
     }
 
+    /// Add the effective parameters of the target connection
+    pub fn add_connection_info(
+        &mut self,
+        connection_info: &stm32cubeprogrammer::probe::ConnectionInfo,
+    ) {
+        self.connection_info = Some(connection_info.clone());
+    }
+
     /// Add list of connected probes
     pub fn add_probe_list(&mut self, list: &[stm32cubeprogrammer::probe::Serial]) {
         self.connected_probes = Some(list.to_vec());
     }
 
+    /// Add the details of the connected probes
+    pub fn add_probe_info(&mut self, probe_info: &[stm32cubeprogrammer::probe::ProbeInfo]) {
+        self.probe_info = Some(probe_info.to_vec());
+    }
+
     /// Add output of a command
     pub fn add_command_output(&mut self, command: CommandOutput) {
         if let Some(ref mut command_output) = self.command_output {
@@ -85,6 +103,8 @@ pub enum CommandOutput {
     #[serde(rename_all = "camelCase")]
     BleStackInfo(stm32cubeprogrammer::fus::Information),
     #[serde(rename_all = "camelCase")]
+    BleAntiRollback(stm32cubeprogrammer::fus::Information),
+    #[serde(rename_all = "camelCase")]
     Reset {
         reset_mode: stm32cubeprogrammer::api_types::probe::ResetMode,
     },
@@ -21,8 +21,9 @@ pub struct Options {
     pub quiet: bool,
 
     /// Path to the STM32CubeProgrammer root directory (e.g. `C:\Program Files\STMicroelectronics\STM32Cube\STM32CubeProgrammer`)
+    /// If no directory is provided, the newest installation in the standard locations is used
     #[bpaf(long, env("STM32_CUBE_PROGRAMMER_DIR"))]
-    pub stm32_cube_programmer_dir: std::path::PathBuf,
+    pub stm32_cube_programmer_dir: Option<std::path::PathBuf>,
 
     #[bpaf(long("serial"))]
     /// The serial number of the probe to use. If no serial is provided, the first connected probe will be used
@@ -32,10 +33,38 @@ pub struct Options {
     /// The protocol to use for communication with the target
     pub protocol: Protocol,
 
+    #[bpaf(long("min-target-voltage"), argument("MILLIVOLTS"))]
+    /// Refuse to connect if the target voltage is below this value (e.g. the target is not powered)
+    pub min_target_voltage_mv: Option<u32>,
+
+    #[bpaf(long("shared"))]
+    /// Share the probe with other applications (e.g. STM32CubeIDE). Needs a running ST-LINK server
+    pub shared: bool,
+
+    #[bpaf(long("low-power-debug"))]
+    /// Keep the debug connection while the target is in a low-power mode (e.g. Stop mode)
+    pub low_power_debug: bool,
+
+    #[bpaf(long("speed-mode"), argument("MODE"))]
+    /// Speed mode of the probe: reliable or fast
+    pub speed_mode: Option<SpeedMode>,
+
+    #[bpaf(long("frequency-fallback"))]
+    /// Retry with the lower frequencies of the probe if the connection fails (e.g. long cables)
+    pub frequency_fallback: bool,
+
+    #[bpaf(long("reconnect"), argument("ATTEMPTS"))]
+    /// Retry a failed or lost connection up to this number of attempts, escalating from hot-plug to under-reset to hardware reset pulse
+    pub reconnect_attempts: Option<u32>,
+
     #[bpaf(long("list"))]
     /// List available probes. If this flag is set, no other commands will be executed
     pub list_probes: bool,
 
+    #[bpaf(long("record"), argument("TRACE"))]
+    /// Record all calls to the STM32CubeProgrammer API to a JSON-lines trace file
+    pub record: Option<std::path::PathBuf>,
+
     #[bpaf(external(target_command), many)]
     /// Commands to run
     pub target_commands: Vec<TargetCommand>,
@@ -68,6 +97,14 @@ pub enum TargetCommand {
         compare: Option<stm32cubeprogrammer::fus::Version>,
     },
 
+    #[bpaf(command, adjacent)]
+    /// Activate the anti-rollback of the wireless stack. Attention: This cannot be undone!
+    BleAntiRollback {
+        #[bpaf(long)]
+        /// Confirm the activation of the anti-rollback. Without this flag, the command fails
+        confirm: bool,
+    },
+
     #[bpaf(command, adjacent)]
     /// Reset the target
     Reset(#[bpaf(fallback(ResetMode::Hardware), external(reset_mode))] ResetMode),
@@ -187,6 +224,33 @@ impl std::str::FromStr for Protocol {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpeedMode {
+    Reliable,
+    Fast,
+}
+
+impl std::str::FromStr for SpeedMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reliable" => Ok(SpeedMode::Reliable),
+            "fast" => Ok(SpeedMode::Fast),
+            _ => Err("Invalid speed mode".to_string()),
+        }
+    }
+}
+
+impl From<SpeedMode> for stm32cubeprogrammer::probe::SpeedMode {
+    fn from(value: SpeedMode) -> Self {
+        match value {
+            SpeedMode::Reliable => stm32cubeprogrammer::probe::SpeedMode::Reliable,
+            SpeedMode::Fast => stm32cubeprogrammer::probe::SpeedMode::Fast,
+        }
+    }
+}
+
 impl From<Protocol> for stm32cubeprogrammer::probe::Protocol {
     fn from(value: Protocol) -> Self {
         match value {
@@ -233,6 +297,25 @@ mod test {
         );
     }
 
+    #[test]
+    fn parse_ble_anti_rollback() {
+        std::env::set_var("STM32_CUBE_PROGRAMMER_DIR", "some/dir");
+
+        let value = options().run_inner(&["ble-anti-rollback"]).unwrap();
+        assert_eq!(
+            value.target_commands,
+            vec![TargetCommand::BleAntiRollback { confirm: false }]
+        );
+
+        let value = options()
+            .run_inner(&["ble-anti-rollback", "--confirm"])
+            .unwrap();
+        assert_eq!(
+            value.target_commands,
+            vec![TargetCommand::BleAntiRollback { confirm: true }]
+        );
+    }
+
     #[test]
     fn parse_multi() {
         std::env::set_var("STM32_CUBE_PROGRAMMER_DIR", "some/dir");
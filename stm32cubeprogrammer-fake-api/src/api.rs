@@ -0,0 +1,596 @@
+//! Exported C symbols of the fake CubeProgrammer API library
+//!
+//! The functions follow the contract of the CubeProgrammer API library and forward all calls to a [`SimulatedBackend`].
+//! Memory which is handed out to the caller is owned by the [`Api`] state and stays valid until the related free function (or the next call of the same function) is called.
+
+// All exported functions have the safety requirements of the CubeProgrammer API functions with the same name
+#![allow(clippy::missing_safety_doc)]
+#![allow(non_snake_case)]
+
+use std::{
+    collections::HashMap,
+    ffi::{c_char, c_int, c_uchar, c_uint, c_ulong, c_void, CStr, CString},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+};
+use stm32cubeprogrammer::{
+    api_log::{DisplayCallbacks, LogMessageType},
+    api_types::{probe::ResetMode, CoreRegister, ErrorCode, GeneralInformation},
+    backend::{
+        simulated::{SimulatedBackend, SimulatedProbe},
+        Backend, OptionBit,
+    },
+    error::{CubeProgrammerError, CubeProgrammerResult},
+    Verbosity,
+};
+use stm32cubeprogrammer_sys::{
+    bank_C, bit_C, category_C, debugConnectParameters, debugResetMode, displayCallBacks,
+    generalInf, peripheral_C,
+};
+
+/// State of the fake API library
+struct Api {
+    backend: SimulatedBackend,
+    probes: Vec<debugConnectParameters>,
+    general_information: Box<generalInf>,
+    option_bytes: Option<OptionBytes>,
+    hsm_strings: HashMap<(&'static str, c_int), CString>,
+    allocations: HashMap<usize, Box<[u8]>>,
+}
+
+static API: Mutex<Option<Api>> = Mutex::new(None);
+
+/// Cancel flag which is handed out via `getCancelPointer`
+static CANCEL: AtomicI32 = AtomicI32::new(0);
+
+impl Api {
+    fn new(config: &crate::Config) -> Self {
+        let probes = config
+            .probes
+            .iter()
+            .map(|serial| SimulatedProbe {
+                serial: serial.clone(),
+                ..Default::default()
+            })
+            .collect();
+
+        Self {
+            backend: SimulatedBackend::new(config.target.simulated_target()).with_probes(probes),
+            probes: Vec::new(),
+            // All fields are plain integers and arrays, so the all-zero pattern is valid
+            general_information: Box::new(unsafe { std::mem::zeroed() }),
+            option_bytes: None,
+            hsm_strings: HashMap::new(),
+            allocations: HashMap::new(),
+        }
+    }
+}
+
+/// Lock the state. If the loader path was not set before, the default configuration is used
+fn api() -> MutexGuard<'static, Option<Api>> {
+    let mut api = API.lock().unwrap_or_else(|x| x.into_inner());
+
+    if api.is_none() {
+        *api = Some(Api::new(&crate::Config::default()));
+    }
+
+    api
+}
+
+/// Run a function with the state
+/// The cancel flag is passed to the simulation at the start of each call
+fn with_api<T>(f: impl FnOnce(&mut Api) -> T) -> T {
+    let mut api = api();
+    let api = api.as_mut().unwrap();
+
+    api.backend
+        .set_cancel_flag(CANCEL.load(Ordering::SeqCst) != 0);
+
+    f(api)
+}
+
+/// Map the result to the return code of the API
+fn return_code<const SUCCESS: c_int>(result: CubeProgrammerResult<()>) -> c_int {
+    match result {
+        Ok(()) => SUCCESS,
+        Err(CubeProgrammerError::ActionFailed { return_code, .. }) => return_code.into(),
+        Err(_) => ErrorCode::UnknownError.into(),
+    }
+}
+
+/// Convert a wide string argument to a path
+unsafe fn path_from_ptr(ptr: *const widestring::WideChar) -> Option<PathBuf> {
+    if ptr.is_null() {
+        return None;
+    }
+
+    let path = unsafe { widestring::WideCStr::from_ptr_str(ptr) }.to_string_lossy();
+    (!path.is_empty()).then(|| PathBuf::from(path))
+}
+
+/// Option bytes description in the layout of the API. Owns all nodes which are referenced via raw pointers
+struct OptionBytes {
+    peripheral: Box<peripheral_C>,
+    _banks: Box<[*mut bank_C]>,
+    _bank: Box<bank_C>,
+    _categories: Box<[*mut category_C]>,
+    _category: Box<category_C>,
+    _bits: Box<[*mut bit_C]>,
+    _bit_values: Vec<bit_C>,
+}
+
+// The raw pointers only reference the heap allocations owned by the struct
+unsafe impl Send for OptionBytes {}
+
+impl OptionBytes {
+    fn new(option_bits: &[OptionBit]) -> Self {
+        // All fields are plain integers, arrays and pointers, so the all-zero pattern is valid
+        let mut bit_values = option_bits
+            .iter()
+            .map(|option_bit| {
+                let mut bit: bit_C = unsafe { std::mem::zeroed() };
+                copy_to_c_char_slice(&mut bit.name, &option_bit.name);
+                bit.bitValue = option_bit.value;
+                bit
+            })
+            .collect::<Vec<_>>();
+
+        let mut bits = bit_values
+            .iter_mut()
+            .map(|x| x as *mut bit_C)
+            .collect::<Box<[_]>>();
+
+        let mut category: Box<category_C> = Box::new(unsafe { std::mem::zeroed() });
+        copy_to_c_char_slice(&mut category.name, "Option bytes");
+        category.bitsNbr = bits.len() as _;
+        category.bits = bits.as_mut_ptr();
+
+        let mut categories = vec![category.as_mut() as *mut category_C].into_boxed_slice();
+
+        let mut bank: Box<bank_C> = Box::new(unsafe { std::mem::zeroed() });
+        bank.categoriesNbr = categories.len() as _;
+        bank.categories = categories.as_mut_ptr();
+
+        let mut banks = vec![bank.as_mut() as *mut bank_C].into_boxed_slice();
+
+        let mut peripheral: Box<peripheral_C> = Box::new(unsafe { std::mem::zeroed() });
+        copy_to_c_char_slice(&mut peripheral.name, "STM32 FLASH");
+        peripheral.banksNbr = banks.len() as _;
+        peripheral.banks = banks.as_mut_ptr();
+
+        Self {
+            peripheral,
+            _banks: banks,
+            _bank: bank,
+            _categories: categories,
+            _category: category,
+            _bits: bits,
+            _bit_values: bit_values,
+        }
+    }
+}
+
+/// Display callbacks of the caller
+struct CallerDisplayCallbacks(displayCallBacks);
+
+impl DisplayCallbacks for CallerDisplayCallbacks {
+    fn init_progress_bar(&self) {
+        if let Some(init_progressbar) = self.0.initProgressBar {
+            unsafe { init_progressbar() };
+        }
+    }
+
+    fn log_message(&self, message_type: LogMessageType, message: &str) {
+        if let (Some(log_message), Ok(message)) = (
+            self.0.logMessage,
+            widestring::WideCString::from_str(message),
+        ) {
+            unsafe { log_message(message_type.into(), message.as_ptr()) };
+        }
+    }
+
+    fn load_bar(&self, current: i32, total: i32) {
+        if let Some(load_bar) = self.0.loadBar {
+            unsafe { load_bar(current, total) };
+        }
+    }
+}
+
+/// Convert the general information back to the C struct of the API
+fn general_inf(general_information: &GeneralInformation) -> generalInf {
+    // All fields are plain integers and arrays, so the all-zero pattern is valid
+    let mut inf: generalInf = unsafe { std::mem::zeroed() };
+
+    inf.deviceId = general_information.device_id as _;
+    inf.flashSize = general_information.flash_size as _;
+    inf.bootloaderVersion = general_information.bootloader_version as _;
+    copy_to_c_char_slice(&mut inf.type_, &general_information.device_type);
+    copy_to_c_char_slice(&mut inf.cpu, &general_information.cpu);
+    copy_to_c_char_slice(&mut inf.name, &general_information.name);
+    copy_to_c_char_slice(&mut inf.series, &general_information.series);
+    copy_to_c_char_slice(&mut inf.description, &general_information.description);
+    copy_to_c_char_slice(&mut inf.revisionId, &general_information.revision_id);
+    copy_to_c_char_slice(&mut inf.board, &general_information.probe_board);
+
+    inf
+}
+
+fn copy_to_c_char_slice(destination: &mut [c_char], value: &str) {
+    // Keep the last element as null terminator
+    let length = value.len().min(destination.len().saturating_sub(1));
+
+    for (destination, source) in destination.iter_mut().zip(&value.as_bytes()[..length]) {
+        *destination = *source as c_char;
+    }
+}
+
+/// Return a string of a HSM function. The string stays valid until the function is called again for the same slot
+fn hsm_string(
+    function: &'static str,
+    slot_id: c_int,
+    value: impl FnOnce(&SimulatedBackend) -> CubeProgrammerResult<String>,
+) -> *const c_char {
+    with_api(|api| match value(&api.backend).map(CString::new) {
+        Ok(Ok(value)) => api
+            .hsm_strings
+            .entry((function, slot_id))
+            .insert_entry(value)
+            .get()
+            .as_ptr(),
+        _ => std::ptr::null(),
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn setLoadersPath(path: *const c_char) {
+    if path.is_null() {
+        return;
+    }
+
+    let loader_dir = PathBuf::from(unsafe { CStr::from_ptr(path) }.to_string_lossy().as_ref());
+
+    // Read the configuration which is installed next to the loaders
+    let config = match std::fs::read_to_string(loader_dir.join(crate::CONFIG_FILE_NAME)) {
+        Ok(content) => content.parse().unwrap_or_else(|e| {
+            log::error!("Invalid fake API configuration: {}", e);
+            crate::Config::default()
+        }),
+        Err(_) => crate::Config::default(),
+    };
+
+    *API.lock().unwrap_or_else(|x| x.into_inner()) = Some(Api::new(&config));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn setVerbosityLevel(level: c_int) {
+    with_api(|api| api.backend.set_verbosity_level(Verbosity::from(level)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn setDisplayCallbacks(c: displayCallBacks) {
+    with_api(|api| {
+        api.backend
+            .set_display_callbacks(Arc::new(CallerDisplayCallbacks(c)))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn getStLinkList(
+    stLinkList: *mut *mut debugConnectParameters,
+    _shared: c_int,
+) -> c_int {
+    with_api(|api| match api.backend.list_probes() {
+        Ok(probes) => {
+            api.probes = probes.into_iter().map(Into::into).collect();
+
+            if !stLinkList.is_null() {
+                unsafe { *stLinkList = api.probes.as_mut_ptr() };
+            }
+
+            api.probes.len() as c_int
+        }
+        Err(e) => return_code::<0>(Err(e)),
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn deleteInterfaceList() {
+    with_api(|api| api.probes = Vec::new())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn connectStLink(debugParameters: debugConnectParameters) -> c_int {
+    with_api(|api| return_code::<0>(api.backend.connect(&debugParameters.into())))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn getDeviceGeneralInf() -> *mut generalInf {
+    with_api(|api| match api.backend.general_information() {
+        Ok(general_information) => {
+            *api.general_information = general_inf(&general_information);
+            api.general_information.as_mut() as *mut generalInf
+        }
+        Err(_) => std::ptr::null_mut(),
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn disconnect() {
+    with_api(|api| api.backend.disconnect())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn checkDeviceConnection() -> c_int {
+    with_api(|api| return_code::<1>(api.backend.check_connection()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn reset(rstMode: debugResetMode) -> c_int {
+    let reset_mode = ResetMode::try_from(rstMode).unwrap_or_default();
+    with_api(|api| return_code::<0>(api.backend.reset(reset_mode)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn downloadFile(
+    filePath: *const widestring::WideChar,
+    address: c_uint,
+    skipErase: c_uint,
+    verify: c_uint,
+    _binPath: *const widestring::WideChar,
+) -> c_int {
+    let file_path = unsafe { path_from_ptr(filePath) }.unwrap_or_default();
+
+    with_api(|api| {
+        return_code::<0>(api.backend.download_file(
+            &file_path,
+            address,
+            skipErase != 0,
+            verify != 0,
+        ))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn massErase(_sFlashMemName: *mut c_char) -> c_int {
+    with_api(|api| return_code::<0>(api.backend.mass_erase()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn saveMemoryToFile(
+    address: c_int,
+    size: c_int,
+    sFileName: *const widestring::WideChar,
+) -> c_int {
+    let file_path = unsafe { path_from_ptr(sFileName) }.unwrap_or_default();
+
+    with_api(|api| {
+        return_code::<0>(
+            api.backend
+                .save_memory_to_file(&file_path, address as u32, size as u32),
+        )
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn sendOptionBytesCmd(command: *mut c_char) -> c_int {
+    if command.is_null() {
+        return ErrorCode::UnknownParameters.into();
+    }
+
+    let command = unsafe { CStr::from_ptr(command) }.to_string_lossy();
+    with_api(|api| return_code::<0>(api.backend.send_option_bytes_command(&command)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn initOptionBytesInterface() -> *mut peripheral_C {
+    with_api(|api| match api.backend.option_bits() {
+        Ok(option_bits) => api
+            .option_bytes
+            .insert(OptionBytes::new(&option_bits))
+            .peripheral
+            .as_mut() as *mut peripheral_C,
+        Err(_) => std::ptr::null_mut(),
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn readUnprotect() -> c_int {
+    with_api(|api| return_code::<0>(api.backend.read_unprotect()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn tzenRegression() -> c_int {
+    with_api(|api| return_code::<0>(api.backend.tzen_regression()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn readMemory(
+    address: c_uint,
+    data: *mut *mut c_uchar,
+    size: c_uint,
+) -> c_int {
+    with_api(|api| match api.backend.read_memory(address, size) {
+        Ok(bytes) => {
+            let mut bytes = bytes.into_boxed_slice();
+
+            if !data.is_null() {
+                unsafe { *data = bytes.as_mut_ptr() };
+            }
+
+            api.allocations.insert(bytes.as_ptr() as usize, bytes);
+            0
+        }
+        Err(e) => return_code::<0>(Err(e)),
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn freeLibraryMemory(ptr: *mut c_void) {
+    with_api(|api| api.allocations.remove(&(ptr as usize)));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn writeMemory(address: c_uint, data: *mut c_char, size: c_uint) -> c_int {
+    let data = if data.is_null() || size == 0 {
+        &[]
+    } else {
+        unsafe { std::slice::from_raw_parts(data as *const u8, size as usize) }
+    };
+
+    with_api(|api| return_code::<0>(api.backend.write_memory(address, data)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn writeCortexRegistres(reg: c_uint, data: c_uint) -> c_int {
+    with_api(|api| {
+        return_code::<0>(
+            api.backend
+                .write_core_register(CoreRegister::from(reg), data),
+        )
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn readCortexReg(reg: c_uint, data: *mut c_uint) -> c_int {
+    with_api(
+        |api| match api.backend.read_core_register(CoreRegister::from(reg)) {
+            Ok(value) => {
+                if !data.is_null() {
+                    unsafe { *data = value };
+                }
+                0
+            }
+            Err(e) => return_code::<0>(Err(e)),
+        },
+    )
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn getUID64(data: *mut *mut c_uchar) -> c_int {
+    with_api(|api| match api.backend.uid64() {
+        Ok(uid64) => {
+            // Released by the caller via `freeLibraryMemory` like the buffer of `readMemory`
+            let mut bytes: Box<[u8]> = Box::new(uid64.to_le_bytes());
+
+            if !data.is_null() {
+                unsafe { *data = bytes.as_mut_ptr() };
+            }
+
+            api.allocations.insert(bytes.as_ptr() as usize, bytes);
+            0
+        }
+        Err(e) => return_code::<0>(Err(e)),
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn startFus() -> c_int {
+    with_api(|api| return_code::<1>(api.backend.start_fus()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn startWirelessStack() -> c_int {
+    with_api(|api| return_code::<1>(api.backend.start_wireless_stack()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn firmwareDelete() -> c_int {
+    with_api(|api| return_code::<1>(api.backend.firmware_delete()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn firmwareUpgrade(
+    filePath: *const widestring::WideChar,
+    address: c_uint,
+    firstInstall: c_uint,
+    startStack: c_uint,
+    verify: c_uint,
+) -> c_int {
+    let file_path = unsafe { path_from_ptr(filePath) }.unwrap_or_default();
+
+    with_api(|api| {
+        return_code::<1>(api.backend.firmware_upgrade(
+            &file_path,
+            address,
+            firstInstall != 0,
+            startStack != 0,
+            verify != 0,
+        ))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn antiRollBack() -> c_int {
+    with_api(|api| return_code::<1>(api.backend.anti_rollback()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn programSsp(
+    sspFile: *const widestring::WideChar,
+    licenseFile: *const widestring::WideChar,
+    tfaFile: *const widestring::WideChar,
+    hsmSlotId: c_int,
+) -> c_int {
+    let ssp_file = unsafe { path_from_ptr(sspFile) }.unwrap_or_default();
+    let license_file = unsafe { path_from_ptr(licenseFile) };
+    let tfa_file = unsafe { path_from_ptr(tfaFile) }.unwrap_or_default();
+
+    with_api(|api| {
+        return_code::<0>(api.backend.program_ssp(
+            &ssp_file,
+            license_file.as_deref(),
+            &tfa_file,
+            hsmSlotId,
+        ))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn getHsmFirmwareID(hsmSlotId: c_int) -> *const c_char {
+    hsm_string("getHsmFirmwareID", hsmSlotId, |backend| {
+        backend.hsm_firmware_id(hsmSlotId)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn getHsmCounter(hsmSlotId: c_int) -> c_ulong {
+    with_api(|api| api.backend.hsm_counter(hsmSlotId).unwrap_or_default() as c_ulong)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn getHsmState(hsmSlotId: c_int) -> *const c_char {
+    hsm_string("getHsmState", hsmSlotId, |backend| {
+        backend.hsm_state(hsmSlotId)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn getHsmVersion(hsmSlotId: c_int) -> *const c_char {
+    hsm_string("getHsmVersion", hsmSlotId, |backend| {
+        backend.hsm_version(hsmSlotId)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn getHsmType(hsmSlotId: c_int) -> *const c_char {
+    hsm_string("getHsmType", hsmSlotId, |backend| {
+        backend.hsm_type(hsmSlotId)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn getHsmLicense(
+    hsmSlotId: c_int,
+    outLicensePath: *const widestring::WideChar,
+) -> c_int {
+    let file_path = unsafe { path_from_ptr(outLicensePath) }.unwrap_or_default();
+    with_api(|api| return_code::<0>(api.backend.hsm_license(hsmSlotId, &file_path)))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn getCancelPointer() -> *mut c_int {
+    CANCEL.as_ptr()
+}
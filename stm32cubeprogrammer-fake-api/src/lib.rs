@@ -0,0 +1,177 @@
+//! This crate builds a fake CubeProgrammer API library for integration tests.
+//!
+//! The `cdylib` exports the same C symbols as the CubeProgrammer API library (e.g. `getStLinkList`, `connectStLink`, `readMemory`, `downloadFile`).
+//! All calls are executed by the [`stm32cubeprogrammer::backend::SimulatedBackend`], so the real `libloading` path of [`stm32cubeprogrammer::CubeProgrammer`] is exercised without an STM32CubeProgrammer installation and without hardware.
+//!
+//! # Example usage:
+//! ```no_run
+//! use stm32cubeprogrammer_fake_api::{Config, Target};
+//!
+//! let dir = std::env::temp_dir().join("fake-cube-programmer");
+//!
+//! // Lay out the fake library like a STM32CubeProgrammer installation (`lib/` + `bin/`)
+//! stm32cubeprogrammer_fake_api::install(&dir, &Config {
+//!     target: Target::Stm32u585,
+//!     ..Default::default()
+//! })
+//! .expect("Failed to install fake API library");
+//!
+//! let programmer = stm32cubeprogrammer::CubeProgrammer::builder()
+//!     .cube_programmer_dir(&dir)
+//!     .build()
+//!     .expect("Failed to create CubeProgrammer");
+//! ```
+//!
+//! The library is built by cargo next to the test executables, which is where [`library_path`] looks for it.
+
+mod api;
+
+use std::path::{Path, PathBuf};
+
+/// Name of the configuration file in the loader directory (`bin/`)
+pub const CONFIG_FILE_NAME: &str = "fake_api.cfg";
+
+/// Name of the crate library without platform specific prefix and suffix
+const LIBRARY_NAME: &str = "stm32cubeprogrammer_fake_api";
+
+/// Preset of the simulated target
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Target {
+    #[default]
+    Stm32wb55,
+    Stm32u585,
+}
+
+impl Target {
+    pub fn simulated_target(&self) -> stm32cubeprogrammer::backend::simulated::SimulatedTarget {
+        use stm32cubeprogrammer::backend::simulated::SimulatedTarget;
+
+        match self {
+            Target::Stm32wb55 => SimulatedTarget::stm32wb55(),
+            Target::Stm32u585 => SimulatedTarget::stm32u585(),
+        }
+    }
+}
+
+impl std::fmt::Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Stm32wb55 => write!(f, "stm32wb55"),
+            Target::Stm32u585 => write!(f, "stm32u585"),
+        }
+    }
+}
+
+impl std::str::FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stm32wb55" => Ok(Target::Stm32wb55),
+            "stm32u585" => Ok(Target::Stm32u585),
+            _ => Err(format!("Unknown target: {}", s)),
+        }
+    }
+}
+
+/// Configuration of the fake API library
+/// The configuration is stored as `key=value` lines in the [`CONFIG_FILE_NAME`] file and read when the loader path is set
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub target: Target,
+    /// Serial numbers of the simulated probes
+    pub probes: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            target: Target::default(),
+            probes: vec![stm32cubeprogrammer::backend::simulated::SimulatedProbe::default().serial],
+        }
+    }
+}
+
+impl std::fmt::Display for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "target={}", self.target)?;
+        writeln!(f, "probes={}", self.probes.join(","))
+    }
+}
+
+impl std::str::FromStr for Config {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = Config::default();
+
+        for line in s.lines().map(str::trim).filter(|x| !x.is_empty()) {
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid line: {}", line))?;
+
+            match key.trim() {
+                "target" => config.target = value.trim().parse()?,
+                "probes" => {
+                    config.probes = value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|x| !x.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                }
+                _ => return Err(format!("Unknown key: {}", key)),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Path of the fake API library built by cargo
+/// Looks next to the current executable and in its parent directory (e.g. `target/debug/deps` and `target/debug`)
+pub fn library_path() -> Option<PathBuf> {
+    let file_name = format!(
+        "{}{}{}",
+        std::env::consts::DLL_PREFIX,
+        LIBRARY_NAME,
+        std::env::consts::DLL_SUFFIX
+    );
+
+    std::env::current_exe()
+        .ok()?
+        .ancestors()
+        .skip(1)
+        .take(2)
+        .map(|dir| dir.join(&file_name))
+        .find(|path| path.exists())
+}
+
+/// Install the fake API library into a directory with the layout of a STM32CubeProgrammer installation
+/// - Copy the library to the API library path (e.g. `lib/libCubeProgrammer_API.so`)
+/// - Create the loader directory (`bin/`) and write the configuration into it
+pub fn install(cube_programmer_dir: impl AsRef<Path>, config: &Config) -> std::io::Result<()> {
+    let library = library_path().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "Fake API library not found. Is the crate built?",
+        )
+    })?;
+
+    let api_path = cube_programmer_dir
+        .as_ref()
+        .join(stm32cubeprogrammer_sys::PATH_API_LIBRARY_RELATIVE);
+
+    if let Some(api_dir) = api_path.parent() {
+        std::fs::create_dir_all(api_dir)?;
+    }
+
+    std::fs::copy(library, api_path)?;
+
+    let loader_dir = cube_programmer_dir
+        .as_ref()
+        .join(stm32cubeprogrammer_sys::PATH_LOADER_DIR_RELATIVE);
+
+    std::fs::create_dir_all(&loader_dir)?;
+    std::fs::write(loader_dir.join(CONFIG_FILE_NAME), config.to_string())
+}
@@ -0,0 +1,230 @@
+//! Tests which load the fake API library via `libloading` like a real STM32CubeProgrammer installation
+
+use stm32cubeprogrammer::{
+    capabilities::Feature,
+    error::{Action, CubeProgrammerError},
+    probe::{ConnectionMode, ConnectionParameters, Protocol},
+    AsyncCubeProgrammer, CoreRegister, CubeProgrammer, TrustZoneState,
+};
+use stm32cubeprogrammer_fake_api::{Config, Target};
+
+/// Install the fake API library into a temporary directory with the given name
+fn install(name: &str, config: &Config) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "stm32cubeprogrammer-fake-api-{}-{}",
+        std::process::id(),
+        name
+    ));
+
+    stm32cubeprogrammer_fake_api::install(&dir, config).unwrap();
+    dir
+}
+
+#[test_log::test]
+fn config_roundtrip() {
+    let config = Config {
+        target: Target::Stm32u585,
+        probes: vec!["PROBE1".to_string(), "PROBE2".to_string()],
+    };
+
+    assert_eq!(config.to_string().parse::<Config>().unwrap(), config);
+    assert!("target=stm32f4".parse::<Config>().is_err());
+}
+
+#[test_log::test]
+fn load_and_program_target() {
+    let dir = install("program", &Config::default());
+
+    let programmer = CubeProgrammer::builder()
+        .cube_programmer_dir(&dir)
+        .build()
+        .unwrap();
+
+    // The fake library does not export the functions for external loaders and bootloader connections
+    let capabilities = programmer.capabilities();
+    assert!(capabilities.supports(Feature::Flashing));
+    assert!(capabilities.supports(Feature::Hsm));
+    assert!(!capabilities.supports(Feature::ExternalLoaders));
+    assert!(!capabilities.supports(Feature::BootloaderTransports));
+    assert_eq!(
+        capabilities.missing_symbols(Feature::ExternalLoaders),
+        [
+            "setExternalLoaderPath",
+            "getExternalLoaders",
+            "removeExternalLoader"
+        ]
+    );
+
+    let probes = programmer.list_available_probes().unwrap();
+    assert_eq!(probes.len(), 1);
+
+    let connected = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+        .unwrap();
+    assert_eq!(connected.general_information().device_id, 0x495);
+    assert_eq!(connected.general_information().name, "STM32WB5x");
+
+    let address = stm32cubeprogrammer_sys::SRAM_BASE_ADDRESS;
+    connected
+        .write_memory::<u32>(address, &[0x12345678, 0x9ABCDEF0])
+        .unwrap();
+    assert_eq!(
+        connected.read_memory::<u32>(address, 2).unwrap(),
+        [0x12345678, 0x9ABCDEF0]
+    );
+
+    // Vector table with stack pointer and reset handler
+    let firmware = [0x2000_4000u32.to_le_bytes(), 0x0800_0101u32.to_le_bytes()].concat();
+    let file = dir.join("firmware.bin");
+    std::fs::write(&file, &firmware).unwrap();
+
+    let flash_address = stm32cubeprogrammer_sys::FLASH_BASE_ADDRESS;
+    connected
+        .download_bin_file(&file, flash_address, false, true)
+        .unwrap();
+    assert_eq!(
+        connected.read_memory::<u8>(flash_address, 8).unwrap(),
+        firmware
+    );
+
+    // Errors of the simulated target are reported via the return codes of the API, the reason via the log callback
+    let error = connected
+        .write_memory::<u8>(flash_address, &[0xAB])
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        CubeProgrammerError::ActionFailed {
+            action: Action::WriteMemory,
+            ..
+        }
+    ));
+    assert_eq!(
+        error.messages(),
+        ["Flash memory at address 0x08000000 is not erased"]
+    );
+
+    connected
+        .reset_target(stm32cubeprogrammer::probe::ResetMode::Hardware)
+        .unwrap();
+    assert_eq!(
+        connected.read_core_register(CoreRegister::PC).unwrap(),
+        0x0800_0100
+    );
+
+    connected.enable_read_out_protection().unwrap();
+    assert!(connected.read_memory::<u8>(flash_address, 1).is_err());
+
+    connected.disable_read_out_protection().unwrap();
+    assert_eq!(
+        connected.read_memory::<u8>(flash_address, 8).unwrap(),
+        [0xFF; 8]
+    );
+
+    connected.disconnect();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test_log::test]
+fn configured_target_and_probes() {
+    let dir = install(
+        "configured",
+        &Config {
+            target: Target::Stm32u585,
+            probes: vec!["PROBE1".to_string(), "PROBE2".to_string()],
+        },
+    );
+
+    let programmer = CubeProgrammer::builder()
+        .cube_programmer_dir(&dir)
+        .build()
+        .unwrap();
+
+    let probes = programmer.list_available_probes().unwrap();
+    assert_eq!(probes.len(), 2);
+
+    let probe = probes.iter().find(|x| x.to_string() == "PROBE2").unwrap();
+
+    let connected = programmer
+        .connect_to_target(
+            probe,
+            &Protocol::Swd,
+            &ConnectionParameters {
+                connection_mode: ConnectionMode::UnderReset,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+    assert_eq!(connected.general_information().device_id, 0x482);
+    assert_eq!(
+        connected.trustzone_state().unwrap(),
+        TrustZoneState::Enabled
+    );
+
+    connected.disconnect();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test_log::test]
+fn upgrade_wireless_stack_arguments() {
+    let dir = install("fus", &Config::default());
+
+    let programmer = CubeProgrammer::builder()
+        .cube_programmer_dir(&dir)
+        .build()
+        .unwrap();
+
+    let probes = programmer.list_available_probes().unwrap();
+    let connected = programmer
+        .connect_to_target_fus(&probes[0], &Protocol::Swd)
+        .unwrap();
+
+    let stack = dir.join("stack.bin");
+    std::fs::write(&stack, [0x5A; 0x100]).unwrap();
+
+    // The flags are passed in the order of the API header: first install, start stack, verify
+    // Without starting the stack, the FUS keeps running and the stack can be deleted
+    connected
+        .upgrade_wireless_stack(&stack, 0x080C_0000, false, true, false)
+        .unwrap();
+    connected.delete_wireless_stack().unwrap();
+
+    // After starting the stack, the FUS is not running anymore
+    connected
+        .upgrade_wireless_stack(&stack, 0x080C_0000, false, false, true)
+        .unwrap();
+    let error = connected.delete_wireless_stack().unwrap_err();
+    assert_eq!(error.messages(), ["FUS is not running"]);
+
+    connected.disconnect();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test_log::test(tokio::test)]
+async fn async_programmer() {
+    let dir = install("async", &Config::default());
+
+    let programmer = AsyncCubeProgrammer::builder()
+        .cube_programmer_dir(&dir)
+        .build()
+        .unwrap();
+
+    let probes = programmer.list_available_probes().await.unwrap();
+    let connected = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+        .await
+        .unwrap();
+
+    let address = stm32cubeprogrammer_sys::SRAM_BASE_ADDRESS;
+    connected
+        .write_memory::<u32>(address, &[0x12345678])
+        .await
+        .unwrap();
+    assert_eq!(
+        connected.read_memory::<u32>(address, 1).await.unwrap(),
+        [0x12345678]
+    );
+
+    connected.disconnect().await;
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -2,8 +2,37 @@
 
 use log::trace;
 use num_enum::{FromPrimitive, IntoPrimitive};
+use std::{cell::RefCell, collections::VecDeque};
 
-use crate::utility;
+/// Maximum number of error and warning messages which are buffered per API call
+const MAX_CAPTURED_MESSAGES: usize = 16;
+
+thread_local! {
+    /// Error and warning messages of the current API call on this thread. They are attached to the error if the call fails
+    static CAPTURED_MESSAGES: RefCell<VecDeque<String>> = const { RefCell::new(VecDeque::new()) };
+}
+
+/// Take the buffered error and warning messages of the current API call
+pub(crate) fn take_captured_messages() -> Vec<String> {
+    CAPTURED_MESSAGES.with(|x| x.take().into())
+}
+
+/// Discard the buffered messages, e.g. after a successful API call
+pub(crate) fn clear_captured_messages() {
+    CAPTURED_MESSAGES.with(|x| x.borrow_mut().clear());
+}
+
+fn capture_message(message: &str) {
+    CAPTURED_MESSAGES.with(|x| {
+        let mut messages = x.borrow_mut();
+
+        if messages.len() == MAX_CAPTURED_MESSAGES {
+            messages.pop_front();
+        }
+
+        messages.push_back(message.trim().to_string());
+    });
+}
 
 /// Log message type
 #[derive(Debug, Clone, Copy, IntoPrimitive, FromPrimitive, strum::Display)]
@@ -39,55 +68,53 @@ pub enum Verbosity {
     Unknown(i32),
 }
 
-pub(crate) unsafe extern "C" fn display_callback_init_progressbar() {
-    log::trace!("Init progress bar");
+/// Receiver of the log messages and progress updates of the API (see [`crate::backend::Backend::set_display_callbacks`])
+pub trait DisplayCallbacks: Send + Sync {
+    /// Start a new progress bar
+    fn init_progress_bar(&self);
 
-    // Forward to display handler if there is one
-    if let Some(display_handler) = crate::display::get_display_callback_handler() {
-        display_handler.lock().unwrap().init_progressbar();
-    }
-}
+    /// Log message of the given type
+    fn log_message(&self, message_type: LogMessageType, message: &str);
 
-#[cfg(unix)]
-pub(crate) unsafe extern "C" fn display_callback_log_message(level: i32, message: *const u32) {
-    display_callback_log_message_inner(level, &widestring::WideCString::from_ptr_str(message));
+    /// Update the progress bar
+    fn load_bar(&self, current: i32, total: i32);
 }
 
-#[cfg(windows)]
-pub(crate) unsafe extern "C" fn display_callback_log_message(level: i32, message: *const u16) {
-    display_callback_log_message_inner(level, &widestring::WideCString::from_ptr_str(message));
-}
+/// Display callbacks of the [`crate::CubeProgrammer`]. The messages are forwarded to the log and to the events of the current operation
+#[derive(Debug)]
+pub(crate) struct ApiLog;
+
+impl DisplayCallbacks for ApiLog {
+    fn init_progress_bar(&self) {
+        log::trace!("Init progress bar");
 
-pub(crate) unsafe extern "C" fn display_callback_load_bar(
-    mut current_number: i32,
-    total_number: i32,
-) {
-    if total_number == 0 {
-        return;
+        crate::display::progress_started();
     }
 
-    if current_number > total_number {
-        current_number = total_number;
+    fn log_message(&self, message_type: LogMessageType, message: &str) {
+        log_message(message_type, message);
     }
 
-    // Forward to display handler if there is one
-    if let Some(display_handler) = crate::display::get_display_callback_handler() {
-        if current_number < 0 || total_number < 0 {
+    fn load_bar(&self, mut current: i32, total: i32) {
+        if total == 0 {
             return;
         }
 
-        display_handler
-            .lock()
-            .unwrap()
-            .update_progressbar(current_number as u64, total_number as u64);
-    }
+        if current > total {
+            current = total;
+        }
 
-    log::trace!("Update progress bar: {}/{}", current_number, total_number);
-}
+        if current < 0 || total < 0 {
+            return;
+        }
 
-fn display_callback_log_message_inner(level: i32, message: &widestring::WideCString) {
-    let level = LogMessageType::from_primitive(level);
+        crate::display::progress(current as u64, total as u64);
 
+        log::trace!("Update progress bar: {}/{}", current, total);
+    }
+}
+
+fn log_message(level: LogMessageType, message: &str) {
     let log_level = match level {
         LogMessageType::Verbosity3 => log::Level::Trace,
         LogMessageType::Verbosity2 => log::Level::Debug,
@@ -104,18 +131,31 @@ fn display_callback_log_message_inner(level: i32, message: &widestring::WideCStr
         LogMessageType::Unknown(_) => log::Level::Error,
     };
 
-    let converted_message = utility::widestring_to_string(message);
+    trace!("API log - level: {:?}, message: {}", level, message);
 
-    if let Ok(message) = converted_message {
-        trace!("API log - level: {:?}, message: {}", level, message);
+    #[cfg(not(feature = "tracing"))]
+    log::log!(log_level, "{:?}, {}", level, message);
 
-        // Forward to display handler if there is one
-        if let Some(display_handler) = crate::display::get_display_callback_handler() {
-            display_handler.lock().unwrap().log_message(level, &message);
-        }
+    // Emit the message as event inside the span of the current operation
+    #[cfg(feature = "tracing")]
+    match log_level {
+        log::Level::Error => tracing::error!(message_type = %level, "{}", message),
+        log::Level::Warn => tracing::warn!(message_type = %level, "{}", message),
+        log::Level::Info => tracing::info!(message_type = %level, "{}", message),
+        log::Level::Debug => tracing::debug!(message_type = %level, "{}", message),
+        log::Level::Trace => tracing::trace!(message_type = %level, "{}", message),
+    }
 
-        log::log!(log_level, "{:?}, {}", level, message);
-    } else {
-        log::error!("Failed to convert message to string");
+    if matches!(
+        level,
+        LogMessageType::Warning
+            | LogMessageType::WarningNoPopup
+            | LogMessageType::Error
+            | LogMessageType::ErrorNoPopup
+    ) && !message.trim().is_empty()
+    {
+        capture_message(message);
     }
+
+    crate::display::log(level, message.to_string());
 }
@@ -58,6 +58,82 @@ pub enum CoreRegister {
     Unknown(u32),
 }
 
+/// State of the TrustZone of the target (TZEN option bit)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, strum::Display)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub enum TrustZoneState {
+    Disabled,
+    Enabled,
+}
+
+/// Version of a STM32CubeProgrammer installation (e.g. 2.18.0)
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Display)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+#[display("{}.{}.{}", major, minor, patch)]
+pub struct CubeProgrammerVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl CubeProgrammerVersion {
+    /// Versions which are supported by this crate. The bindings are generated from the headers of version 2.18.0
+    pub const SUPPORTED: std::ops::Range<Self> = Self::new(2, 17, 0)..Self::new(2, 20, 0);
+
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        Self::SUPPORTED.contains(self)
+    }
+}
+
+/// Handling of a loaded STM32CubeProgrammer version outside of [`CubeProgrammerVersion::SUPPORTED`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub enum VersionPolicy {
+    /// Log a warning and continue
+    #[default]
+    Warn,
+    /// Fail with [`CubeProgrammerError::UnsupportedVersion`]
+    Error,
+    /// Continue silently
+    Ignore,
+}
+
+impl std::str::FromStr for CubeProgrammerVersion {
+    type Err = CubeProgrammerError;
+
+    /// Parse a version like "2.18.0" or "v2.18.0". A missing patch version is 0
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .trim_start_matches(['v', 'V'])
+            .split('.')
+            .map(|x| x.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>();
+
+        match parts.as_deref() {
+            Ok([major, minor]) => Ok(Self::new(*major, *minor, 0)),
+            Ok([major, minor, patch]) => Ok(Self::new(*major, *minor, *patch)),
+            _ => Err(CubeProgrammerError::TypeConversion {
+                message: format!(
+                    "Cannot convert \"{}\" to a version. Expecting the following format \"major.minor.patch\" e.g. \"2.18.0\"",
+                    s
+                ),
+                source: crate::error::TypeConversionError::VersionError,
+            }),
+        }
+    }
+}
+
 /// Return code which is mapped to an error if it is not equal to SUCCESS
 /// Sometimes success is 0, sometimes it is 1
 #[derive(Debug, From, Into)]
@@ -71,11 +147,47 @@ impl<const SUCCESS: i32> ReturnCode<SUCCESS> {
             Err(CubeProgrammerError::ActionFailed {
                 action,
                 return_code: ErrorCode::from(self.0),
+                messages: Vec::new(),
             })
         }
     }
 }
 
+/// Explicit confirmation for operations which cannot be undone (e.g. activating the anti-rollback of the wireless stack)
+/// The operation is only executed if [`Confirmation::Confirmed`] is passed
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Confirmation {
+    #[default]
+    NotConfirmed,
+    Confirmed,
+}
+
+impl Confirmation {
+    /// Return an error if the operation is not confirmed
+    pub(crate) fn check(&self, action: crate::error::Action) -> CubeProgrammerResult<()> {
+        match self {
+            Confirmation::Confirmed => Ok(()),
+            Confirmation::NotConfirmed => Err(CubeProgrammerError::Parameter {
+                message: format!(
+                    "Action {} is irreversible and needs to be confirmed",
+                    action
+                ),
+                action,
+            }),
+        }
+    }
+}
+
+impl From<bool> for Confirmation {
+    fn from(value: bool) -> Self {
+        if value {
+            Confirmation::Confirmed
+        } else {
+            Confirmation::NotConfirmed
+        }
+    }
+}
+
 pub mod probe {
     use super::*;
 
@@ -125,6 +237,33 @@ pub mod probe {
         HardwareResetPulse,
     }
 
+    #[derive(
+        Debug, Default, Clone, Copy, PartialEq, IntoPrimitive, TryFromPrimitive, strum::Display,
+    )]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+    #[repr(i32)]
+    /// Debug support in low-power modes (`DBG_Sleep`)
+    /// If enabled, the debug connection is kept while the target is in Sleep, Stop or Standby mode
+    pub enum LowPowerDebug {
+        #[default]
+        Disabled,
+        Enabled,
+    }
+
+    #[derive(
+        Debug, Default, Clone, Copy, PartialEq, IntoPrimitive, TryFromPrimitive, strum::Display,
+    )]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+    #[repr(i32)]
+    /// Speed mode of the probe (`speed`)
+    pub enum SpeedMode {
+        #[default]
+        Reliable,
+        Fast,
+    }
+
     /// Frequency for the target connection
     #[derive(Debug, Default, Clone, PartialEq)]
     #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -147,6 +286,31 @@ pub mod probe {
         pub frequency: Frequency,
         pub reset_mode: ResetMode,
         pub connection_mode: ConnectionMode,
+        /// Refuse to connect with [`CubeProgrammerError::TargetVoltageTooLow`] if the target voltage is below this value in millivolts (e.g. the target is not powered)
+        /// The connection is not refused if the probe reports no valid voltage
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub min_target_voltage_mv: Option<u32>,
+        /// Access port of the core to connect to (e.g. the Cortex-M4 of a STM32H745). Must be lower than the number of access ports of the probe ([`ProbeInfo::access_port_number`])
+        /// `None` uses the default access port of the probe
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub access_port: Option<u32>,
+        /// Share the probe with other applications (e.g. a debug session of STM32CubeIDE)
+        /// Needs a running ST-LINK server ([`crate::discovery::stlink_server_running`]), otherwise the connection is refused with [`CubeProgrammerError::StlinkServerNotRunning`]
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub shared: bool,
+        /// Keep the debug connection while the target is in a low-power mode. `None` uses the default of the probe
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub low_power_debug: Option<LowPowerDebug>,
+        /// Speed mode of the probe. `None` uses the default of the probe
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub speed_mode: Option<SpeedMode>,
+        /// If the connection fails, retry with each lower frequency which the probe advertises until a connection succeeds
+        /// The used frequency is reported in [`ConnectionInfo::frequency_khz`]
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub frequency_fallback: bool,
+        /// Retry a failed connection and re-establish a lost connection before the operations of the session. `None` disables the retries
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub reconnect: Option<ReconnectPolicy>,
     }
 
     impl Default for ConnectionParameters {
@@ -155,11 +319,68 @@ pub mod probe {
                 frequency: Frequency::Highest,
                 reset_mode: ResetMode::Hardware,
                 connection_mode: ConnectionMode::Normal,
+                min_target_voltage_mv: None,
+                access_port: None,
+                shared: false,
+                low_power_debug: None,
+                speed_mode: None,
+                frequency_fallback: false,
+                reconnect: None,
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+    /// Retries of failed or lost target connections (e.g. flaky pogo pins)
+    /// Each attempt is reported as [`crate::EventKind::ReconnectAttempt`]
+    pub struct ReconnectPolicy {
+        /// Maximum number of attempts after the connection failed
+        pub max_attempts: u32,
+        /// Delay before the first attempt. The delay is doubled for each further attempt up to `max_backoff`
+        pub backoff: std::time::Duration,
+        pub max_backoff: std::time::Duration,
+        /// Connection modes of the attempts in the order of escalation. The last mode is used for all remaining attempts
+        /// If empty, the connection mode of the connection is used for the connect and hot plug for the re-establish of a lost connection
+        pub escalation: Vec<ConnectionMode>,
+    }
+
+    impl Default for ReconnectPolicy {
+        fn default() -> Self {
+            Self {
+                max_attempts: 3,
+                backoff: std::time::Duration::from_millis(100),
+                max_backoff: std::time::Duration::from_secs(1),
+                escalation: vec![
+                    ConnectionMode::HotPlug,
+                    ConnectionMode::UnderReset,
+                    ConnectionMode::HardwareResetPulse,
+                ],
             }
         }
     }
 
-    #[derive(Debug, Clone, Deref, From, AsRef, Into, Hash, PartialEq, Eq, Display)]
+    impl ReconnectPolicy {
+        /// Delay before the given attempt (starting at 1)
+        pub fn delay(&self, attempt: u32) -> std::time::Duration {
+            self.backoff
+                .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+                .min(self.max_backoff)
+        }
+
+        /// Connection mode of the given attempt (starting at 1). `None` if no escalation is configured
+        pub fn connection_mode(&self, attempt: u32) -> Option<ConnectionMode> {
+            let last = self.escalation.len().checked_sub(1)?;
+            self.escalation
+                .get((attempt.saturating_sub(1) as usize).min(last))
+                .copied()
+        }
+    }
+
+    #[derive(
+        Debug, Clone, Deref, From, AsRef, Into, Hash, PartialEq, Eq, PartialOrd, Ord, Display,
+    )]
     #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
     /// The serial of a probe
@@ -180,10 +401,124 @@ pub mod probe {
         }
     }
 
+    #[derive(Debug, Clone, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+    /// Information about a connected probe
+    pub struct ProbeInfo {
+        pub serial: Serial,
+        /// Name of the board which contains the probe (e.g. `NUCLEO-WB55RG`)
+        pub board: String,
+        pub firmware_version: String,
+        /// The firmware of the probe is outdated and should be upgraded
+        pub is_old_firmware: bool,
+        /// Voltage of the target in millivolts. `None` if the probe reports no valid voltage
+        pub target_voltage_mv: Option<u32>,
+        /// Number of access ports of the target
+        pub access_port_number: u32,
+        /// The probe is a bridge device (e.g. STLINK-V3 with bridge interfaces)
+        pub is_bridge: bool,
+        /// Supported SWD frequencies in kHz, the highest frequency first
+        pub swd_frequencies_khz: Vec<u32>,
+        /// Supported JTAG frequencies in kHz, the highest frequency first
+        pub jtag_frequencies_khz: Vec<u32>,
+    }
+
+    impl From<&Probe> for ProbeInfo {
+        fn from(probe: &Probe) -> Self {
+            Self {
+                serial: Serial(probe.serial_number().to_string()),
+                board: probe.board().to_string(),
+                firmware_version: probe.firmware_version().to_string(),
+                is_old_firmware: probe.0.isOldFirmware != 0,
+                target_voltage_mv: probe.target_voltage_mv(),
+                access_port_number: u32::try_from(probe.0.accessPortNumber).unwrap_or_default(),
+                is_bridge: probe.0.isBridge != 0,
+                swd_frequencies_khz: probe.swd_frequencies().to_vec(),
+                jtag_frequencies_khz: probe.jtag_frequencies().to_vec(),
+            }
+        }
+    }
+
+    impl std::fmt::Display for ProbeInfo {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(
+                f,
+                "STLink (Serial: {}), Board: {}, Firmware version: {}{}, Target voltage: ",
+                self.serial,
+                self.board,
+                self.firmware_version,
+                if self.is_old_firmware {
+                    " (outdated)"
+                } else {
+                    ""
+                },
+            )?;
+
+            match self.target_voltage_mv {
+                Some(voltage) => write!(f, "{} mV", voltage)?,
+                None => write!(f, "Unknown")?,
+            }
+
+            write!(
+                f,
+                ", Access ports: {}, Bridge: {}",
+                self.access_port_number, self.is_bridge
+            )
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+    /// Effective parameters of a target connection as passed to the probe
+    pub struct ConnectionInfo {
+        pub serial: Serial,
+        pub protocol: Protocol,
+        pub connection_mode: ConnectionMode,
+        pub reset_mode: ResetMode,
+        /// Frequency in kHz
+        pub frequency_khz: u32,
+        /// Access port of the core
+        pub access_port: u32,
+        pub shared: bool,
+        pub low_power_debug: LowPowerDebug,
+        pub speed_mode: SpeedMode,
+    }
+
+    impl From<&Probe> for ConnectionInfo {
+        fn from(probe: &Probe) -> Self {
+            Self {
+                serial: Serial(probe.serial_number().to_string()),
+                protocol: probe.debug_port(),
+                connection_mode: probe.connection_mode(),
+                reset_mode: probe.reset_mode(),
+                frequency_khz: probe.frequency_khz(),
+                access_port: probe.access_port(),
+                shared: probe.shared(),
+                low_power_debug: probe.low_power_debug(),
+                speed_mode: probe.speed_mode(),
+            }
+        }
+    }
+
     #[derive(Debug, Clone, Deref)]
     #[repr(transparent)]
+    /// Probe with its connection parameters, which is listed and connected via the [`crate::backend::Backend`]
     /// Transparent wrapper around the [`stm32cubeprogrammer_sys::debugConnectParameters`]
-    pub(crate) struct Probe(pub(crate) stm32cubeprogrammer_sys::debugConnectParameters);
+    pub struct Probe(pub(crate) stm32cubeprogrammer_sys::debugConnectParameters);
+
+    impl From<stm32cubeprogrammer_sys::debugConnectParameters> for Probe {
+        fn from(value: stm32cubeprogrammer_sys::debugConnectParameters) -> Self {
+            Self(value)
+        }
+    }
+
+    impl From<Probe> for stm32cubeprogrammer_sys::debugConnectParameters {
+        fn from(value: Probe) -> Self {
+            value.0
+        }
+    }
 
     impl Probe {
         /// Create a modified version of connect parameters
@@ -197,62 +532,153 @@ pub mod probe {
             debug_probe.set_debug_protocol(*protocol);
             debug_probe.set_reset_mode(connect_parameters.reset_mode);
             debug_probe.set_connection_mode(connect_parameters.connection_mode);
-            debug_probe.set_shared(false);
-
-            let frequency = match (&connect_parameters.frequency, debug_probe.debug_port()) {
-                (Frequency::Custom(custom_frequency), _) => Some(*custom_frequency),
-                (Frequency::Low, Protocol::Jtag) => debug_probe.0.freq.jtagFreq.get(3).copied(),
-                (Frequency::Low, Protocol::Swd) => debug_probe.0.freq.swdFreq.get(3).copied(),
-                (Frequency::Medium, Protocol::Jtag) => debug_probe.0.freq.jtagFreq.get(2).copied(),
-                (Frequency::Medium, Protocol::Swd) => debug_probe.0.freq.swdFreq.get(2).copied(),
-                (Frequency::High, Protocol::Jtag) => debug_probe.0.freq.jtagFreq.get(1).copied(),
-                (Frequency::High, Protocol::Swd) => debug_probe.0.freq.swdFreq.get(1).copied(),
-                (Frequency::Highest, Protocol::Jtag) => {
-                    debug_probe.0.freq.jtagFreq.first().copied()
-                }
-                (Frequency::Highest, Protocol::Swd) => debug_probe.0.freq.swdFreq.first().copied(),
+            debug_probe.set_shared(connect_parameters.shared);
+
+            if let Some(access_port) = connect_parameters.access_port {
+                debug_probe.0.accessPort = access_port as _;
+            }
+
+            if let Some(low_power_debug) = connect_parameters.low_power_debug {
+                debug_probe.0.DBG_Sleep = low_power_debug.into();
+            }
+
+            if let Some(speed_mode) = connect_parameters.speed_mode {
+                debug_probe.0.speed = speed_mode.into();
+            }
+
+            // Entries beyond the advertised table fall back to its lowest entry
+            let advertised = |index: usize| {
+                let frequencies = debug_probe.frequencies();
+                frequencies
+                    .get(index.min(frequencies.len().saturating_sub(1)))
+                    .copied()
+            };
+
+            let frequency = match connect_parameters.frequency {
+                Frequency::Custom(custom_frequency) => Some(custom_frequency),
+                Frequency::Highest => advertised(0),
+                Frequency::High => advertised(1),
+                Frequency::Medium => advertised(2),
+                Frequency::Low => advertised(3),
             };
 
-            debug_assert!(frequency.is_some());
-            debug_probe.0.frequency = frequency.expect("Cannot get frequency") as i32;
+            match frequency {
+                Some(frequency) => debug_probe.set_frequency_khz(frequency),
+                None => log::warn!(
+                    "Probe with serial number {} advertises no frequencies. Use the default frequency of {} kHz",
+                    debug_probe.serial_number(),
+                    debug_probe.0.frequency
+                ),
+            }
 
             debug_probe
         }
 
-        pub(crate) fn serial_number(&self) -> &str {
+        pub fn serial_number(&self) -> &str {
             crate::utility::c_char_slice_to_string(self.0.serialNumber.as_ref())
                 .unwrap_or("Unknown")
                 .trim_matches('\0')
         }
 
-        pub(crate) fn board(&self) -> &str {
+        pub fn board(&self) -> &str {
             crate::utility::c_char_slice_to_string(self.0.board.as_ref())
                 .unwrap_or("Unknown")
                 .trim_matches('\0')
         }
 
-        pub(crate) fn firmware_version(&self) -> &str {
+        pub fn firmware_version(&self) -> &str {
             crate::utility::c_char_slice_to_string(self.0.firmwareVersion.as_ref())
                 .unwrap_or("Unknown")
                 .trim_matches('\0')
         }
 
-        pub(crate) fn debug_port(&self) -> Protocol {
+        /// Access port of the core which is used for the connection
+        pub fn access_port(&self) -> u32 {
+            u32::try_from(self.0.accessPort).unwrap_or_default()
+        }
+
+        /// Target voltage which is reported as string in volts (e.g. "3.28")
+        pub fn target_voltage_mv(&self) -> Option<u32> {
+            let voltage = crate::utility::c_char_slice_to_string(self.0.targetVoltage.as_ref())
+                .ok()?
+                .trim_matches('\0')
+                .trim()
+                .parse::<f32>()
+                .ok()?;
+
+            (voltage.is_finite() && voltage >= 0.0).then(|| (voltage * 1000.0).round() as u32)
+        }
+
+        pub(crate) fn swd_frequencies(&self) -> &[u32] {
+            let frequencies = &self.0.freq.swdFreq;
+            &frequencies[..(self.0.freq.swdFreqNumber as usize).min(frequencies.len())]
+        }
+
+        pub(crate) fn jtag_frequencies(&self) -> &[u32] {
+            let frequencies = &self.0.freq.jtagFreq;
+            &frequencies[..(self.0.freq.jtagFreqNumber as usize).min(frequencies.len())]
+        }
+
+        /// Advertised frequencies in kHz of the selected debug port, the highest frequency first
+        pub fn frequencies(&self) -> &[u32] {
+            match self.debug_port() {
+                Protocol::Jtag => self.jtag_frequencies(),
+                Protocol::Swd => self.swd_frequencies(),
+            }
+        }
+
+        /// Frequency in kHz which is used for the connection
+        pub fn frequency_khz(&self) -> u32 {
+            u32::try_from(self.0.frequency).unwrap_or_default()
+        }
+
+        pub(crate) fn set_frequency_khz(&mut self, frequency: u32) {
+            self.0.frequency = frequency as _;
+        }
+
+        /// Advertised frequencies which are lower than the frequency of the connection, the highest frequency first
+        pub(crate) fn lower_frequencies(&self) -> Vec<u32> {
+            let mut frequencies = self
+                .frequencies()
+                .iter()
+                .copied()
+                .filter(|x| *x < self.frequency_khz())
+                .collect::<Vec<_>>();
+            frequencies.sort_unstable_by(|a, b| b.cmp(a));
+            frequencies.dedup();
+            frequencies
+        }
+
+        pub fn debug_port(&self) -> Protocol {
             Protocol::try_from(self.0.dbgPort).expect("Cannot convert debug port")
         }
 
-        pub(crate) fn connection_mode(&self) -> ConnectionMode {
+        pub fn connection_mode(&self) -> ConnectionMode {
             ConnectionMode::try_from(self.0.connectionMode).expect("Cannot convert connection mode")
         }
 
-        pub(crate) fn reset_mode(&self) -> ResetMode {
+        pub fn reset_mode(&self) -> ResetMode {
             ResetMode::try_from(self.0.resetMode).expect("Cannot convert reset mode")
         }
 
-        pub(crate) fn shared(&self) -> bool {
+        pub fn shared(&self) -> bool {
             self.0.shared != 0
         }
 
+        /// Any value other than 0 enables the debug support in low-power modes
+        pub fn low_power_debug(&self) -> LowPowerDebug {
+            if self.0.DBG_Sleep != 0 {
+                LowPowerDebug::Enabled
+            } else {
+                LowPowerDebug::Disabled
+            }
+        }
+
+        /// Unknown values are reported as [`SpeedMode::Reliable`]
+        pub fn speed_mode(&self) -> SpeedMode {
+            SpeedMode::try_from(self.0.speed).unwrap_or_default()
+        }
+
         pub(crate) fn set_debug_protocol(&mut self, protocol: Protocol) {
             self.0.dbgPort = protocol.into();
         }
@@ -274,7 +700,7 @@ pub mod probe {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             write!(
             f,
-            "STLink (Serial: {}), Board: {}, Firmware version: {}, Debug port: {}, Connection mode: {}, Reset mode: {}, Frequency: {} Hz, Shared: {}",
+            "STLink (Serial: {}), Board: {}, Firmware version: {}, Debug port: {}, Connection mode: {}, Reset mode: {}, Frequency: {} kHz, Shared: {}, Low-power debug: {}, Speed mode: {}",
             self.serial_number(),
             self.board(),
             self.firmware_version(),
@@ -282,7 +708,9 @@ pub mod probe {
             self.connection_mode(),
             self.reset_mode(),
             self.0.frequency,
-            self.shared()
+            self.shared(),
+            self.low_power_debug(),
+            self.speed_mode()
         )
         }
     }
@@ -457,14 +885,320 @@ pub mod fus {
         pub fus_version: Version,
         pub uid64: u64,
         pub device_id: u16,
+        pub anti_rollback: AntiRollbackState,
     }
 
     impl std::fmt::Display for Information {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             write!(
                 f,
-                "Wireless stack version: {}, FUS version: {}, UUID64: {:X}, Device ID: {:X}",
-                self.wireless_stack_version, self.fus_version, self.uid64, self.device_id
+                "Wireless stack version: {}, FUS version: {}, UUID64: {:X}, Device ID: {:X}, Anti-rollback: {}",
+                self.wireless_stack_version,
+                self.fus_version,
+                self.uid64,
+                self.device_id,
+                self.anti_rollback
+            )
+        }
+    }
+
+    /// Anti-rollback state of the wireless stack
+    ///
+    /// The FUS info table does not document a field with the anti-rollback state (see AN5185). Therefore, the state is
+    /// only known after a successful activation via [`crate::ConnectedFusProgrammer::activate_anti_rollback`]
+    #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, strum::Display)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+    pub enum AntiRollbackState {
+        /// The state cannot be read from the target
+        #[default]
+        Unknown,
+        /// Only the installed or newer wireless stack versions can be installed
+        Active,
+    }
+}
+
+pub mod uid {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, From, Into)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    /// 64 bit unique device identifier (UID64)
+    pub struct Uid64(pub u64);
+
+    impl std::fmt::Display for Uid64 {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:016X}", self.0)
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, From, Into)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    /// 96 bit unique device identifier which is stored in the system memory of the target
+    /// The words are stored in the order they are read from the target: index 0 contains bits 0..31
+    pub struct Uid96(pub [u32; 3]);
+
+    impl std::fmt::Display for Uid96 {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:08X}{:08X}{:08X}", self.0[2], self.0[1], self.0[0])
+        }
+    }
+
+    /// Get the base address of the 96 bit unique device identifier for a given device id (see reference manual of the device family)
+    /// Returns `None` if the device family is unknown
+    pub(crate) fn uid96_base_address(device_id: u32) -> Option<u32> {
+        match device_id {
+            // STM32F0, STM32F3
+            0x440 | 0x442 | 0x444 | 0x445 | 0x448 => Some(0x1FFF_F7AC),
+            0x422 | 0x432 | 0x438 | 0x439 | 0x446 => Some(0x1FFF_F7AC),
+            // STM32F1
+            0x410 | 0x412 | 0x414 | 0x418 | 0x420 | 0x428 | 0x430 => Some(0x1FFF_F7E8),
+            // STM32F2, STM32F4
+            0x411 | 0x413 | 0x419 | 0x421 | 0x423 | 0x431 | 0x433 | 0x434 | 0x441 | 0x458
+            | 0x463 => Some(0x1FFF_7A10),
+            // STM32F72x/F73x
+            0x452 => Some(0x1FF0_7A10),
+            // STM32F74x/F75x, STM32F76x/F77x
+            0x449 | 0x451 => Some(0x1FF0_F420),
+            // STM32H74x/H75x, STM32H72x/H73x
+            0x450 | 0x483 => Some(0x1FF1_E800),
+            // STM32H7Ax/H7Bx
+            0x480 => Some(0x08FF_F800),
+            // STM32L0, STM32L1 (cat. 1 and 2)
+            0x416 | 0x417 | 0x425 | 0x429 | 0x447 | 0x457 => Some(0x1FF8_0050),
+            // STM32L1 (cat. 3 to 6)
+            0x427 | 0x436 | 0x437 => Some(0x1FF8_00D0),
+            // STM32C0
+            0x443 | 0x453 => Some(0x1FFF_7550),
+            // STM32G0, STM32G4, STM32L4, STM32WB, STM32WL
+            0x456 | 0x460 | 0x466 | 0x467 => Some(0x1FFF_7590),
+            0x468 | 0x469 | 0x479 => Some(0x1FFF_7590),
+            0x415 | 0x435 | 0x461 | 0x462 | 0x464 | 0x470 | 0x471 => Some(0x1FFF_7590),
+            0x494 | 0x495 | 0x497 => Some(0x1FFF_7590),
+            // STM32L5
+            0x472 => Some(0x0BFA_0590),
+            // STM32U5
+            0x476 | 0x481 | 0x482 => Some(0x0BFA_0700),
+            _ => None,
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn uid96_base_address_per_family() {
+            let table = [
+                // (device id, base address)
+                (0x440, Some(0x1FFF_F7AC)), // STM32F05x
+                (0x446, Some(0x1FFF_F7AC)), // STM32F30x
+                (0x410, Some(0x1FFF_F7E8)), // STM32F10x medium density
+                (0x413, Some(0x1FFF_7A10)), // STM32F40x
+                (0x463, Some(0x1FFF_7A10)), // STM32F413
+                (0x452, Some(0x1FF0_7A10)), // STM32F72x
+                (0x449, Some(0x1FF0_F420)), // STM32F74x
+                (0x451, Some(0x1FF0_F420)), // STM32F76x
+                (0x450, Some(0x1FF1_E800)), // STM32H74x
+                (0x483, Some(0x1FF1_E800)), // STM32H72x
+                (0x480, Some(0x08FF_F800)), // STM32H7Ax
+                (0x417, Some(0x1FF8_0050)), // STM32L05x
+                (0x416, Some(0x1FF8_0050)), // STM32L1 cat. 1
+                (0x436, Some(0x1FF8_00D0)), // STM32L1 cat. 4
+                (0x443, Some(0x1FFF_7550)), // STM32C01x
+                (0x460, Some(0x1FFF_7590)), // STM32G07x
+                (0x469, Some(0x1FFF_7590)), // STM32G47x
+                (0x415, Some(0x1FFF_7590)), // STM32L47x
+                (0x495, Some(0x1FFF_7590)), // STM32WB55
+                (0x497, Some(0x1FFF_7590)), // STM32WLE5
+                (0x472, Some(0x0BFA_0590)), // STM32L5
+                (0x482, Some(0x0BFA_0700)), // STM32U575/585
+                (0x500, None),              // STM32MP1
+                (0x000, None),
+            ];
+
+            for (device_id, base_address) in table {
+                assert_eq!(
+                    uid96_base_address(device_id),
+                    base_address,
+                    "Device id {:#05x}",
+                    device_id
+                );
+            }
+        }
+
+        #[test]
+        fn display() {
+            let table = [
+                (
+                    [0x0000_0000, 0x0000_0000, 0x0000_0000],
+                    "000000000000000000000000",
+                ),
+                (
+                    [0x0000_0001, 0x0000_0002, 0x0000_0003],
+                    "000000030000000200000001",
+                ),
+                (
+                    [0x0039_0030, 0x3032_5113, 0x2036_3357],
+                    "203633573032511300390030",
+                ),
+                (
+                    [0xFFFF_FFFF, 0x0000_0000, 0xABCD_EF01],
+                    "ABCDEF0100000000FFFFFFFF",
+                ),
+            ];
+
+            for (words, expected) in table {
+                assert_eq!(Uid96(words).to_string(), expected);
+            }
+
+            assert_eq!(Uid64(0x0080_E115_0000_1234).to_string(), "0080E11500001234");
+        }
+
+        #[cfg(feature = "serde")]
+        #[test]
+        fn serde() {
+            let table = [
+                ([0x0000_0000, 0x0000_0000, 0x0000_0000], "[0,0,0]"),
+                (
+                    [0x0039_0030, 0x3032_5113, 0x2036_3357],
+                    "[3735600,808603923,540422999]",
+                ),
+                ([u32::MAX, 1, 2], "[4294967295,1,2]"),
+            ];
+
+            for (words, json) in table {
+                assert_eq!(serde_json::to_string(&Uid96(words)).unwrap(), json);
+                assert_eq!(serde_json::from_str::<Uid96>(json).unwrap(), Uid96(words));
+            }
+
+            assert_eq!(serde_json::to_string(&Uid64(42)).unwrap(), "42");
+            assert_eq!(serde_json::from_str::<Uid64>("42").unwrap(), Uid64(42));
+        }
+    }
+}
+
+pub mod ssp {
+    #[cfg(feature = "serde")]
+    use serde::{Deserialize, Serialize};
+
+    /// Source of the license for the Secure Secret Provisioning (SSP)
+    #[derive(Debug, Clone, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+    pub enum License {
+        /// License file which was previously exported from a HSM
+        File(std::path::PathBuf),
+        /// License is generated by the HSM plugged into the given slot
+        Hsm { slot_id: u32 },
+    }
+
+    /// Parameters for the Secure Secret Provisioning (SSP) of a stm32mp1 target
+    #[derive(Debug, Clone, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+    pub struct Parameters {
+        /// Encrypted secrets file (generated with STM32TrustedPackageCreator)
+        pub ssp_file: std::path::PathBuf,
+        /// RSSe binary which runs the provisioning on the target (tfa-ssp file)
+        pub rsse_file: std::path::PathBuf,
+        /// Optional RSSe extension binary. The CubeProgrammer API accepts a single binary, so the extension is appended to the RSSe binary
+        #[cfg_attr(feature = "serde", serde(default))]
+        pub rsse_extension_file: Option<std::path::PathBuf>,
+        /// License source
+        pub license: License,
+    }
+
+    /// Result of a successful Secure Secret Provisioning
+    #[derive(Debug, Clone, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+    pub struct Outcome {
+        pub parameters: Parameters,
+        pub duration: std::time::Duration,
+    }
+
+    impl std::fmt::Display for Outcome {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(
+                f,
+                "SSP file: `{}`, RSSe file: `{}`{}, License: {}, Duration: {:?}",
+                self.parameters.ssp_file.display(),
+                self.parameters.rsse_file.display(),
+                match &self.parameters.rsse_extension_file {
+                    Some(path) => format!(", RSSe extension file: `{}`", path.display()),
+                    None => String::new(),
+                },
+                match &self.parameters.license {
+                    License::File(path) => format!("file `{}`", path.display()),
+                    License::Hsm { slot_id } => format!("HSM slot {}", slot_id),
+                },
+                self.duration
+            )
+        }
+    }
+}
+
+pub mod hsm {
+    #[cfg(feature = "serde")]
+    use serde::{Deserialize, Serialize};
+
+    /// State of a hardware secure module (HSM)
+    #[derive(Debug, Clone, PartialEq, strum::Display, strum::EnumString)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+    pub enum HsmState {
+        #[strum(serialize = "ST_STATE")]
+        St,
+        #[strum(serialize = "OEM_STATE")]
+        Oem,
+        #[strum(serialize = "OPERATIONAL_STATE")]
+        Operational,
+        #[strum(serialize = "UNKNOWN_STATE")]
+        Unknown,
+
+        /// State which is not known by this crate
+        #[strum(default)]
+        Other(String),
+    }
+
+    /// Type of a hardware secure module (HSM)
+    #[derive(Debug, Clone, PartialEq, strum::Display, strum::EnumString)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+    pub enum HsmType {
+        #[strum(serialize = "SFI")]
+        Sfi,
+        #[strum(serialize = "SMU")]
+        Smu,
+        #[strum(serialize = "SSP")]
+        Ssp,
+
+        /// Type which is not known by this crate
+        #[strum(default)]
+        Other(String),
+    }
+
+    /// Status of a hardware secure module (HSM)
+    #[derive(Debug, Clone, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    #[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+    pub struct HsmStatus {
+        pub slot_id: u32,
+        pub firmware_id: String,
+        /// Remaining number of licenses which can be generated by the HSM
+        pub counter: u64,
+        pub state: HsmState,
+        pub version: u32,
+        pub r#type: HsmType,
+    }
+
+    impl std::fmt::Display for HsmStatus {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(
+                f,
+                "HSM (Slot: {}), Firmware ID: {}, Counter: {}, State: {}, Version: {}, Type: {}",
+                self.slot_id, self.firmware_id, self.counter, self.state, self.version, self.r#type
             )
         }
     }
@@ -0,0 +1,479 @@
+//! Async API for the CubeProgrammer (feature `async`)
+//!
+//! The CubeProgrammer API library is blocking. The async types run all calls on a dedicated worker thread and return futures which resolve with the results.
+//! The futures do not depend on a specific runtime and can be used with e.g. tokio.
+//!
+//! An operation is queued on the worker thread when the method is called, the operations are executed in the order in which they are queued.
+//! Progress and log messages of the API are delivered as a [`Stream`] of [`ProgrammerEvent`]s (see [`AsyncCubeProgrammer::events`]).
+
+use crate::{
+    api_log,
+    backend::Backend,
+    display::{EventHandler, ProgrammerEvent},
+    error::{CubeProgrammerError, CubeProgrammerResult},
+    ConnectedFusProgrammer, ConnectedProgrammer, CubeProgrammer,
+};
+use bon::bon;
+use futures_channel::oneshot;
+use futures_core::Stream;
+use log::warn;
+use std::{
+    future::Future,
+    panic::AssertUnwindSafe,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+/// Async handle to the [`CubeProgrammer`]. Factory for async connections
+///
+/// The handle can be cloned. All clones share the same worker thread, which is stopped after the last handle and connection is dropped.
+#[derive(Debug, Clone)]
+pub struct AsyncCubeProgrammer {
+    programmer: CubeProgrammer,
+    worker: Worker,
+}
+
+/// Async handle to a [`ConnectedProgrammer`] which is created via calling [`AsyncCubeProgrammer::connect_to_target`]
+///
+/// Dropping the handle disconnects on the worker thread
+#[derive(Debug)]
+pub struct AsyncConnectedProgrammer {
+    programmer: Option<Arc<ConnectedProgrammer>>,
+    worker: Worker,
+}
+
+/// Async handle to a [`ConnectedFusProgrammer`] which is created via calling [`AsyncCubeProgrammer::connect_to_target_fus`]
+///
+/// Dropping the handle disconnects on the worker thread
+#[derive(Debug)]
+pub struct AsyncConnectedFusProgrammer {
+    programmer: Option<Arc<Mutex<ConnectedFusProgrammer>>>,
+    /// Token of the connection. Kept separately, because the connection is locked while an operation is running
+    cancellation_token: crate::CancellationToken,
+    worker: Worker,
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Thread which executes the blocking API calls
+#[derive(Debug, Clone)]
+struct Worker {
+    jobs: std::sync::mpsc::Sender<Job>,
+}
+
+impl Worker {
+    /// Spawn the worker thread. The thread stops after all senders are dropped and all queued jobs are executed
+    fn spawn() -> CubeProgrammerResult<Self> {
+        let (jobs, receiver) = std::sync::mpsc::channel::<Job>();
+
+        std::thread::Builder::new()
+            .name("cube-programmer".to_string())
+            .spawn(move || {
+                for job in receiver {
+                    job();
+                }
+            })
+            .map_err(CubeProgrammerError::WorkerSpawn)?;
+
+        Ok(Self { jobs })
+    }
+
+    /// Queue a job which is executed on the worker thread
+    /// A panic of the job is caught on the worker thread and resumed when the future is polled. If the worker thread is gone, [`CubeProgrammerError::WorkerStopped`] is returned
+    fn run<T: Send + 'static>(
+        &self,
+        job: impl FnOnce() -> CubeProgrammerResult<T> + Send + 'static,
+    ) -> impl Future<Output = CubeProgrammerResult<T>> + Send + 'static {
+        let (sender, receiver) = oneshot::channel();
+
+        // If the worker thread is gone, the job and therefore the sender is dropped
+        let _ = self.jobs.send(Box::new(move || {
+            let _ = sender.send(std::panic::catch_unwind(AssertUnwindSafe(job)));
+        }));
+
+        async move {
+            match receiver
+                .await
+                .map_err(|_| CubeProgrammerError::WorkerStopped)?
+            {
+                Ok(result) => result,
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        }
+    }
+
+    /// Queue a job without waiting for the result
+    fn execute(&self, job: impl FnOnce() + Send + 'static) {
+        let _ = self.jobs.send(Box::new(move || {
+            if std::panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                warn!("Job on the worker thread panicked");
+            }
+        }));
+    }
+}
+
+#[bon]
+impl AsyncCubeProgrammer {
+    /// Create new instance (see [`CubeProgrammer::new`]) and spawn the worker thread
+    /// The event handler receives the events of the API in addition to the event streams
+    #[builder]
+    pub fn new(
+        cube_programmer_dir: &impl AsRef<std::path::Path>,
+        log_verbosity: Option<api_log::Verbosity>,
+        event_handler: Option<Arc<dyn EventHandler>>,
+        version_policy: Option<crate::api_types::VersionPolicy>,
+    ) -> CubeProgrammerResult<Self> {
+        Ok(Self {
+            programmer: CubeProgrammer::builder()
+                .cube_programmer_dir(cube_programmer_dir)
+                .maybe_log_verbosity(log_verbosity)
+                .maybe_event_handler(event_handler)
+                .maybe_version_policy(version_policy)
+                .build()?,
+            worker: Worker::spawn()?,
+        })
+    }
+
+    /// Create new instance which uses a custom [`Backend`] (see [`CubeProgrammer::from_backend`]) and spawn the worker thread
+    #[builder(start_fn = with_backend, finish_fn = build)]
+    pub fn from_backend(
+        backend: Box<dyn Backend>,
+        log_verbosity: Option<api_log::Verbosity>,
+        event_handler: Option<Arc<dyn EventHandler>>,
+        version_policy: Option<crate::api_types::VersionPolicy>,
+    ) -> CubeProgrammerResult<Self> {
+        Ok(Self {
+            programmer: CubeProgrammer::with_backend()
+                .backend(backend)
+                .maybe_log_verbosity(log_verbosity)
+                .maybe_event_handler(event_handler)
+                .maybe_version_policy(version_policy)
+                .build()?,
+            worker: Worker::spawn()?,
+        })
+    }
+
+    /// Blocking handle to the CubeProgrammer, which shares the API with the async handle
+    pub fn blocking(&self) -> &CubeProgrammer {
+        &self.programmer
+    }
+
+    /// Version of the loaded STM32CubeProgrammer API (see [`CubeProgrammer::api_version`])
+    pub fn api_version(&self) -> Option<crate::api_types::CubeProgrammerVersion> {
+        self.programmer.api_version()
+    }
+
+    /// Functions of the API library which are available (see [`CubeProgrammer::capabilities`])
+    pub fn capabilities(&self) -> &crate::capabilities::Capabilities {
+        self.programmer.capabilities()
+    }
+
+    /// Set the event handler (see [`CubeProgrammer::set_event_handler`])
+    /// The handler is called on the worker thread
+    pub fn set_event_handler(&self, event_handler: Arc<dyn EventHandler>) {
+        self.programmer.set_event_handler(event_handler)
+    }
+
+    /// Remove the event handler and return it. The event streams are not affected
+    pub fn remove_event_handler(&self) -> Option<Arc<dyn EventHandler>> {
+        self.programmer.remove_event_handler()
+    }
+
+    /// Stream of the events of the operations of this programmer and its connections
+    /// The stream receives all events from the time of the subscription on
+    pub fn events(&self) -> impl Stream<Item = ProgrammerEvent> + Send + Unpin + 'static {
+        self.programmer.subscribe_stream()
+    }
+
+    /// List available probes (see [`CubeProgrammer::list_available_probes`])
+    pub fn list_available_probes(
+        &self,
+    ) -> impl Future<Output = CubeProgrammerResult<Vec<crate::probe::Serial>>> + Send + 'static
+    {
+        let programmer = self.programmer.clone();
+        self.worker.run(move || programmer.list_available_probes())
+    }
+
+    /// Connect to a target via a given probe (see [`CubeProgrammer::connect_to_target`])
+    pub fn connect_to_target(
+        &self,
+        probe_serial_number: &crate::probe::Serial,
+        protocol: &crate::probe::Protocol,
+        connection_parameters: &crate::probe::ConnectionParameters,
+    ) -> impl Future<Output = CubeProgrammerResult<AsyncConnectedProgrammer>> + Send + 'static {
+        let programmer = self.programmer.clone();
+        let probe_serial_number = probe_serial_number.clone();
+        let protocol = *protocol;
+        let connection_parameters = connection_parameters.clone();
+        let worker = self.worker.clone();
+
+        let connected = self.worker.run(move || {
+            programmer.connect_to_target(&probe_serial_number, &protocol, &connection_parameters)
+        });
+
+        async move {
+            Ok(AsyncConnectedProgrammer {
+                programmer: Some(Arc::new(connected.await?)),
+                worker,
+            })
+        }
+    }
+
+    /// Connect to the FUS of a target via a given probe (see [`CubeProgrammer::connect_to_target_fus`])
+    pub fn connect_to_target_fus(
+        &self,
+        probe_serial_number: &crate::probe::Serial,
+        protocol: &crate::probe::Protocol,
+    ) -> impl Future<Output = CubeProgrammerResult<AsyncConnectedFusProgrammer>> + Send + 'static
+    {
+        let programmer = self.programmer.clone();
+        let probe_serial_number = probe_serial_number.clone();
+        let protocol = *protocol;
+        let worker = self.worker.clone();
+
+        let connected = self
+            .worker
+            .run(move || programmer.connect_to_target_fus(&probe_serial_number, &protocol));
+
+        async move {
+            let connected = connected.await?;
+
+            Ok(AsyncConnectedFusProgrammer {
+                cancellation_token: connected.cancellation_token(),
+                programmer: Some(Arc::new(Mutex::new(connected))),
+                worker,
+            })
+        }
+    }
+}
+
+impl Drop for AsyncConnectedProgrammer {
+    fn drop(&mut self) {
+        if let Some(programmer) = self.programmer.take() {
+            self.worker.execute(move || drop(programmer));
+        }
+    }
+}
+
+impl AsyncConnectedProgrammer {
+    /// Queue an operation on the connected programmer
+    fn run<T: Send + 'static>(
+        &self,
+        operation: impl FnOnce(&ConnectedProgrammer) -> CubeProgrammerResult<T> + Send + 'static,
+    ) -> impl Future<Output = CubeProgrammerResult<T>> + Send + 'static {
+        let programmer = self.connected().clone();
+        self.worker.run(move || operation(&programmer))
+    }
+
+    fn connected(&self) -> &Arc<ConnectedProgrammer> {
+        // Only taken in drop and disconnect
+        self.programmer.as_ref().unwrap()
+    }
+
+    /// Disconnect from the target. The future resolves after the disconnect
+    pub fn disconnect(mut self) -> impl Future<Output = ()> + Send + 'static {
+        let programmer = self.programmer.take();
+        let disconnected = self.worker.run(move || {
+            drop(programmer);
+            Ok(())
+        });
+
+        // A stopped worker thread already dropped the programmer with the queued job
+        async move {
+            let _ = disconnected.await;
+        }
+    }
+
+    /// Token which cancels the long-running operations of this connection (see [`ConnectedProgrammer::cancellation_token`])
+    pub fn cancellation_token(&self) -> crate::CancellationToken {
+        self.connected().cancellation_token()
+    }
+
+    /// General information about the connected target
+    pub fn general_information(&self) -> &crate::GeneralInformation {
+        self.connected().general_information()
+    }
+
+    /// Read the target voltage in millivolts (see [`ConnectedProgrammer::target_voltage`])
+    pub fn target_voltage(
+        &self,
+    ) -> impl Future<Output = CubeProgrammerResult<Option<u32>>> + Send + 'static {
+        self.run(|x| x.target_voltage())
+    }
+
+    /// Reset target (see [`ConnectedProgrammer::reset_target`])
+    pub fn reset_target(
+        &self,
+        reset_mode: crate::probe::ResetMode,
+    ) -> impl Future<Output = CubeProgrammerResult<()>> + Send + 'static {
+        self.run(move |x| x.reset_target(reset_mode))
+    }
+
+    /// Download hex file to target (see [`ConnectedProgrammer::download_hex_file`])
+    pub fn download_hex_file(
+        &self,
+        file_path: impl AsRef<std::path::Path>,
+        skip_erase: bool,
+        verify: bool,
+    ) -> impl Future<Output = CubeProgrammerResult<()>> + Send + 'static {
+        let file_path = file_path.as_ref().to_path_buf();
+        self.run(move |x| x.download_hex_file(file_path, skip_erase, verify))
+    }
+
+    /// Download binary file to target (see [`ConnectedProgrammer::download_bin_file`])
+    pub fn download_bin_file(
+        &self,
+        file_path: impl AsRef<std::path::Path>,
+        start_address: u32,
+        skip_erase: bool,
+        verify: bool,
+    ) -> impl Future<Output = CubeProgrammerResult<()>> + Send + 'static {
+        let file_path = file_path.as_ref().to_path_buf();
+        self.run(move |x| x.download_bin_file(file_path, start_address, skip_erase, verify))
+    }
+
+    /// Perform mass erase (see [`ConnectedProgrammer::mass_erase`])
+    pub fn mass_erase(&self) -> impl Future<Output = CubeProgrammerResult<()>> + Send + 'static {
+        self.run(|x| x.mass_erase())
+    }
+
+    /// Save memory to file (see [`ConnectedProgrammer::save_memory`])
+    /// Attention: The file path must end with .hex or .bin
+    pub fn save_memory(
+        &self,
+        file_path: impl AsRef<std::path::Path>,
+        start_address: u32,
+        size_bytes: u32,
+    ) -> impl Future<Output = CubeProgrammerResult<()>> + Send + 'static {
+        let file_path = file_path.as_ref().to_path_buf();
+        self.run(move |x| x.save_memory(file_path, start_address, size_bytes))
+    }
+
+    /// Read memory as struct (see [`ConnectedProgrammer::read_memory`])
+    pub fn read_memory<T: bytemuck::Pod + bytemuck::Zeroable + Send>(
+        &self,
+        address: u32,
+        count: usize,
+    ) -> impl Future<Output = CubeProgrammerResult<Vec<T>>> + Send + 'static {
+        self.run(move |x| x.read_memory::<T>(address, count))
+    }
+
+    /// Write memory as struct (see [`ConnectedProgrammer::write_memory`])
+    /// The data is copied, so the future does not borrow the slice
+    pub fn write_memory<T: bytemuck::Pod + std::fmt::Debug + Send>(
+        &self,
+        address: u32,
+        data: &[T],
+    ) -> impl Future<Output = CubeProgrammerResult<()>> + Send + 'static {
+        let data = data.to_vec();
+        self.run(move |x| x.write_memory(address, &data))
+    }
+
+    /// Write [`crate::api_types::CoreRegister`] (see [`ConnectedProgrammer::write_core_register`])
+    pub fn write_core_register(
+        &self,
+        register: crate::api_types::CoreRegister,
+        value: u32,
+    ) -> impl Future<Output = CubeProgrammerResult<()>> + Send + 'static {
+        self.run(move |x| x.write_core_register(register, value))
+    }
+
+    /// Read [`crate::api_types::CoreRegister`] (see [`ConnectedProgrammer::read_core_register`])
+    pub fn read_core_register(
+        &self,
+        register: crate::api_types::CoreRegister,
+    ) -> impl Future<Output = CubeProgrammerResult<u32>> + Send + 'static {
+        self.run(move |x| x.read_core_register(register))
+    }
+}
+
+impl Drop for AsyncConnectedFusProgrammer {
+    fn drop(&mut self) {
+        if let Some(programmer) = self.programmer.take() {
+            self.worker.execute(move || drop(programmer));
+        }
+    }
+}
+
+impl AsyncConnectedFusProgrammer {
+    /// Queue an operation on the connected FUS programmer
+    fn run<T: Send + 'static>(
+        &self,
+        operation: impl FnOnce(&mut ConnectedFusProgrammer) -> CubeProgrammerResult<T> + Send + 'static,
+    ) -> impl Future<Output = CubeProgrammerResult<T>> + Send + 'static {
+        // Only taken in drop and disconnect
+        let programmer = self.programmer.as_ref().unwrap().clone();
+
+        self.worker
+            .run(move || operation(&mut programmer.lock().unwrap_or_else(PoisonError::into_inner)))
+    }
+
+    /// Disconnect from the target. The future resolves after the disconnect
+    pub fn disconnect(mut self) -> impl Future<Output = ()> + Send + 'static {
+        let programmer = self.programmer.take();
+        let disconnected = self.worker.run(move || {
+            drop(programmer);
+            Ok(())
+        });
+
+        // A stopped worker thread already dropped the programmer with the queued job
+        async move {
+            let _ = disconnected.await;
+        }
+    }
+
+    /// Token which cancels the long-running operations of this connection (see [`ConnectedFusProgrammer::cancellation_token`])
+    pub fn cancellation_token(&self) -> crate::CancellationToken {
+        self.cancellation_token.clone()
+    }
+
+    /// FUS information (see [`ConnectedFusProgrammer::fus_info`])
+    pub fn fus_info(
+        &self,
+    ) -> impl Future<Output = CubeProgrammerResult<crate::fus::Information>> + Send + 'static {
+        self.run(|x| Ok(*x.fus_info()))
+    }
+
+    /// Delete the wireless stack (see [`ConnectedFusProgrammer::delete_wireless_stack`])
+    pub fn delete_wireless_stack(
+        &self,
+    ) -> impl Future<Output = CubeProgrammerResult<()>> + Send + 'static {
+        self.run(|x| x.delete_wireless_stack())
+    }
+
+    /// Upgrade the wireless stack (see [`ConnectedFusProgrammer::upgrade_wireless_stack`])
+    pub fn upgrade_wireless_stack(
+        &self,
+        file_path: impl AsRef<std::path::Path>,
+        start_address: u32,
+        first_install: bool,
+        verify: bool,
+        start_stack_after_update: bool,
+    ) -> impl Future<Output = CubeProgrammerResult<()>> + Send + 'static {
+        let file_path = file_path.as_ref().to_path_buf();
+        self.run(move |x| {
+            x.upgrade_wireless_stack(
+                file_path,
+                start_address,
+                first_install,
+                verify,
+                start_stack_after_update,
+            )
+        })
+    }
+
+    /// Start the wireless stack (see [`ConnectedFusProgrammer::start_wireless_stack`])
+    pub fn start_wireless_stack(
+        &self,
+    ) -> impl Future<Output = CubeProgrammerResult<()>> + Send + 'static {
+        self.run(|x| x.start_wireless_stack())
+    }
+
+    /// Activate the anti-rollback of the wireless stack (see [`ConnectedFusProgrammer::activate_anti_rollback`])
+    /// Attention: This operation is irreversible and therefore needs to be confirmed via [`crate::Confirmation::Confirmed`]
+    pub fn activate_anti_rollback(
+        &self,
+        confirmation: crate::Confirmation,
+    ) -> impl Future<Output = CubeProgrammerResult<crate::fus::Information>> + Send + 'static {
+        self.run(move |x| x.activate_anti_rollback(confirmation).copied())
+    }
+}
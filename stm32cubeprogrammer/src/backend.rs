@@ -0,0 +1,885 @@
+//! Backends which execute the calls of [`crate::CubeProgrammer`] and its connections
+//!
+//! - [`FfiBackend`] forwards all calls to the CubeProgrammer API library
+//! - [`SimulatedBackend`] simulates probes and a connected target in-process. This allows testing without the library and without hardware
+//! - `RecordingBackend` and `ReplayBackend` record the calls of another backend to a trace and replay it (feature `recording`)
+
+use crate::{
+    api_log, api_types,
+    error::{Action, CubeProgrammerError, CubeProgrammerResult},
+    utility,
+};
+use log::debug;
+use std::{
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+};
+use stm32cubeprogrammer_sys::libloading;
+
+#[cfg(feature = "recording")]
+pub mod recording;
+pub mod simulated;
+
+#[cfg(feature = "recording")]
+pub use recording::{RecordingBackend, ReplayBackend};
+pub use simulated::SimulatedBackend;
+
+/// Functions of the API struct which are resolved in the loaded library
+macro_rules! resolved_symbols {
+    ($api:expr, $($field:ident),*) => {{
+        let mut symbols = Vec::new();
+        $(
+            match &$api.$field {
+                Ok(_) => symbols.push(stringify!($field)),
+                Err(err) => debug!("Missing symbol '{}': {}", stringify!($field), err),
+            }
+        )*
+        symbols
+    }};
+}
+
+/// Return [`CubeProgrammerError::ActionNotSupported`] if one of the functions is missing in the loaded library
+macro_rules! require_symbols {
+    ($backend:expr, $action:expr, $($field:ident),*) => {
+        $(
+            if $backend.api.$field.is_err() {
+                return Err(CubeProgrammerError::ActionNotSupported {
+                    action: $action,
+                    message: format!("Missing symbol '{}' in the API library", stringify!($field)),
+                });
+            }
+        )*
+    };
+}
+
+/// Option bit of the target (e.g. `RDP` or `TZEN`) with its current value
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OptionBit {
+    pub name: String,
+    pub value: u32,
+}
+
+/// Interface to the CubeProgrammer API
+///
+/// The functions mirror the functions of the CubeProgrammer API library. Return codes are already checked and mapped to a [`CubeProgrammerError`].
+/// Like the library, a backend keeps track of the current target connection internally: All target related functions operate on the target of the last successful [`Backend::connect`]
+pub trait Backend: Send + Sync {
+    /// Version of STM32CubeProgrammer which executes the calls. `None` if the version is unknown
+    fn api_version(&self) -> Option<api_types::CubeProgrammerVersion> {
+        None
+    }
+
+    /// Functions which are available in the API library. Defaults to all functions
+    fn capabilities(&self) -> crate::capabilities::Capabilities {
+        crate::capabilities::Capabilities::all()
+    }
+
+    /// Check if the ST-LINK server is running, which is needed for shared connections. Defaults to `false`
+    fn stlink_server_running(&self) -> bool {
+        false
+    }
+
+    /// Set the verbosity level of the log messages
+    fn set_verbosity_level(&self, verbosity: api_log::Verbosity);
+
+    /// Set the callbacks which receive log messages and progress updates
+    fn set_display_callbacks(&self, callbacks: Arc<dyn api_log::DisplayCallbacks>);
+
+    /// Set the flag which cancels the running operation (e.g. a download or a mass erase)
+    /// Is called from other threads while an operation is running and therefore must not wait for the running operation
+    fn set_cancel_flag(&self, cancel: bool);
+
+    /// List the connected probes with their default connection parameters
+    fn list_probes(&self) -> CubeProgrammerResult<Vec<api_types::probe::Probe>>;
+
+    /// Connect to a target via the probe and with the connection parameters of the given probe
+    fn connect(&self, probe: &api_types::probe::Probe) -> CubeProgrammerResult<()>;
+
+    /// Disconnect from the current target
+    fn disconnect(&self);
+
+    /// Check if the connection to the current target is still maintained
+    fn check_connection(&self) -> CubeProgrammerResult<()>;
+
+    /// Read the general information of the current target
+    fn general_information(&self) -> CubeProgrammerResult<api_types::GeneralInformation>;
+
+    fn reset(&self, reset_mode: api_types::probe::ResetMode) -> CubeProgrammerResult<()>;
+
+    /// Download a hex or bin file. The address is only used for bin files
+    fn download_file(
+        &self,
+        file_path: &Path,
+        address: u32,
+        skip_erase: bool,
+        verify: bool,
+    ) -> CubeProgrammerResult<()>;
+
+    fn mass_erase(&self) -> CubeProgrammerResult<()>;
+
+    fn save_memory_to_file(
+        &self,
+        file_path: &Path,
+        address: u32,
+        size: u32,
+    ) -> CubeProgrammerResult<()>;
+
+    /// Send an option bytes command (e.g. `-ob rdp=0xbb`)
+    fn send_option_bytes_command(&self, command: &str) -> CubeProgrammerResult<()>;
+
+    /// Read all option bits of the current target
+    fn option_bits(&self) -> CubeProgrammerResult<Vec<OptionBit>>;
+
+    fn read_unprotect(&self) -> CubeProgrammerResult<()>;
+
+    fn tzen_regression(&self) -> CubeProgrammerResult<()>;
+
+    fn read_memory(&self, address: u32, size: u32) -> CubeProgrammerResult<Vec<u8>>;
+
+    fn write_memory(&self, address: u32, data: &[u8]) -> CubeProgrammerResult<()>;
+
+    fn read_core_register(&self, register: api_types::CoreRegister) -> CubeProgrammerResult<u32>;
+
+    fn write_core_register(
+        &self,
+        register: api_types::CoreRegister,
+        value: u32,
+    ) -> CubeProgrammerResult<()>;
+
+    fn uid64(&self) -> CubeProgrammerResult<u64>;
+
+    fn start_fus(&self) -> CubeProgrammerResult<()>;
+
+    fn start_wireless_stack(&self) -> CubeProgrammerResult<()>;
+
+    fn firmware_delete(&self) -> CubeProgrammerResult<()>;
+
+    fn firmware_upgrade(
+        &self,
+        file_path: &Path,
+        address: u32,
+        first_install: bool,
+        start_stack: bool,
+        verify: bool,
+    ) -> CubeProgrammerResult<()>;
+
+    fn anti_rollback(&self) -> CubeProgrammerResult<()>;
+
+    /// Run the secure secret provisioning. Without a license file, the license is generated by the HSM in the given slot
+    fn program_ssp(
+        &self,
+        ssp_file: &Path,
+        license_file: Option<&Path>,
+        tfa_file: &Path,
+        hsm_slot_id: i32,
+    ) -> CubeProgrammerResult<()>;
+
+    fn hsm_firmware_id(&self, slot_id: i32) -> CubeProgrammerResult<String>;
+
+    fn hsm_counter(&self, slot_id: i32) -> CubeProgrammerResult<u64>;
+
+    fn hsm_state(&self, slot_id: i32) -> CubeProgrammerResult<String>;
+
+    fn hsm_version(&self, slot_id: i32) -> CubeProgrammerResult<String>;
+
+    fn hsm_type(&self, slot_id: i32) -> CubeProgrammerResult<String>;
+
+    /// Export the license of the HSM in the given slot to a file
+    fn hsm_license(&self, slot_id: i32, file_path: &Path) -> CubeProgrammerResult<()>;
+}
+
+/// Backend which forwards all calls to the CubeProgrammer API library
+pub struct FfiBackend {
+    api: stm32cubeprogrammer_sys::CubeProgrammer_API,
+    version: Option<api_types::CubeProgrammerVersion>,
+    capabilities: crate::capabilities::Capabilities,
+}
+
+impl FfiBackend {
+    /// Load the CubeProgrammer API library
+    /// - Load the library from the root directory of the STM32CubeProgrammer installation
+    /// - Verify that the symbols which are needed to connect to a target are available and determine the [`crate::capabilities::Capabilities`]
+    /// - Set the loader path
+    /// - Detect the version of the installation
+    pub fn load(cube_programmer_dir: impl AsRef<Path>) -> CubeProgrammerResult<Self> {
+        use stm32cubeprogrammer_sys::{PATH_API_LIBRARY_RELATIVE, PATH_LOADER_DIR_RELATIVE};
+
+        let api_path = cube_programmer_dir
+            .as_ref()
+            .join(PATH_API_LIBRARY_RELATIVE)
+            .canonicalize()
+            .map_err(CubeProgrammerError::FileIo)?;
+
+        let loader_path = cube_programmer_dir
+            .as_ref()
+            .join(PATH_LOADER_DIR_RELATIVE)
+            .canonicalize()
+            .map_err(CubeProgrammerError::FileIo)?;
+
+        debug!("API path: {:?}", api_path);
+        debug!("Loader path: {:?}", loader_path);
+
+        let library = Self::load_library(&api_path).map_err(CubeProgrammerError::LibLoading)?;
+
+        let api = unsafe {
+            stm32cubeprogrammer_sys::CubeProgrammer_API::from_library(library)
+                .map_err(CubeProgrammerError::LibLoading)?
+        };
+
+        let capabilities = crate::capabilities::Capabilities::from_symbols(resolved_symbols!(
+            api,
+            setVerbosityLevel,
+            setDisplayCallbacks,
+            setLoadersPath,
+            getStLinkList,
+            deleteInterfaceList,
+            connectStLink,
+            getDeviceGeneralInf,
+            disconnect,
+            startFus,
+            reset,
+            downloadFile,
+            massErase,
+            saveMemoryToFile,
+            sendOptionBytesCmd,
+            readUnprotect,
+            checkDeviceConnection,
+            readMemory,
+            writeMemory,
+            freeLibraryMemory,
+            startWirelessStack,
+            writeCortexRegistres,
+            readCortexReg,
+            firmwareDelete,
+            firmwareUpgrade,
+            antiRollBack,
+            getUID64,
+            initOptionBytesInterface,
+            tzenRegression,
+            programSsp,
+            getHsmFirmwareID,
+            getHsmCounter,
+            getHsmState,
+            getHsmVersion,
+            getHsmType,
+            getHsmLicense,
+            getCancelPointer,
+            setExternalLoaderPath,
+            getExternalLoaders,
+            removeExternalLoader,
+            getUsartList,
+            connectUsartBootloader,
+            getDfuDeviceList,
+            connectDfuBootloader,
+            connectSpiBootloader,
+            connectCanBootloader,
+            connectI2cBootloader
+        ));
+
+        // The functions which are needed to connect to a target must be available before proceeding
+        if let Some(symbol) = crate::capabilities::REQUIRED_SYMBOLS
+            .iter()
+            .find(|x| !capabilities.has_symbol(x))
+        {
+            return Err(CubeProgrammerError::MissingDllSymbol {
+                message: format!("Missing symbol '{}'", symbol),
+            });
+        }
+
+        debug!("Supported features: {:?}", capabilities.features());
+
+        unsafe {
+            api.setLoadersPath(utility::path_to_cstring(loader_path)?.as_ptr());
+        }
+
+        let version = crate::discovery::detect_version(cube_programmer_dir.as_ref());
+        debug!("API version: {:?}", version);
+
+        Ok(Self {
+            api,
+            version,
+            capabilities,
+        })
+    }
+
+    /// Load the dynamic library with libloading
+    fn load_library(
+        api_library_path: impl AsRef<std::ffi::OsStr>,
+    ) -> Result<libloading::Library, libloading::Error> {
+        #[cfg(windows)]
+        unsafe fn load_inner(
+            path: impl AsRef<std::ffi::OsStr>,
+        ) -> Result<libloading::Library, libloading::Error> {
+            let library: libloading::Library = unsafe {
+                libloading::os::windows::Library::load_with_flags(
+                    path,
+                    libloading::os::windows::LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR
+                        | libloading::os::windows::LOAD_LIBRARY_SEARCH_SYSTEM32
+                        | libloading::os::windows::LOAD_LIBRARY_SEARCH_DEFAULT_DIRS,
+                )?
+                .into()
+            };
+
+            Ok(library)
+        }
+
+        #[cfg(unix)]
+        unsafe fn load_inner(
+            path: impl AsRef<std::ffi::OsStr>,
+        ) -> Result<libloading::Library, libloading::Error> {
+            use stm32cubeprogrammer_sys::libloading;
+
+            let library: libloading::Library =
+                unsafe { libloading::os::unix::Library::new(path)?.into() };
+
+            Ok(library)
+        }
+
+        unsafe { load_inner(api_library_path.as_ref()) }
+    }
+
+    /// Check if the cancel flag of the API is set
+    fn cancel_flag(&self) -> bool {
+        if self.api.getCancelPointer.is_err() {
+            return false;
+        }
+
+        let cancel_pointer = unsafe { self.api.getCancelPointer() };
+        !cancel_pointer.is_null() && unsafe { std::ptr::read_volatile(cancel_pointer) } != 0
+    }
+
+    /// Report the failure of an operation as cancelled if the API aborted it because of the cancel flag
+    fn check_cancelled<const SUCCESS: i32>(
+        &self,
+        return_code: api_types::ReturnCode<SUCCESS>,
+        action: Action,
+    ) -> CubeProgrammerResult<()> {
+        match return_code.check(action) {
+            Err(_) if self.cancel_flag() => {
+                debug!("Action {} was cancelled", action);
+                Err(CubeProgrammerError::Cancelled { action })
+            }
+            result => result,
+        }
+    }
+
+    /// Convert a string returned by the HSM functions of the API
+    fn hsm_string_from_ptr(ptr: *const std::ffi::c_char) -> CubeProgrammerResult<String> {
+        if ptr.is_null() {
+            return Err(CubeProgrammerError::ActionOutputUnexpected {
+                action: Action::ReadHsmStatus,
+                unexpected_output: crate::error::UnexpectedOutput::Null,
+                messages: Vec::new(),
+            });
+        }
+
+        unsafe { std::ffi::CStr::from_ptr(ptr) }
+            .to_str()
+            .map(|x| x.to_string())
+            .map_err(|x| CubeProgrammerError::TypeConversion {
+                message: format!("Failed to convert HSM string: {:?}", x),
+                source: crate::error::TypeConversionError::Utf8Error,
+            })
+    }
+}
+
+/// Display callbacks of the API library
+/// The library calls plain function pointers without a context. Like the library itself, the callbacks therefore exist once per process
+static FFI_DISPLAY_CALLBACKS: Mutex<Option<Arc<dyn api_log::DisplayCallbacks>>> = Mutex::new(None);
+
+fn ffi_display_callbacks() -> Option<Arc<dyn api_log::DisplayCallbacks>> {
+    // Call the callbacks without holding the lock
+    FFI_DISPLAY_CALLBACKS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+unsafe extern "C" fn ffi_init_progress_bar() {
+    if let Some(callbacks) = ffi_display_callbacks() {
+        callbacks.init_progress_bar();
+    }
+}
+
+unsafe extern "C" fn ffi_log_message(message_type: i32, message: *const widestring::WideChar) {
+    if message.is_null() {
+        return;
+    }
+
+    let message = unsafe { widestring::WideCStr::from_ptr_str(message) }.to_string_lossy();
+
+    if let Some(callbacks) = ffi_display_callbacks() {
+        callbacks.log_message(api_log::LogMessageType::from(message_type), &message);
+    }
+}
+
+unsafe extern "C" fn ffi_load_bar(current: i32, total: i32) {
+    if let Some(callbacks) = ffi_display_callbacks() {
+        callbacks.load_bar(current, total);
+    }
+}
+
+impl std::fmt::Debug for FfiBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FfiBackend").finish_non_exhaustive()
+    }
+}
+
+impl Backend for FfiBackend {
+    fn api_version(&self) -> Option<api_types::CubeProgrammerVersion> {
+        self.version
+    }
+
+    fn capabilities(&self) -> crate::capabilities::Capabilities {
+        self.capabilities.clone()
+    }
+
+    fn set_verbosity_level(&self, verbosity: api_log::Verbosity) {
+        unsafe { self.api.setVerbosityLevel(verbosity.into()) }
+    }
+
+    fn stlink_server_running(&self) -> bool {
+        crate::discovery::stlink_server_running()
+    }
+
+    fn set_display_callbacks(&self, callbacks: Arc<dyn api_log::DisplayCallbacks>) {
+        *FFI_DISPLAY_CALLBACKS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(callbacks);
+
+        unsafe {
+            self.api
+                .setDisplayCallbacks(stm32cubeprogrammer_sys::displayCallBacks {
+                    initProgressBar: Some(ffi_init_progress_bar),
+                    logMessage: Some(ffi_log_message),
+                    loadBar: Some(ffi_load_bar),
+                })
+        }
+    }
+
+    fn set_cancel_flag(&self, cancel: bool) {
+        if self.api.getCancelPointer.is_err() {
+            debug!("Cancelling is not supported by the API library");
+            return;
+        }
+
+        // The API polls the flag during read/write operations
+        let cancel_pointer = unsafe { self.api.getCancelPointer() };
+
+        if !cancel_pointer.is_null() {
+            unsafe { std::ptr::write_volatile(cancel_pointer, cancel.into()) }
+        }
+    }
+
+    fn list_probes(&self) -> CubeProgrammerResult<Vec<api_types::probe::Probe>> {
+        let mut debug_parameters =
+            std::ptr::null_mut::<stm32cubeprogrammer_sys::debugConnectParameters>();
+        let return_value = unsafe { self.api.getStLinkList(&mut debug_parameters, 0) };
+
+        if return_value < 0 || debug_parameters.is_null() {
+            return Err(CubeProgrammerError::ActionOutputUnexpected {
+                action: Action::ListConnectedProbes,
+                unexpected_output: crate::error::UnexpectedOutput::Null,
+                messages: Vec::new(),
+            });
+        }
+
+        let probes = unsafe { std::slice::from_raw_parts(debug_parameters, return_value as _) }
+            .iter()
+            .copied()
+            .map(api_types::probe::Probe::from)
+            .collect();
+
+        // Free the memory allocated by the API
+        unsafe {
+            self.api.deleteInterfaceList();
+        }
+
+        Ok(probes)
+    }
+
+    fn connect(&self, probe: &api_types::probe::Probe) -> CubeProgrammerResult<()> {
+        api_types::ReturnCode::<0>::from(unsafe { self.api.connectStLink(**probe) })
+            .check(Action::Connect)
+    }
+
+    fn disconnect(&self) {
+        unsafe { self.api.disconnect() }
+    }
+
+    fn check_connection(&self) -> CubeProgrammerResult<()> {
+        require_symbols!(self, Action::CheckConnection, checkDeviceConnection);
+
+        api_types::ReturnCode::<1>::from(unsafe { self.api.checkDeviceConnection() })
+            .check(Action::CheckConnection)
+    }
+
+    fn general_information(&self) -> CubeProgrammerResult<api_types::GeneralInformation> {
+        let general_information = unsafe { self.api.getDeviceGeneralInf() };
+
+        if general_information.is_null() {
+            return Err(CubeProgrammerError::ActionOutputUnexpected {
+                action: Action::ReadTargetInfo,
+                unexpected_output: crate::error::UnexpectedOutput::Null,
+                messages: Vec::new(),
+            });
+        }
+
+        Ok(api_types::GeneralInformation::from(unsafe {
+            *general_information
+        }))
+    }
+
+    fn reset(&self, reset_mode: api_types::probe::ResetMode) -> CubeProgrammerResult<()> {
+        require_symbols!(self, Action::Reset, reset);
+
+        api_types::ReturnCode::<0>::from(unsafe { self.api.reset(reset_mode.into()) })
+            .check(Action::Reset)
+    }
+
+    fn download_file(
+        &self,
+        file_path: &Path,
+        address: u32,
+        skip_erase: bool,
+        verify: bool,
+    ) -> CubeProgrammerResult<()> {
+        require_symbols!(self, Action::DownloadFile, downloadFile);
+
+        let file_path = utility::path_to_widestring(file_path)?;
+
+        self.check_cancelled(
+            api_types::ReturnCode::<0>::from(unsafe {
+                self.api.downloadFile(
+                    file_path.as_ptr(),
+                    address,
+                    if skip_erase { 1 } else { 0 },
+                    if verify { 1 } else { 0 },
+                    std::ptr::null(),
+                )
+            }),
+            Action::DownloadFile,
+        )
+    }
+
+    fn mass_erase(&self) -> CubeProgrammerResult<()> {
+        require_symbols!(self, Action::MassErase, massErase);
+
+        self.check_cancelled(
+            api_types::ReturnCode::<0>::from(unsafe { self.api.massErase(std::ptr::null_mut()) }),
+            Action::MassErase,
+        )
+    }
+
+    fn save_memory_to_file(
+        &self,
+        file_path: &Path,
+        address: u32,
+        size: u32,
+    ) -> CubeProgrammerResult<()> {
+        require_symbols!(self, Action::SaveMemory, saveMemoryToFile);
+
+        self.check_cancelled(
+            api_types::ReturnCode::<0>::from(unsafe {
+                self.api.saveMemoryToFile(
+                    i32::try_from(address).map_err(|x| CubeProgrammerError::Parameter {
+                        action: Action::SaveMemory,
+                        message: format!("Start address exceeds max value: {}", x),
+                    })?,
+                    i32::try_from(size).map_err(|x| CubeProgrammerError::Parameter {
+                        action: Action::SaveMemory,
+                        message: format!("Size exceeds max value: {}", x),
+                    })?,
+                    utility::path_to_widestring(file_path)?.as_ptr(),
+                )
+            }),
+            Action::SaveMemory,
+        )
+    }
+
+    fn send_option_bytes_command(&self, command: &str) -> CubeProgrammerResult<()> {
+        require_symbols!(self, Action::WriteOptionBytes, sendOptionBytesCmd);
+
+        api_types::ReturnCode::<0>::from(unsafe {
+            self.api.sendOptionBytesCmd(
+                utility::string_to_cstring(command)?.as_ptr() as *mut std::ffi::c_char
+            )
+        })
+        .check(Action::WriteOptionBytes)
+    }
+
+    fn option_bits(&self) -> CubeProgrammerResult<Vec<OptionBit>> {
+        require_symbols!(self, Action::ReadOptionBytes, initOptionBytesInterface);
+
+        let peripheral = unsafe { self.api.initOptionBytesInterface() };
+
+        let peripheral =
+            unsafe { peripheral.as_ref() }.ok_or(CubeProgrammerError::ActionOutputUnexpected {
+                action: Action::ReadOptionBytes,
+                unexpected_output: crate::error::UnexpectedOutput::Null,
+                messages: Vec::new(),
+            })?;
+
+        let option_bits = unsafe { utility::iter_ptr_array(peripheral.banks, peripheral.banksNbr) }
+            .flat_map(|bank| unsafe {
+                utility::iter_ptr_array(bank.categories, bank.categoriesNbr)
+            })
+            .flat_map(|category| unsafe {
+                utility::iter_ptr_array(category.bits, category.bitsNbr)
+            })
+            .filter_map(|bit| {
+                // Only use the part up to the null terminator
+                let name = bit.name.split(|x| *x == 0).next().unwrap_or_default();
+
+                utility::c_char_slice_to_string(name)
+                    .ok()
+                    .map(|name| OptionBit {
+                        name: name.to_string(),
+                        value: bit.bitValue,
+                    })
+            })
+            .collect();
+
+        Ok(option_bits)
+    }
+
+    fn read_unprotect(&self) -> CubeProgrammerResult<()> {
+        require_symbols!(self, Action::DisableReadOutProtection, readUnprotect);
+
+        api_types::ReturnCode::<0>::from(unsafe { self.api.readUnprotect() })
+            .check(Action::DisableReadOutProtection)
+    }
+
+    fn tzen_regression(&self) -> CubeProgrammerResult<()> {
+        require_symbols!(self, Action::TrustZoneRegression, tzenRegression);
+
+        api_types::ReturnCode::<0>::from(unsafe { self.api.tzenRegression() })
+            .check(Action::TrustZoneRegression)
+    }
+
+    fn read_memory(&self, address: u32, size: u32) -> CubeProgrammerResult<Vec<u8>> {
+        require_symbols!(self, Action::ReadMemory, readMemory, freeLibraryMemory);
+
+        let mut data = std::ptr::null_mut();
+
+        self.check_cancelled(
+            api_types::ReturnCode::<0>::from(unsafe {
+                self.api.readMemory(address, &mut data, size)
+            }),
+            Action::ReadMemory,
+        )?;
+
+        if data.is_null() {
+            return Err(CubeProgrammerError::ActionOutputUnexpected {
+                action: Action::ReadMemory,
+                unexpected_output: crate::error::UnexpectedOutput::Null,
+                messages: Vec::new(),
+            });
+        }
+
+        let bytes = unsafe { std::slice::from_raw_parts(data, size as _) }.to_vec();
+
+        unsafe {
+            self.api.freeLibraryMemory(data as *mut std::ffi::c_void);
+        }
+
+        Ok(bytes)
+    }
+
+    fn write_memory(&self, address: u32, data: &[u8]) -> CubeProgrammerResult<()> {
+        require_symbols!(self, Action::WriteMemory, writeMemory);
+
+        let size = u32::try_from(data.len()).map_err(|x| CubeProgrammerError::Parameter {
+            action: Action::WriteMemory,
+            message: format!("Size exceeds max value: {}", x),
+        })?;
+
+        let mut data = data.to_vec();
+
+        self.check_cancelled(
+            api_types::ReturnCode::<0>::from(unsafe {
+                self.api
+                    .writeMemory(address, data.as_mut_ptr() as *mut std::ffi::c_char, size)
+            }),
+            Action::WriteMemory,
+        )
+    }
+
+    fn read_core_register(&self, register: api_types::CoreRegister) -> CubeProgrammerResult<u32> {
+        require_symbols!(self, Action::ReadCoreRegister, readCortexReg);
+
+        let mut value = 0;
+
+        api_types::ReturnCode::<0>::from(unsafe {
+            self.api.readCortexReg(register.into(), &mut value)
+        })
+        .check(Action::ReadCoreRegister)?;
+
+        Ok(value)
+    }
+
+    fn write_core_register(
+        &self,
+        register: api_types::CoreRegister,
+        value: u32,
+    ) -> CubeProgrammerResult<()> {
+        require_symbols!(self, Action::WriteCoreRegister, writeCortexRegistres);
+
+        api_types::ReturnCode::<0>::from(unsafe {
+            self.api.writeCortexRegistres(register.into(), value)
+        })
+        .check(Action::WriteCoreRegister)
+    }
+
+    fn uid64(&self) -> CubeProgrammerResult<u64> {
+        require_symbols!(self, Action::ReadUid64, getUID64, freeLibraryMemory);
+
+        let mut data = std::ptr::null_mut();
+
+        let result = api_types::ReturnCode::<0>::from(unsafe { self.api.getUID64(&mut data) })
+            .check(Action::ReadUid64);
+
+        if data.is_null() {
+            result?;
+
+            return Err(CubeProgrammerError::ActionOutputUnexpected {
+                action: Action::ReadUid64,
+                unexpected_output: crate::error::UnexpectedOutput::Null,
+                messages: Vec::new(),
+            });
+        }
+
+        // The buffer is allocated by the API and needs to be released on the success and on the error path
+        let uid64 = result.map(|()| {
+            let bytes = unsafe { std::slice::from_raw_parts(data, std::mem::size_of::<u64>()) };
+            bytemuck::pod_read_unaligned::<u64>(bytes)
+        });
+
+        unsafe {
+            self.api.freeLibraryMemory(data as *mut std::ffi::c_void);
+        }
+
+        uid64
+    }
+
+    fn start_fus(&self) -> CubeProgrammerResult<()> {
+        require_symbols!(self, Action::StartFus, startFus);
+
+        api_types::ReturnCode::<1>::from(unsafe { self.api.startFus() }).check(Action::StartFus)
+    }
+
+    fn start_wireless_stack(&self) -> CubeProgrammerResult<()> {
+        require_symbols!(self, Action::StartWirelessStack, startWirelessStack);
+
+        api_types::ReturnCode::<1>::from(unsafe { self.api.startWirelessStack() })
+            .check(Action::StartWirelessStack)
+    }
+
+    fn firmware_delete(&self) -> CubeProgrammerResult<()> {
+        require_symbols!(self, Action::DeleteWirelessStack, firmwareDelete);
+
+        self.check_cancelled(
+            api_types::ReturnCode::<1>::from(unsafe { self.api.firmwareDelete() }),
+            Action::DeleteWirelessStack,
+        )
+    }
+
+    fn firmware_upgrade(
+        &self,
+        file_path: &Path,
+        address: u32,
+        first_install: bool,
+        start_stack: bool,
+        verify: bool,
+    ) -> CubeProgrammerResult<()> {
+        require_symbols!(self, Action::UpgradeWirelessStack, firmwareUpgrade);
+
+        self.check_cancelled(
+            api_types::ReturnCode::<1>::from(unsafe {
+                self.api.firmwareUpgrade(
+                    utility::path_to_widestring(file_path)?.as_ptr(),
+                    address,
+                    if first_install { 1 } else { 0 },
+                    if start_stack { 1 } else { 0 },
+                    if verify { 1 } else { 0 },
+                )
+            }),
+            Action::UpgradeWirelessStack,
+        )
+    }
+
+    fn anti_rollback(&self) -> CubeProgrammerResult<()> {
+        require_symbols!(self, Action::ActivateAntiRollback, antiRollBack);
+
+        api_types::ReturnCode::<1>::from(unsafe { self.api.antiRollBack() })
+            .check(Action::ActivateAntiRollback)
+    }
+
+    fn program_ssp(
+        &self,
+        ssp_file: &Path,
+        license_file: Option<&Path>,
+        tfa_file: &Path,
+        hsm_slot_id: i32,
+    ) -> CubeProgrammerResult<()> {
+        require_symbols!(self, Action::ProgramSsp, programSsp);
+
+        let license_file = match license_file {
+            Some(license_file) => utility::path_to_widestring(license_file)?,
+            None => utility::string_to_widestring("")?,
+        };
+
+        api_types::ReturnCode::<0>::from(unsafe {
+            self.api.programSsp(
+                utility::path_to_widestring(ssp_file)?.as_ptr(),
+                license_file.as_ptr(),
+                utility::path_to_widestring(tfa_file)?.as_ptr(),
+                hsm_slot_id,
+            )
+        })
+        .check(Action::ProgramSsp)
+    }
+
+    fn hsm_firmware_id(&self, slot_id: i32) -> CubeProgrammerResult<String> {
+        require_symbols!(self, Action::ReadHsmStatus, getHsmFirmwareID);
+
+        Self::hsm_string_from_ptr(unsafe { self.api.getHsmFirmwareID(slot_id) })
+    }
+
+    fn hsm_counter(&self, slot_id: i32) -> CubeProgrammerResult<u64> {
+        require_symbols!(self, Action::ReadHsmStatus, getHsmCounter);
+
+        // `c_ulong` is 32 bit on windows and 64 bit on linux
+        #[allow(clippy::unnecessary_cast)]
+        Ok(unsafe { self.api.getHsmCounter(slot_id) } as u64)
+    }
+
+    fn hsm_state(&self, slot_id: i32) -> CubeProgrammerResult<String> {
+        require_symbols!(self, Action::ReadHsmStatus, getHsmState);
+
+        Self::hsm_string_from_ptr(unsafe { self.api.getHsmState(slot_id) })
+    }
+
+    fn hsm_version(&self, slot_id: i32) -> CubeProgrammerResult<String> {
+        require_symbols!(self, Action::ReadHsmStatus, getHsmVersion);
+
+        Self::hsm_string_from_ptr(unsafe { self.api.getHsmVersion(slot_id) })
+    }
+
+    fn hsm_type(&self, slot_id: i32) -> CubeProgrammerResult<String> {
+        require_symbols!(self, Action::ReadHsmStatus, getHsmType);
+
+        Self::hsm_string_from_ptr(unsafe { self.api.getHsmType(slot_id) })
+    }
+
+    fn hsm_license(&self, slot_id: i32, file_path: &Path) -> CubeProgrammerResult<()> {
+        require_symbols!(self, Action::ExportHsmLicense, getHsmLicense);
+
+        api_types::ReturnCode::<0>::from(unsafe {
+            self.api
+                .getHsmLicense(slot_id, utility::path_to_widestring(file_path)?.as_ptr())
+        })
+        .check(Action::ExportHsmLicense)
+    }
+}
@@ -0,0 +1,1427 @@
+//! In-process simulation of probes with a connected target
+//!
+//! The simulation models the parts of a target which are used by [`crate::CubeProgrammer`]:
+//! - Flash memory organized in sectors. Programming is only possible on erased memory
+//! - SRAM
+//! - Core registers. A reset loads the stack pointer and the program counter from the vector table at the start of the flash memory
+//! - Read out protection and TrustZone option bits
+//! - FUS info table of a stm32wb5x in the shared SRAM2A
+//! - Hardware secure modules (HSM)
+//! - Cancellation of downloads, wireless stack upgrades and mass erase via [`Backend::set_cancel_flag`]
+//!
+//! All probes are connected to the same simulated target.
+//! The backend can be cloned: All clones share the same state, which allows to inspect the target after handing a clone to [`crate::CubeProgrammer`]
+
+use super::{Backend, OptionBit};
+use crate::{
+    api_log, api_types,
+    error::{Action, CubeProgrammerError, CubeProgrammerResult},
+    fus, hsm,
+    utility::copy_to_c_char_slice,
+    LogMessageType,
+};
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+};
+
+/// Read out protection level 0
+const RDP_LEVEL_0: u32 = 0xAA;
+/// Read out protection level 2 (irreversible)
+const RDP_LEVEL_2: u32 = 0xCC;
+/// Keyword which marks the FUS device info table as valid
+const FUS_DEVICE_INFO_TABLE_VALIDITY_KEYWORD: u32 = 0xA94656B9;
+/// Number of core registers (R0 - R12, SP, LR, PC)
+const CORE_REGISTER_COUNT: usize = 16;
+
+/// Flash memory of the simulated target
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulatedFlash {
+    pub base_address: u32,
+    pub sector_size: u32,
+    pub sector_count: u32,
+}
+
+impl SimulatedFlash {
+    /// Size of the flash memory in bytes
+    pub fn size(&self) -> u32 {
+        self.sector_size * self.sector_count
+    }
+}
+
+/// RAM of the simulated target
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulatedRam {
+    pub base_address: u32,
+    pub size: u32,
+}
+
+/// Firmware update service (FUS) of the simulated target
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulatedFus {
+    pub fus_version: fus::Version,
+    pub wireless_stack_version: fus::Version,
+    /// Version of the wireless stack which is reported after a wireless stack upgrade
+    pub upgrade_wireless_stack_version: fus::Version,
+    /// Address of the FUS device info table. Needs to be located in the SRAM
+    pub info_table_address: u32,
+}
+
+/// Probe of the simulation
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulatedProbe {
+    pub serial: String,
+    pub board: String,
+    pub firmware_version: String,
+    pub is_old_firmware: bool,
+    /// Target voltage as reported by the API in volts (e.g. "3.28")
+    pub target_voltage: String,
+    /// Highest frequency in kHz at which the connection succeeds (e.g. limited by a long cable). `None` if all frequencies work
+    pub max_frequency_khz: Option<u32>,
+}
+
+impl Default for SimulatedProbe {
+    fn default() -> Self {
+        Self {
+            serial: "SIMULATED0001".to_string(),
+            board: "NUCLEO-SIM".to_string(),
+            firmware_version: "V3J15M7".to_string(),
+            is_old_firmware: false,
+            target_voltage: "3.28".to_string(),
+            max_frequency_khz: None,
+        }
+    }
+}
+
+/// Description of the simulated target
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulatedTarget {
+    pub device_id: u16,
+    pub name: String,
+    pub series: String,
+    pub cpu: String,
+    pub revision_id: String,
+    pub bootloader_version: u8,
+    pub flash: SimulatedFlash,
+    pub sram: SimulatedRam,
+    /// Initial option bits. The read out protection (`RDP`) is mandatory
+    pub option_bits: Vec<OptionBit>,
+    pub uid64: u64,
+    pub uid96: [u32; 3],
+    /// FUS of the target. Only stm32wb5x targets have a FUS
+    pub fus: Option<SimulatedFus>,
+    /// Number of access ports. Each access port has its own core registers (e.g. the two cores of a dual-core device)
+    pub access_ports: u32,
+}
+
+impl SimulatedTarget {
+    /// stm32wb55 with 1 MB flash, 256 KB SRAM and a FUS
+    pub fn stm32wb55() -> Self {
+        Self {
+            device_id: 0x495,
+            name: "STM32WB5x".to_string(),
+            series: "STM32WB".to_string(),
+            cpu: "Cortex-M4".to_string(),
+            revision_id: "Rev Y".to_string(),
+            bootloader_version: 0xD5,
+            flash: SimulatedFlash {
+                base_address: stm32cubeprogrammer_sys::FLASH_BASE_ADDRESS,
+                sector_size: 0x1000,
+                sector_count: 256,
+            },
+            sram: SimulatedRam {
+                base_address: stm32cubeprogrammer_sys::SRAM_BASE_ADDRESS,
+                size: 0x40000,
+            },
+            option_bits: vec![OptionBit {
+                name: "RDP".to_string(),
+                value: RDP_LEVEL_0,
+            }],
+            uid64: 0x0080_E115_0000_1234,
+            uid96: [0x0034_0021, 0x3132_5110, 0x3036_3834],
+            fus: Some(SimulatedFus {
+                fus_version: fus::Version {
+                    major: 1,
+                    minor: 2,
+                    sub: 0,
+                    r#type: Some(0),
+                },
+                wireless_stack_version: fus::Version {
+                    major: 1,
+                    minor: 17,
+                    sub: 3,
+                    r#type: Some(2),
+                },
+                upgrade_wireless_stack_version: fus::Version {
+                    major: 1,
+                    minor: 20,
+                    sub: 0,
+                    r#type: Some(2),
+                },
+                info_table_address: stm32cubeprogrammer_sys::SRAM_BASE_ADDRESS + 0x30100,
+            }),
+            access_ports: 1,
+        }
+    }
+
+    /// stm32u585 with 2 MB flash, 768 KB SRAM and enabled TrustZone
+    pub fn stm32u585() -> Self {
+        Self {
+            device_id: 0x482,
+            name: "STM32U575/STM32U585".to_string(),
+            series: "STM32U5".to_string(),
+            cpu: "Cortex-M33".to_string(),
+            revision_id: "Rev X".to_string(),
+            bootloader_version: 0x90,
+            flash: SimulatedFlash {
+                base_address: stm32cubeprogrammer_sys::FLASH_BASE_ADDRESS,
+                sector_size: 0x2000,
+                sector_count: 256,
+            },
+            sram: SimulatedRam {
+                base_address: stm32cubeprogrammer_sys::SRAM_BASE_ADDRESS,
+                size: 0xC0000,
+            },
+            option_bits: vec![
+                OptionBit {
+                    name: "RDP".to_string(),
+                    value: RDP_LEVEL_0,
+                },
+                OptionBit {
+                    name: "TZEN".to_string(),
+                    value: 1,
+                },
+            ],
+            uid64: 0x0020_3B9A_0000_5678,
+            uid96: [0x0041_0032, 0x3233_5101, 0x3834_3036],
+            fus: None,
+            access_ports: 1,
+        }
+    }
+}
+
+impl Default for SimulatedTarget {
+    fn default() -> Self {
+        Self::stm32wb55()
+    }
+}
+
+/// Memory region of the simulated target
+enum Region {
+    Flash(usize),
+    Sram(usize),
+    Uid(usize),
+}
+
+/// Mutable state of the simulation
+struct State {
+    api_version: Option<api_types::CubeProgrammerVersion>,
+    stlink_server_running: bool,
+    /// Number of the following connection attempts which fail
+    failing_connections: u32,
+    probes: Vec<SimulatedProbe>,
+    hsm: Vec<hsm::HsmStatus>,
+    verbosity: api_log::Verbosity,
+    callbacks: Option<Arc<dyn api_log::DisplayCallbacks>>,
+    connected: Option<api_types::probe::Probe>,
+    flash: Vec<u8>,
+    sram: Vec<u8>,
+    option_bits: Vec<OptionBit>,
+    /// Core registers per access port
+    core_registers: Vec<[u32; CORE_REGISTER_COUNT]>,
+    fus_running: bool,
+    wireless_stack_version: Option<fus::Version>,
+    anti_rollback_active: bool,
+}
+
+struct Inner {
+    target: SimulatedTarget,
+    state: Mutex<State>,
+    /// Cancel flag of the API. Is not part of the state, because it is set while an operation holds the state
+    cancel: AtomicBool,
+}
+
+/// Backend which simulates probes with a connected target in-process
+#[derive(Clone)]
+pub struct SimulatedBackend {
+    inner: Arc<Inner>,
+}
+
+impl SimulatedBackend {
+    /// Create a new simulation with one [`SimulatedProbe::default`] probe
+    pub fn new(target: SimulatedTarget) -> Self {
+        let state = State {
+            api_version: Some(api_types::CubeProgrammerVersion::new(2, 18, 0)),
+            stlink_server_running: false,
+            failing_connections: 0,
+            probes: vec![SimulatedProbe::default()],
+            hsm: Vec::new(),
+            verbosity: api_log::Verbosity::Level3,
+            callbacks: None,
+            connected: None,
+            flash: vec![0xFF; target.flash.size() as usize],
+            sram: vec![0; target.sram.size as usize],
+            option_bits: target.option_bits.clone(),
+            core_registers: vec![[0; CORE_REGISTER_COUNT]; target.access_ports.max(1) as usize],
+            fus_running: false,
+            wireless_stack_version: target.fus.as_ref().map(|x| x.wireless_stack_version),
+            anti_rollback_active: false,
+        };
+
+        Self {
+            inner: Arc::new(Inner {
+                target,
+                state: Mutex::new(state),
+                cancel: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Set the simulated version of STM32CubeProgrammer. Defaults to 2.18.0
+    pub fn with_api_version(self, version: Option<api_types::CubeProgrammerVersion>) -> Self {
+        self.state().api_version = version;
+        self
+    }
+
+    /// Replace the probes of the simulation
+    pub fn with_probes(self, probes: Vec<SimulatedProbe>) -> Self {
+        self.state().probes = probes;
+        self
+    }
+
+    /// Add a hardware secure module (HSM) to the simulation
+    pub fn with_hsm(self, status: hsm::HsmStatus) -> Self {
+        self.state().hsm.push(status);
+        self
+    }
+
+    /// Set the target voltage which is reported by the probe with the given serial (e.g. "0.00" for an unpowered target)
+    pub fn set_target_voltage(&self, serial: &str, target_voltage: &str) {
+        if let Some(probe) = self.state().probes.iter_mut().find(|x| x.serial == serial) {
+            probe.target_voltage = target_voltage.to_string();
+        }
+    }
+
+    /// Simulate a running ST-LINK server, which is needed for shared connections. Defaults to not running
+    pub fn set_stlink_server_running(&self, running: bool) {
+        self.state().stlink_server_running = running;
+    }
+
+    /// Simulate a lost connection (e.g. a glitch of the pogo pins). All target related calls fail until the next connect
+    pub fn drop_connection(&self) {
+        self.state().connected = None;
+    }
+
+    /// Let the given number of the following connection attempts fail
+    pub fn fail_connections(&self, count: u32) {
+        self.state().failing_connections = count;
+    }
+
+    /// Description of the simulated target
+    pub fn target(&self) -> &SimulatedTarget {
+        &self.inner.target
+    }
+
+    /// Serial of the probe which is currently connected to the target
+    pub fn connected_probe(&self) -> Option<String> {
+        self.state()
+            .connected
+            .as_ref()
+            .map(|x| x.serial_number().to_string())
+    }
+
+    /// Content of the whole flash memory
+    pub fn flash(&self) -> Vec<u8> {
+        self.state().flash.clone()
+    }
+
+    /// Content of the whole SRAM
+    pub fn sram(&self) -> Vec<u8> {
+        self.state().sram.clone()
+    }
+
+    /// Current value of an option bit
+    pub fn option_bit(&self, name: &str) -> Option<u32> {
+        self.state()
+            .option_bits
+            .iter()
+            .find(|x| x.name.eq_ignore_ascii_case(name))
+            .map(|x| x.value)
+    }
+
+    /// Check if the anti-rollback of the wireless stack was activated
+    pub fn anti_rollback_active(&self) -> bool {
+        self.state().anti_rollback_active
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.inner.state.lock().unwrap()
+    }
+
+    /// Lock the state and make sure that there is a connection to the target
+    fn connected_state(&self, action: Action) -> CubeProgrammerResult<MutexGuard<'_, State>> {
+        let state = self.state();
+
+        if state.connected.is_none() {
+            return Err(failed(action, api_types::ErrorCode::DeviceNotConnected));
+        }
+
+        Ok(state)
+    }
+
+    /// Access port of the current connection
+    fn connected_access_port(state: &State) -> usize {
+        state
+            .connected
+            .as_ref()
+            .map(|x| x.access_port() as usize)
+            .unwrap_or_default()
+    }
+
+    /// Fail if the cancel flag is set, like the API aborts a running operation
+    fn check_cancel(&self, state: &State, action: Action) -> CubeProgrammerResult<()> {
+        if self.inner.cancel.load(Ordering::SeqCst) {
+            log(state, LogMessageType::Warning, "Operation cancelled");
+            return Err(CubeProgrammerError::Cancelled { action });
+        }
+
+        Ok(())
+    }
+
+    /// Map an address range to a memory region of the target
+    fn region(&self, address: u32, size: usize) -> Option<Region> {
+        let target = &self.inner.target;
+        let contains = |base: u32, len: usize| {
+            let offset = address.checked_sub(base)? as usize;
+            (offset + size <= len).then_some(offset)
+        };
+
+        if let Some(offset) = contains(target.flash.base_address, target.flash.size() as usize) {
+            return Some(Region::Flash(offset));
+        }
+
+        if let Some(offset) = contains(target.sram.base_address, target.sram.size as usize) {
+            return Some(Region::Sram(offset));
+        }
+
+        api_types::uid::uid96_base_address(target.device_id as u32)
+            .and_then(|base| contains(base, std::mem::size_of::<[u32; 3]>()))
+            .map(Region::Uid)
+    }
+
+    fn read(&self, state: &State, address: u32, size: usize) -> CubeProgrammerResult<Vec<u8>> {
+        match self.region(address, size) {
+            Some(Region::Flash(offset)) => {
+                if option_bit(state, "RDP") != Some(RDP_LEVEL_0) {
+                    log(
+                        state,
+                        LogMessageType::Error,
+                        "Read out protection is enabled",
+                    );
+                    return Err(failed(
+                        Action::ReadMemory,
+                        api_types::ErrorCode::RdpEnabledError,
+                    ));
+                }
+
+                Ok(state.flash[offset..offset + size].to_vec())
+            }
+            Some(Region::Sram(offset)) => Ok(state.sram[offset..offset + size].to_vec()),
+            Some(Region::Uid(offset)) => {
+                let uid: &[u8] = bytemuck::cast_slice(&self.inner.target.uid96);
+                Ok(uid[offset..offset + size].to_vec())
+            }
+            None => Err(failed(
+                Action::ReadMemory,
+                api_types::ErrorCode::MemoryReadError,
+            )),
+        }
+    }
+
+    /// Write to memory. Flash memory can only be programmed if it is erased
+    fn write(
+        &self,
+        state: &mut State,
+        action: Action,
+        address: u32,
+        data: &[u8],
+    ) -> CubeProgrammerResult<()> {
+        match self.region(address, data.len()) {
+            Some(Region::Flash(offset)) => {
+                if option_bit(state, "RDP") != Some(RDP_LEVEL_0) {
+                    log(
+                        state,
+                        LogMessageType::Error,
+                        "Read out protection is enabled",
+                    );
+                    return Err(failed(action, api_types::ErrorCode::RdpEnabledError));
+                }
+
+                let flash = &mut state.flash[offset..offset + data.len()];
+
+                if flash
+                    .iter()
+                    .zip(data)
+                    .any(|(current, new)| *current != 0xFF && current != new)
+                {
+                    log(
+                        state,
+                        LogMessageType::Error,
+                        &format!("Flash memory at address 0x{:08X} is not erased", address),
+                    );
+                    return Err(failed(action, api_types::ErrorCode::MemoryWriteError));
+                }
+
+                flash.copy_from_slice(data);
+                Ok(())
+            }
+            Some(Region::Sram(offset)) => {
+                state.sram[offset..offset + data.len()].copy_from_slice(data);
+                Ok(())
+            }
+            Some(Region::Uid(_)) | None => {
+                Err(failed(action, api_types::ErrorCode::MemoryWriteError))
+            }
+        }
+    }
+
+    /// Erase all flash sectors which overlap with the given address range
+    fn erase_sectors(&self, state: &mut State, address: u32, size: usize) {
+        let flash = &self.inner.target.flash;
+        let sector_size = flash.sector_size as usize;
+
+        let start = (address.saturating_sub(flash.base_address) as usize).min(state.flash.len());
+        let end = (start + size).min(state.flash.len());
+
+        if start == end {
+            return;
+        }
+
+        let first_sector = start / sector_size;
+        let last_sector = (end - 1) / sector_size;
+
+        log(
+            state,
+            LogMessageType::Info,
+            &format!("Erasing sectors {} to {}", first_sector, last_sector),
+        );
+
+        state.flash[first_sector * sector_size..(last_sector + 1) * sector_size].fill(0xFF);
+    }
+
+    fn mass_erase_inner(&self, state: &mut State) {
+        log(state, LogMessageType::Info, "Mass erase");
+        state.flash.fill(0xFF);
+    }
+
+    /// Program segments of a file to the target
+    fn program(
+        &self,
+        state: &mut State,
+        action: Action,
+        segments: &[(u32, Vec<u8>)],
+        skip_erase: bool,
+        verify: bool,
+    ) -> CubeProgrammerResult<()> {
+        if !skip_erase {
+            for (address, data) in segments {
+                if matches!(self.region(*address, data.len()), Some(Region::Flash(_))) {
+                    self.erase_sectors(state, *address, data.len());
+                }
+            }
+        }
+
+        progress_init(state);
+
+        for (index, (address, data)) in segments.iter().enumerate() {
+            self.check_cancel(state, action)?;
+            self.write(state, action, *address, data)?;
+            progress_update(state, index + 1, segments.len());
+        }
+
+        if verify {
+            self.check_cancel(state, action)?;
+
+            for (address, data) in segments {
+                if self.read(state, *address, data.len())? != *data {
+                    log(state, LogMessageType::Error, "Verification failed");
+                    return Err(failed(action, api_types::ErrorCode::MemoryWriteError));
+                }
+            }
+
+            log(
+                state,
+                LogMessageType::GreenInfo,
+                "Download verified successfully",
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Reset the core of the access port. The stack pointer and the program counter are loaded from the vector table
+    fn reset_core(&self, state: &mut State, access_port: usize) {
+        let word = |index: usize| {
+            bytemuck::pod_read_unaligned::<u32>(&state.flash[index * 4..index * 4 + 4])
+        };
+
+        let (stack_pointer, reset_handler) = (word(0), word(1));
+
+        let mut core_registers = [0; CORE_REGISTER_COUNT];
+        core_registers[u32::from(api_types::CoreRegister::LR) as usize] = 0xFFFF_FFFF;
+
+        if stack_pointer != 0xFFFF_FFFF {
+            core_registers[u32::from(api_types::CoreRegister::SP) as usize] = stack_pointer;
+            core_registers[u32::from(api_types::CoreRegister::PC) as usize] = reset_handler & !1;
+        }
+
+        state.core_registers[access_port] = core_registers;
+    }
+
+    /// Write the FUS device info table to the SRAM2A
+    fn write_fus_info_table(&self, state: &mut State) -> CubeProgrammerResult<()> {
+        fn version_to_u32(version: Option<fus::Version>) -> u32 {
+            version
+                .map(|x| {
+                    (x.major as u32) << 24
+                        | (x.minor as u32) << 16
+                        | (x.sub as u32) << 8
+                        | x.r#type.unwrap_or_default() as u32
+                })
+                .unwrap_or_default()
+        }
+
+        let Some(fus) = &self.inner.target.fus else {
+            return Ok(());
+        };
+
+        let mut table = [0u8; 52];
+        table[0..4].copy_from_slice(&FUS_DEVICE_INFO_TABLE_VALIDITY_KEYWORD.to_le_bytes());
+        table[5] = if state.fus_running { 1 } else { 0 };
+        table[12..16].copy_from_slice(&version_to_u32(Some(fus.fus_version)).to_le_bytes());
+        table[20..24].copy_from_slice(&version_to_u32(state.wireless_stack_version).to_le_bytes());
+        table[40..48].copy_from_slice(&self.inner.target.uid64.to_le_bytes());
+        table[48..50].copy_from_slice(&self.inner.target.device_id.to_le_bytes());
+
+        let sram2a_address = stm32cubeprogrammer_sys::SRAM_BASE_ADDRESS + 0x30000;
+
+        self.write(
+            state,
+            Action::StartFus,
+            sram2a_address,
+            &fus.info_table_address.to_le_bytes(),
+        )?;
+        self.write(state, Action::StartFus, fus.info_table_address, &table)
+    }
+
+    /// Make sure that the target has a running FUS
+    fn check_fus_running(&self, state: &State, action: Action) -> CubeProgrammerResult<()> {
+        if self.inner.target.fus.is_none() || !state.fus_running {
+            log(state, LogMessageType::Error, "FUS is not running");
+            return Err(failed(action, api_types::ErrorCode::UnsupportedOperation));
+        }
+
+        Ok(())
+    }
+
+    fn hsm_status(&self, slot_id: i32) -> CubeProgrammerResult<hsm::HsmStatus> {
+        self.state()
+            .hsm
+            .iter()
+            .find(|x| x.slot_id as i32 == slot_id)
+            .cloned()
+            .ok_or(CubeProgrammerError::ActionOutputUnexpected {
+                action: Action::ReadHsmStatus,
+                unexpected_output: crate::error::UnexpectedOutput::Null,
+                messages: Vec::new(),
+            })
+    }
+}
+
+impl std::fmt::Debug for SimulatedBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SimulatedBackend")
+            .field("target", &self.inner.target.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Backend for SimulatedBackend {
+    fn api_version(&self) -> Option<api_types::CubeProgrammerVersion> {
+        self.state().api_version
+    }
+
+    fn stlink_server_running(&self) -> bool {
+        self.state().stlink_server_running
+    }
+
+    fn set_verbosity_level(&self, verbosity: api_log::Verbosity) {
+        self.state().verbosity = verbosity;
+    }
+
+    fn set_display_callbacks(&self, callbacks: Arc<dyn api_log::DisplayCallbacks>) {
+        self.state().callbacks = Some(callbacks);
+    }
+
+    fn set_cancel_flag(&self, cancel: bool) {
+        self.inner.cancel.store(cancel, Ordering::SeqCst);
+    }
+
+    fn list_probes(&self) -> CubeProgrammerResult<Vec<api_types::probe::Probe>> {
+        let mut state = self.state();
+
+        // Like the API, the probe scan ends the current connection
+        state.connected = None;
+
+        Ok(state
+            .probes
+            .iter()
+            .enumerate()
+            .map(|(index, probe)| {
+                api_types::probe::Probe::from(debug_connect_parameters(
+                    index,
+                    probe,
+                    self.inner.target.access_ports,
+                ))
+            })
+            .collect())
+    }
+
+    fn connect(&self, parameters: &api_types::probe::Probe) -> CubeProgrammerResult<()> {
+        let mut state = self.state();
+        let serial = parameters.serial_number().to_string();
+
+        let Some(probe) = state.probes.iter().find(|x| x.serial == serial) else {
+            log(
+                &state,
+                LogMessageType::Error,
+                &format!("No ST-LINK with serial {} detected", serial),
+            );
+            return Err(failed(Action::Connect, api_types::ErrorCode::NoDeviceFound));
+        };
+
+        if state.failing_connections > 0 {
+            state.failing_connections -= 1;
+            log(&state, LogMessageType::Error, "No STM32 target found");
+            return Err(failed(Action::Connect, api_types::ErrorCode::NoDeviceFound));
+        }
+
+        let frequency = parameters.frequency_khz();
+
+        if probe
+            .max_frequency_khz
+            .is_some_and(|max_frequency| frequency > max_frequency)
+        {
+            log(
+                &state,
+                LogMessageType::Error,
+                &format!("No STM32 target found at {} kHz", frequency),
+            );
+            return Err(failed(Action::Connect, api_types::ErrorCode::NoDeviceFound));
+        }
+
+        log(
+            &state,
+            LogMessageType::Info,
+            &format!("ST-LINK SN  : {}", serial),
+        );
+
+        if parameters.shared() && !state.stlink_server_running {
+            log(
+                &state,
+                LogMessageType::Error,
+                "Shared mode needs a running ST-LINK server",
+            );
+            return Err(failed(
+                Action::Connect,
+                api_types::ErrorCode::ConnectionError,
+            ));
+        }
+
+        let access_port = usize::try_from(parameters.access_port()).unwrap_or(usize::MAX);
+
+        if access_port >= state.core_registers.len() {
+            log(
+                &state,
+                LogMessageType::Error,
+                &format!("Access port {} is not available", parameters.access_port()),
+            );
+            return Err(failed(
+                Action::Connect,
+                api_types::ErrorCode::ConnectionError,
+            ));
+        }
+
+        let connection_mode = parameters.connection_mode();
+
+        if !matches!(
+            connection_mode,
+            api_types::probe::ConnectionMode::HotPlug | api_types::probe::ConnectionMode::PowerDown
+        ) {
+            self.reset_core(&mut state, access_port);
+        }
+
+        state.connected = Some(parameters.clone());
+        Ok(())
+    }
+
+    fn disconnect(&self) {
+        let mut state = self.state();
+
+        if state.connected.take().is_some() {
+            log(&state, LogMessageType::Info, "Disconnected from device");
+        }
+    }
+
+    fn check_connection(&self) -> CubeProgrammerResult<()> {
+        self.connected_state(Action::CheckConnection).map(|_| ())
+    }
+
+    fn general_information(&self) -> CubeProgrammerResult<api_types::GeneralInformation> {
+        let state = self.connected_state(Action::ReadTargetInfo)?;
+        let target = &self.inner.target;
+
+        // All fields are plain integers and arrays, so the all-zero pattern is valid
+        let mut information: stm32cubeprogrammer_sys::generalInf = unsafe { std::mem::zeroed() };
+
+        information.deviceId = target.device_id;
+        // Flash size in KBytes
+        information.flashSize = (target.flash.size() / 1024) as _;
+        information.bootloaderVersion = target.bootloader_version as _;
+        copy_to_c_char_slice(&mut information.type_, "MCU");
+        copy_to_c_char_slice(&mut information.cpu, &target.cpu);
+        copy_to_c_char_slice(&mut information.name, &target.name);
+        copy_to_c_char_slice(&mut information.series, &target.series);
+        copy_to_c_char_slice(&mut information.description, "Simulated target");
+        copy_to_c_char_slice(&mut information.revisionId, &target.revision_id);
+
+        if let Some(connected) = &state.connected {
+            information.board = connected.board;
+        }
+
+        Ok(api_types::GeneralInformation::from(information))
+    }
+
+    fn reset(&self, _reset_mode: api_types::probe::ResetMode) -> CubeProgrammerResult<()> {
+        let mut state = self.connected_state(Action::Reset)?;
+        let access_port = Self::connected_access_port(&state);
+        self.reset_core(&mut state, access_port);
+        Ok(())
+    }
+
+    fn download_file(
+        &self,
+        file_path: &Path,
+        address: u32,
+        skip_erase: bool,
+        verify: bool,
+    ) -> CubeProgrammerResult<()> {
+        let mut state = self.connected_state(Action::DownloadFile)?;
+        let segments = read_segments(&state, Action::DownloadFile, file_path, address)?;
+
+        self.program(
+            &mut state,
+            Action::DownloadFile,
+            &segments,
+            skip_erase,
+            verify,
+        )?;
+
+        log(&state, LogMessageType::GreenInfo, "File download complete");
+        Ok(())
+    }
+
+    fn mass_erase(&self) -> CubeProgrammerResult<()> {
+        let mut state = self.connected_state(Action::MassErase)?;
+
+        if option_bit(&state, "RDP") != Some(RDP_LEVEL_0) {
+            return Err(failed(
+                Action::MassErase,
+                api_types::ErrorCode::RdpEnabledError,
+            ));
+        }
+
+        self.check_cancel(&state, Action::MassErase)?;
+        self.mass_erase_inner(&mut state);
+        Ok(())
+    }
+
+    fn save_memory_to_file(
+        &self,
+        file_path: &Path,
+        address: u32,
+        size: u32,
+    ) -> CubeProgrammerResult<()> {
+        let state = self.connected_state(Action::SaveMemory)?;
+        let data = self.read(&state, address, size as usize)?;
+
+        let content = match extension(file_path).as_deref() {
+            Some("bin") => data,
+            #[cfg(feature = "ihex")]
+            Some("hex") => to_ihex(address, &data)
+                .map_err(|_| failed(Action::SaveMemory, api_types::ErrorCode::UnknownError))?
+                .into_bytes(),
+            _ => {
+                return Err(failed(
+                    Action::SaveMemory,
+                    api_types::ErrorCode::UnsupportedFileFormat,
+                ))
+            }
+        };
+
+        std::fs::write(file_path, content).map_err(CubeProgrammerError::FileIo)
+    }
+
+    fn send_option_bytes_command(&self, command: &str) -> CubeProgrammerResult<()> {
+        let action = Action::WriteOptionBytes;
+        let mut state = self.connected_state(action)?;
+
+        let mut tokens = command.split_whitespace();
+
+        if !tokens.next().is_some_and(|x| x.eq_ignore_ascii_case("-ob")) {
+            return Err(failed(action, api_types::ErrorCode::UnknownParameters));
+        }
+
+        // Parse all values before changing anything
+        let mut values = Vec::new();
+        let mut reset = false;
+        for token in tokens {
+            let (name, value) = token
+                .split_once('=')
+                .and_then(|(name, value)| Some((name, parse_u32(value)?)))
+                .ok_or(failed(action, api_types::ErrorCode::UnknownParameters))?;
+
+            if option_bit(&state, name).is_none() {
+                log(
+                    &state,
+                    LogMessageType::Error,
+                    &format!("Unknown option bit {}", name),
+                );
+                return Err(failed(action, api_types::ErrorCode::UnknownParameters));
+            }
+
+            values.push((name.to_string(), value));
+        }
+
+        if option_bit(&state, "RDP") == Some(RDP_LEVEL_2) {
+            log(&state, LogMessageType::Error, "Option bytes are locked");
+            return Err(failed(action, api_types::ErrorCode::SecurityError));
+        }
+
+        for (name, value) in values {
+            let current = option_bit(&state, &name);
+
+            if name.eq_ignore_ascii_case("TZEN") && current != Some(0) && value == 0 {
+                log(
+                    &state,
+                    LogMessageType::Error,
+                    "TrustZone can only be disabled via a TrustZone regression",
+                );
+                return Err(failed(action, api_types::ErrorCode::SecurityError));
+            }
+
+            if name.eq_ignore_ascii_case("RDP")
+                && current != Some(RDP_LEVEL_0)
+                && value == RDP_LEVEL_0
+            {
+                // Read out protection regression to level 0
+                self.mass_erase_inner(&mut state);
+            }
+
+            // The option byte launch after a change of the read out protection resets the target
+            reset |= name.eq_ignore_ascii_case("RDP") && current != Some(value);
+
+            if let Some(bit) = state
+                .option_bits
+                .iter_mut()
+                .find(|x| x.name.eq_ignore_ascii_case(&name))
+            {
+                bit.value = value;
+            }
+        }
+
+        log(
+            &state,
+            LogMessageType::GreenInfo,
+            "Option Bytes successfully programmed",
+        );
+
+        if reset {
+            state.connected = None;
+        }
+
+        Ok(())
+    }
+
+    fn option_bits(&self) -> CubeProgrammerResult<Vec<OptionBit>> {
+        Ok(self
+            .connected_state(Action::ReadOptionBytes)?
+            .option_bits
+            .clone())
+    }
+
+    fn read_unprotect(&self) -> CubeProgrammerResult<()> {
+        let action = Action::DisableReadOutProtection;
+        let mut state = self.connected_state(action)?;
+
+        match option_bit(&state, "RDP") {
+            Some(RDP_LEVEL_0) => {
+                log(
+                    &state,
+                    LogMessageType::Info,
+                    "Read out protection is not enabled",
+                );
+            }
+            Some(RDP_LEVEL_2) => {
+                return Err(failed(action, api_types::ErrorCode::SecurityError));
+            }
+            _ => {
+                self.mass_erase_inner(&mut state);
+                set_option_bit(&mut state, "RDP", RDP_LEVEL_0);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn tzen_regression(&self) -> CubeProgrammerResult<()> {
+        let action = Action::TrustZoneRegression;
+        let mut state = self.connected_state(action)?;
+
+        match (option_bit(&state, "RDP"), option_bit(&state, "TZEN")) {
+            (_, None) => Err(failed(action, api_types::ErrorCode::UnsupportedOperation)),
+            (Some(RDP_LEVEL_2), _) => Err(failed(action, api_types::ErrorCode::SecurityError)),
+            (Some(RDP_LEVEL_0), _) | (Some(0x55), _) => {
+                log(
+                    &state,
+                    LogMessageType::Error,
+                    "TrustZone regression requires read out protection level 1",
+                );
+                Err(failed(action, api_types::ErrorCode::SecurityError))
+            }
+            _ => {
+                self.mass_erase_inner(&mut state);
+                set_option_bit(&mut state, "TZEN", 0);
+                set_option_bit(&mut state, "RDP", RDP_LEVEL_0);
+
+                // The regression resets the target, which ends the connection
+                state.connected = None;
+                Ok(())
+            }
+        }
+    }
+
+    fn read_memory(&self, address: u32, size: u32) -> CubeProgrammerResult<Vec<u8>> {
+        let state = self.connected_state(Action::ReadMemory)?;
+        self.read(&state, address, size as usize)
+    }
+
+    fn write_memory(&self, address: u32, data: &[u8]) -> CubeProgrammerResult<()> {
+        let mut state = self.connected_state(Action::WriteMemory)?;
+        self.write(&mut state, Action::WriteMemory, address, data)
+    }
+
+    fn read_core_register(&self, register: api_types::CoreRegister) -> CubeProgrammerResult<u32> {
+        let state = self.connected_state(Action::ReadCoreRegister)?;
+
+        state.core_registers[Self::connected_access_port(&state)]
+            .get(u32::from(register) as usize)
+            .copied()
+            .ok_or(failed(
+                Action::ReadCoreRegister,
+                api_types::ErrorCode::UnknownParameters,
+            ))
+    }
+
+    fn write_core_register(
+        &self,
+        register: api_types::CoreRegister,
+        value: u32,
+    ) -> CubeProgrammerResult<()> {
+        let mut state = self.connected_state(Action::WriteCoreRegister)?;
+        let access_port = Self::connected_access_port(&state);
+
+        let register = state.core_registers[access_port]
+            .get_mut(u32::from(register) as usize)
+            .ok_or(failed(
+                Action::WriteCoreRegister,
+                api_types::ErrorCode::UnknownParameters,
+            ))?;
+
+        *register = value;
+        Ok(())
+    }
+
+    fn uid64(&self) -> CubeProgrammerResult<u64> {
+        self.check_connection()?;
+        Ok(self.inner.target.uid64)
+    }
+
+    fn start_fus(&self) -> CubeProgrammerResult<()> {
+        let mut state = self.connected_state(Action::StartFus)?;
+
+        if self.inner.target.fus.is_none() {
+            return Err(failed(
+                Action::StartFus,
+                api_types::ErrorCode::UnsupportedOperation,
+            ));
+        }
+
+        state.fus_running = true;
+        self.write_fus_info_table(&mut state)?;
+
+        log(&state, LogMessageType::GreenInfo, "FUS started");
+        Ok(())
+    }
+
+    fn start_wireless_stack(&self) -> CubeProgrammerResult<()> {
+        let mut state = self.connected_state(Action::StartWirelessStack)?;
+
+        if self.inner.target.fus.is_none() || state.wireless_stack_version.is_none() {
+            return Err(failed(
+                Action::StartWirelessStack,
+                api_types::ErrorCode::UnsupportedOperation,
+            ));
+        }
+
+        state.fus_running = false;
+        Ok(())
+    }
+
+    fn firmware_delete(&self) -> CubeProgrammerResult<()> {
+        let mut state = self.connected_state(Action::DeleteWirelessStack)?;
+        self.check_fus_running(&state, Action::DeleteWirelessStack)?;
+
+        state.wireless_stack_version = None;
+        self.write_fus_info_table(&mut state)?;
+
+        log(&state, LogMessageType::GreenInfo, "Firmware delete success");
+        Ok(())
+    }
+
+    fn firmware_upgrade(
+        &self,
+        file_path: &Path,
+        address: u32,
+        first_install: bool,
+        start_stack: bool,
+        verify: bool,
+    ) -> CubeProgrammerResult<()> {
+        let action = Action::UpgradeWirelessStack;
+        let mut state = self.connected_state(action)?;
+        self.check_fus_running(&state, action)?;
+
+        if !first_install {
+            state.wireless_stack_version = None;
+        }
+
+        let segments = read_segments(&state, action, file_path, address)?;
+        self.program(&mut state, action, &segments, false, verify)?;
+
+        state.wireless_stack_version = self
+            .inner
+            .target
+            .fus
+            .as_ref()
+            .map(|x| x.upgrade_wireless_stack_version);
+        self.write_fus_info_table(&mut state)?;
+
+        log(
+            &state,
+            LogMessageType::GreenInfo,
+            "Firmware Upgrade Success",
+        );
+
+        if start_stack {
+            state.fus_running = false;
+        }
+
+        Ok(())
+    }
+
+    fn anti_rollback(&self) -> CubeProgrammerResult<()> {
+        let mut state = self.connected_state(Action::ActivateAntiRollback)?;
+        self.check_fus_running(&state, Action::ActivateAntiRollback)?;
+
+        state.anti_rollback_active = true;
+        self.write_fus_info_table(&mut state)?;
+
+        log(
+            &state,
+            LogMessageType::GreenInfo,
+            "Anti-rollback successfully activated",
+        );
+        Ok(())
+    }
+
+    fn program_ssp(
+        &self,
+        _ssp_file: &Path,
+        _license_file: Option<&Path>,
+        tfa_file: &Path,
+        _hsm_slot_id: i32,
+    ) -> CubeProgrammerResult<()> {
+        let state = self.connected_state(Action::ProgramSsp)?;
+
+        let Ok(tfa) = std::fs::read(tfa_file) else {
+            log(
+                &state,
+                LogMessageType::Error,
+                &format!("File {} not found", tfa_file.display()),
+            );
+            return Err(failed(
+                Action::ProgramSsp,
+                api_types::ErrorCode::FileNotFound,
+            ));
+        };
+        log(
+            &state,
+            LogMessageType::Info,
+            &format!("RSSe binary with {} bytes loaded", tfa.len()),
+        );
+
+        // Like the API, the SSP is not supported via the debug interface
+        log(
+            &state,
+            LogMessageType::Error,
+            "SSP is only supported via USB DFU and UART",
+        );
+        Err(failed(
+            Action::ProgramSsp,
+            api_types::ErrorCode::UnsupportedInterface,
+        ))
+    }
+
+    fn hsm_firmware_id(&self, slot_id: i32) -> CubeProgrammerResult<String> {
+        self.hsm_status(slot_id).map(|x| x.firmware_id)
+    }
+
+    fn hsm_counter(&self, slot_id: i32) -> CubeProgrammerResult<u64> {
+        self.hsm_status(slot_id).map(|x| x.counter)
+    }
+
+    fn hsm_state(&self, slot_id: i32) -> CubeProgrammerResult<String> {
+        self.hsm_status(slot_id).map(|x| x.state.to_string())
+    }
+
+    fn hsm_version(&self, slot_id: i32) -> CubeProgrammerResult<String> {
+        self.hsm_status(slot_id).map(|x| x.version.to_string())
+    }
+
+    fn hsm_type(&self, slot_id: i32) -> CubeProgrammerResult<String> {
+        self.hsm_status(slot_id).map(|x| x.r#type.to_string())
+    }
+
+    fn hsm_license(&self, slot_id: i32, file_path: &Path) -> CubeProgrammerResult<()> {
+        let action = Action::ExportHsmLicense;
+        let mut state = self.connected_state(action)?;
+        let uid64 = self.inner.target.uid64;
+
+        let hsm = state
+            .hsm
+            .iter_mut()
+            .find(|x| x.slot_id as i32 == slot_id)
+            .ok_or(failed(action, api_types::ErrorCode::NoDeviceFound))?;
+
+        if hsm.counter == 0 {
+            return Err(failed(action, api_types::ErrorCode::SecurityError));
+        }
+
+        hsm.counter -= 1;
+
+        let mut license = hsm.firmware_id.as_bytes().to_vec();
+        license.extend_from_slice(&uid64.to_le_bytes());
+
+        std::fs::write(file_path, license).map_err(CubeProgrammerError::FileIo)
+    }
+}
+
+/// Create an [`CubeProgrammerError::ActionFailed`] error like the API return codes are mapped
+fn failed(action: Action, error_code: api_types::ErrorCode) -> CubeProgrammerError {
+    CubeProgrammerError::ActionFailed {
+        action,
+        return_code: error_code,
+        messages: Vec::new(),
+    }
+}
+
+fn option_bit(state: &State, name: &str) -> Option<u32> {
+    state
+        .option_bits
+        .iter()
+        .find(|x| x.name.eq_ignore_ascii_case(name))
+        .map(|x| x.value)
+}
+
+fn set_option_bit(state: &mut State, name: &str, value: u32) {
+    if let Some(bit) = state
+        .option_bits
+        .iter_mut()
+        .find(|x| x.name.eq_ignore_ascii_case(name))
+    {
+        bit.value = value;
+    }
+}
+
+/// Forward a log message to the display callbacks like the API does
+fn log(state: &State, message_type: LogMessageType, message: &str) {
+    let Some(callbacks) = &state.callbacks else {
+        return;
+    };
+
+    let level = match message_type {
+        LogMessageType::Verbosity1 => 1,
+        LogMessageType::Verbosity2 => 2,
+        LogMessageType::Verbosity3 => 3,
+        _ => 0,
+    };
+
+    if level > i32::from(state.verbosity) {
+        return;
+    }
+
+    callbacks.log_message(message_type, message);
+}
+
+fn progress_init(state: &State) {
+    if let Some(callbacks) = &state.callbacks {
+        callbacks.init_progress_bar();
+    }
+}
+
+fn progress_update(state: &State, current: usize, total: usize) {
+    if let Some(callbacks) = &state.callbacks {
+        callbacks.load_bar(current as _, total as _);
+    }
+}
+
+/// Read the segments of a hex or bin file. The address is only used for bin files
+fn read_segments(
+    state: &State,
+    action: Action,
+    file_path: &Path,
+    address: u32,
+) -> CubeProgrammerResult<Vec<(u32, Vec<u8>)>> {
+    let Ok(content) = std::fs::read(file_path) else {
+        log(
+            state,
+            LogMessageType::Error,
+            &format!("File {} not found", file_path.display()),
+        );
+        return Err(failed(action, api_types::ErrorCode::FileNotFound));
+    };
+
+    match extension(file_path).as_deref() {
+        #[cfg(feature = "ihex")]
+        Some("hex") => {
+            from_ihex(&content).ok_or(failed(action, api_types::ErrorCode::UnsupportedFileFormat))
+        }
+        Some("bin") => Ok(vec![(address, content)]),
+        _ => Err(failed(action, api_types::ErrorCode::UnsupportedFileFormat)),
+    }
+}
+
+fn extension(file_path: &Path) -> Option<String> {
+    file_path
+        .extension()
+        .and_then(|x| x.to_str())
+        .map(|x| x.to_ascii_lowercase())
+}
+
+/// Parse the data records of an intel hex file into segments of consecutive data
+#[cfg(feature = "ihex")]
+fn from_ihex(content: &[u8]) -> Option<Vec<(u32, Vec<u8>)>> {
+    let mut segments: Vec<(u32, Vec<u8>)> = Vec::new();
+    let mut base_address = 0u32;
+
+    for record in ihex::Reader::new(std::str::from_utf8(content).ok()?) {
+        match record.ok()? {
+            ihex::Record::Data { offset, value } => {
+                let address = base_address + offset as u32;
+
+                match segments.last_mut() {
+                    Some((start, data)) if *start + data.len() as u32 == address => {
+                        data.extend_from_slice(&value)
+                    }
+                    _ => segments.push((address, value)),
+                }
+            }
+            ihex::Record::ExtendedLinearAddress(upper) => base_address = (upper as u32) << 16,
+            ihex::Record::ExtendedSegmentAddress(segment) => base_address = (segment as u32) << 4,
+            ihex::Record::EndOfFile => break,
+            _ => {}
+        }
+    }
+
+    Some(segments)
+}
+
+/// Create an intel hex file from a memory dump
+#[cfg(feature = "ihex")]
+fn to_ihex(address: u32, data: &[u8]) -> Result<String, ihex::WriterError> {
+    const RECORD_SIZE: usize = 16;
+
+    let mut records = Vec::new();
+    let mut upper = None;
+
+    for (index, chunk) in data.chunks(RECORD_SIZE).enumerate() {
+        let chunk_address = address + (index * RECORD_SIZE) as u32;
+
+        if upper != Some(chunk_address >> 16) {
+            upper = Some(chunk_address >> 16);
+            records.push(ihex::Record::ExtendedLinearAddress(
+                (chunk_address >> 16) as u16,
+            ));
+        }
+
+        records.push(ihex::Record::Data {
+            offset: chunk_address as u16,
+            value: chunk.to_vec(),
+        });
+    }
+
+    records.push(ihex::Record::EndOfFile);
+    ihex::create_object_file_representation(&records)
+}
+
+fn parse_u32(value: &str) -> Option<u32> {
+    match value.strip_prefix("0x").or(value.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+/// Default connection parameters of a simulated probe like returned by the API
+fn debug_connect_parameters(
+    index: usize,
+    probe: &SimulatedProbe,
+    access_ports: u32,
+) -> stm32cubeprogrammer_sys::debugConnectParameters {
+    const SWD_FREQUENCIES: [u32; 7] = [24000, 8000, 3300, 1000, 200, 50, 5];
+    const JTAG_FREQUENCIES: [u32; 6] = [21333, 16000, 12000, 8000, 1777, 750];
+
+    // All fields are plain integers and arrays, so the all-zero pattern is valid
+    let mut parameters: stm32cubeprogrammer_sys::debugConnectParameters =
+        unsafe { std::mem::zeroed() };
+
+    parameters.dbgPort = api_types::probe::Protocol::Swd.into();
+    parameters.index = index as _;
+    copy_to_c_char_slice(&mut parameters.serialNumber, &probe.serial);
+    copy_to_c_char_slice(&mut parameters.firmwareVersion, &probe.firmware_version);
+    copy_to_c_char_slice(&mut parameters.targetVoltage, &probe.target_voltage);
+    copy_to_c_char_slice(&mut parameters.board, &probe.board);
+    parameters.accessPortNumber = access_ports as _;
+    parameters.isOldFirmware = probe.is_old_firmware.into();
+    parameters.connectionMode = api_types::probe::ConnectionMode::Normal.into();
+    parameters.resetMode = api_types::probe::ResetMode::Hardware.into();
+    parameters.freq.swdFreq[..SWD_FREQUENCIES.len()].copy_from_slice(&SWD_FREQUENCIES);
+    parameters.freq.swdFreqNumber = SWD_FREQUENCIES.len() as _;
+    parameters.freq.jtagFreq[..JTAG_FREQUENCIES.len()].copy_from_slice(&JTAG_FREQUENCIES);
+    parameters.freq.jtagFreqNumber = JTAG_FREQUENCIES.len() as _;
+    parameters.frequency = SWD_FREQUENCIES[0] as _;
+
+    parameters
+}
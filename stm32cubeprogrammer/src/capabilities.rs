@@ -0,0 +1,154 @@
+//! Functions of the CubeProgrammer API library which are available in the loaded library and the features they enable
+//!
+//! Older or stripped versions of the library do not export all functions. Only the functions which are needed to connect to a target are required.
+//! Methods which need a missing function return [`crate::error::CubeProgrammerError::ActionNotSupported`].
+
+use std::collections::BTreeSet;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Functions which are needed to connect to a target. The library is not loaded if one of them is missing
+pub const REQUIRED_SYMBOLS: &[&str] = &[
+    "setVerbosityLevel",
+    "setDisplayCallbacks",
+    "setLoadersPath",
+    "getStLinkList",
+    "deleteInterfaceList",
+    "connectStLink",
+    "getDeviceGeneralInf",
+    "disconnect",
+];
+
+/// High-level feature which is enabled by a group of API functions
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, strum::Display, strum::EnumIter,
+)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub enum Feature {
+    /// Downloading files, mass erase and saving memory to a file
+    Flashing,
+    /// Reading and writing memory
+    MemoryAccess,
+    /// Reading and writing core registers
+    CoreRegisters,
+    /// Reading and writing option bytes including readout protection and TrustZone regression
+    OptionBytes,
+    /// Cancelling long-running operations
+    Cancel,
+    /// Firmware upgrade service of the stm32wb
+    Fus,
+    /// Hardware secure modules
+    Hsm,
+    /// Secure secret provisioning
+    Ssp,
+    /// External flash loaders
+    ExternalLoaders,
+    /// Connections via the bootloader (UART, USB DFU, SPI, CAN and I2C)
+    BootloaderTransports,
+}
+
+impl Feature {
+    /// Functions which are needed for the feature
+    pub fn symbols(&self) -> &'static [&'static str] {
+        match self {
+            Self::Flashing => &["downloadFile", "massErase", "saveMemoryToFile", "reset"],
+            Self::MemoryAccess => &["readMemory", "writeMemory", "freeLibraryMemory"],
+            Self::CoreRegisters => &["readCortexReg", "writeCortexRegistres"],
+            Self::OptionBytes => &[
+                "initOptionBytesInterface",
+                "sendOptionBytesCmd",
+                "readUnprotect",
+                "tzenRegression",
+            ],
+            Self::Cancel => &["getCancelPointer"],
+            Self::Fus => &[
+                "startFus",
+                "firmwareUpgrade",
+                "firmwareDelete",
+                "startWirelessStack",
+                "antiRollBack",
+            ],
+            Self::Hsm => &[
+                "getHsmFirmwareID",
+                "getHsmCounter",
+                "getHsmState",
+                "getHsmVersion",
+                "getHsmType",
+                "getHsmLicense",
+            ],
+            Self::Ssp => &["programSsp"],
+            Self::ExternalLoaders => &[
+                "setExternalLoaderPath",
+                "getExternalLoaders",
+                "removeExternalLoader",
+            ],
+            Self::BootloaderTransports => &[
+                "getUsartList",
+                "connectUsartBootloader",
+                "getDfuDeviceList",
+                "connectDfuBootloader",
+                "connectSpiBootloader",
+                "connectCanBootloader",
+                "connectI2cBootloader",
+            ],
+        }
+    }
+}
+
+/// Functions which are available in the loaded API library
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// `None` if all functions are available (e.g. for the simulated backend)
+    symbols: Option<BTreeSet<String>>,
+}
+
+impl Capabilities {
+    /// All functions are available
+    pub fn all() -> Self {
+        Self { symbols: None }
+    }
+
+    /// Only the given functions are available
+    pub fn from_symbols(symbols: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            symbols: Some(symbols.into_iter().map(Into::into).collect()),
+        }
+    }
+
+    /// Available functions. `None` if all functions are available
+    pub fn symbols(&self) -> Option<&BTreeSet<String>> {
+        self.symbols.as_ref()
+    }
+
+    /// Check if the function is available
+    pub fn has_symbol(&self, symbol: &str) -> bool {
+        match &self.symbols {
+            Some(symbols) => symbols.contains(symbol),
+            None => true,
+        }
+    }
+
+    /// Check if all functions of the feature are available
+    pub fn supports(&self, feature: Feature) -> bool {
+        feature.symbols().iter().all(|x| self.has_symbol(x))
+    }
+
+    /// Features whose functions are all available
+    pub fn features(&self) -> Vec<Feature> {
+        use strum::IntoEnumIterator;
+
+        Feature::iter().filter(|x| self.supports(*x)).collect()
+    }
+
+    /// Functions of the feature which are missing
+    pub fn missing_symbols(&self, feature: Feature) -> Vec<&'static str> {
+        feature
+            .symbols()
+            .iter()
+            .copied()
+            .filter(|x| !self.has_symbol(x))
+            .collect()
+    }
+}
@@ -1,200 +1,364 @@
 use crate::{
-    api_log, api_types, display,
+    api_log, api_types,
+    backend::Backend,
+    display,
     error::{CubeProgrammerError, CubeProgrammerResult},
-    utility,
 };
 use bon::bon;
 use derive_more::Into;
-use log::{debug, error};
+use log::{debug, error, warn};
 use std::{
-    cell::RefCell,
-    collections::HashMap,
-    sync::{Arc, Mutex},
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
 };
-use stm32cubeprogrammer_sys::libloading;
 use stm32cubeprogrammer_sys::SRAM_BASE_ADDRESS;
 
-macro_rules! verify_api_struct {
-    ($api:expr, $($field:ident),*) => {{
-        $(
-            match &$api.$field {
-                Ok(_) => (), // Symbol exists, continue
-                Err(err) => return Err(CubeProgrammerError::MissingDllSymbol{message: format!(
-                    "Missing symbol '{}': {}", stringify!($field), err
-                )}),
-            }
-        )*
-        Ok(())
-    }};
-}
+/// Name of the read out protection option byte
+const OPTION_BYTE_RDP: &str = "RDP";
+/// Name of the TrustZone enable option bit
+const OPTION_BYTE_TZEN: &str = "TZEN";
 
 /// HashMap to store connected probes.
 /// The key is the serial number of the probe.
-/// The value is an option of a probe: A probe is only usable for one target connection at a time. If a connection is established, the probe is taken from the hashmap. If a connection is closed, the probe is returned to the hashmap.
-type ProbeRegistry = HashMap<crate::probe::Serial, Option<crate::probe::Probe>>;
+/// Each access port (core) of a probe is usable for one exclusive target connection or any number of shared target connections at a time. A probe is available if none of its access ports is in use.
+type ProbeRegistry = HashMap<crate::probe::Serial, RegisteredProbe>;
+
+/// Entry of the [`ProbeRegistry`]
+#[derive(Debug)]
+struct RegisteredProbe {
+    /// Probe with the default connection parameters of the last scan
+    probe: crate::probe::Probe,
+    /// Access ports which are used by a connection
+    access_ports_in_use: BTreeMap<u32, AccessPortUsage>,
+}
+
+impl RegisteredProbe {
+    fn is_available(&self) -> bool {
+        self.access_ports_in_use.is_empty()
+    }
+}
+
+/// Usage of an access port in the [`ProbeRegistry`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum AccessPortUsage {
+    /// Used by one connection
+    Exclusive,
+    /// Used by the given number of shared connections
+    Shared(usize),
+}
 
 /// Central struct to interact with the underlying CubeProgrammer API library. Factory for connections.
 /// Multiple connections are possible at the same time, if multiple probes are connected.
+///
+/// The CubeProgrammer is a cheap handle which can be cloned and shared between threads. All clones use the same API instance.
+/// The access to the API is serialized, because the API library only has one global connection (see [`ConnectedProgrammer`]).
+#[derive(Clone)]
 pub struct CubeProgrammer {
-    /// API
-    api: stm32cubeprogrammer_sys::CubeProgrammer_API,
+    shared: Arc<Shared>,
+}
+
+/// State which is shared by all handles of a [`CubeProgrammer`] and its connections
+struct Shared {
+    /// Backend which executes the API calls
+    backend: Box<dyn Backend>,
+
+    /// State of the API. The mutex serializes the access to the backend
+    api_state: Mutex<ApiState>,
 
     /// Registry of probes
-    probe_registry: RefCell<ProbeRegistry>,
+    probe_registry: Mutex<ProbeRegistry>,
+
+    /// Handler and subscribers for the events of the operations
+    event_sinks: Arc<display::EventSinks>,
+
+    /// Version of the loaded API. `None` if the version is unknown
+    api_version: Option<api_types::CubeProgrammerVersion>,
+
+    /// Functions which are available in the API library
+    capabilities: crate::capabilities::Capabilities,
+}
+
+/// State of the API library, which only has one global connection
+#[derive(Default)]
+struct ApiState {
+    /// Serial number of the probe and access port of the current connection
+    active_connection: Option<(String, u32)>,
+}
+
+/// Exclusive access to the backend. The lock is released when the guard is dropped
+struct ApiGuard<'a> {
+    state: MutexGuard<'a, ApiState>,
+    backend: &'a dyn Backend,
+}
+
+impl ApiGuard<'_> {
+    /// Call the backend. The error and warning messages which the API logs during the call are attached to a returned error
+    fn call<T>(
+        &self,
+        f: impl FnOnce(&dyn Backend) -> CubeProgrammerResult<T>,
+    ) -> CubeProgrammerResult<T> {
+        api_log::clear_captured_messages();
+        f(self.backend).map_err(CubeProgrammerError::with_api_messages)
+    }
+}
+
+/// Identity of a connection in the [`ApiState`]: Serial number of the probe and access port
+fn connection_key(connection: &crate::probe::Probe) -> (String, u32) {
+    (
+        connection.serial_number().to_string(),
+        connection.access_port(),
+    )
 }
 
 /// Programmer connected to the target which is created via calling [`CubeProgrammer::connect_to_target`] on the CubeProgrammer
+///
+/// The connection owns a handle to the CubeProgrammer and can be moved to other threads.
+/// If another connection used the API in the meantime, the connection is re-established via hot-plug (without reset) before the next operation.
+/// If the connection is lost, it is re-established according to the [`crate::probe::ReconnectPolicy`] of the connection parameters before the next operation.
 #[derive(Debug)]
-pub struct ConnectedProgrammer<'a> {
-    /// Reference to the CubeProgrammer for api access and reinsertion of the probe into the probe registry
-    programmer: &'a CubeProgrammer,
+pub struct ConnectedProgrammer {
+    /// Handle to the CubeProgrammer for api access and reinsertion of the probe into the probe registry
+    programmer: CubeProgrammer,
     /// Connected probe. The probe is taken from the probe registry and reinserted after the connection is closed
     probe: crate::probe::Probe,
+    /// Parameters which were used to establish the connection
+    connection: crate::probe::Probe,
     /// General information about the connected target which is retrieved after the connection is established
     general_information: api_types::GeneralInformation,
+    /// Retries of a lost connection. `None` if a lost connection is reported as error
+    reconnect_policy: Option<crate::probe::ReconnectPolicy>,
+    /// Token which cancels the long-running operations of this connection
+    cancellation_token: CancellationToken,
 }
 
 /// Programmer connected to the target FUS (firmware update service) which is created via calling [`CubeProgrammer::connect_to_target_fus`]
 #[derive(Debug)]
-pub struct ConnectedFusProgrammer<'a> {
-    programmer: ConnectedProgrammer<'a>,
+pub struct ConnectedFusProgrammer {
+    programmer: ConnectedProgrammer,
     fus_info: crate::fus::Information,
 }
 
+/// Token to cancel long-running operations (downloads, mass erase, memory read/write and wireless stack updates) from another thread or a Ctrl-C handler
+/// Each connection has its own token (see [`ConnectedProgrammer::cancellation_token`]). Clones of a token share the same state, so one token can be used for several connections via [`ConnectedProgrammer::set_cancellation_token`]
+///
+/// Cancelling aborts the running operation of the connections which use the token. If no operation is running, the next cancellable operation returns [`CubeProgrammerError::Cancelled`] without being started.
+/// The token is reset after the cancelled operation, so the connection stays usable
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    state: Arc<CancellationState>,
+}
+
+#[derive(Default)]
+struct CancellationState {
+    /// Set by [`CancellationToken::cancel`] until the next cancellable operation has finished
+    cancelled: AtomicBool,
+    /// API of the running operation which uses the token. The lock orders cancelling with the start and the end of an operation
+    running: Mutex<Option<Arc<Shared>>>,
+}
+
+/// Cancellable operation which is running with a [`CancellationToken`]. Resets the token and the cancel flag of the API when dropped
+struct RunningOperation<'a> {
+    token: &'a CancellationToken,
+}
+
+/// Handle to a hardware secure module (HSM) plugged into a smart card reader, which is created via calling [`CubeProgrammer::hsm`]
+#[derive(Debug)]
+pub struct Hsm {
+    programmer: CubeProgrammer,
+    slot_id: u32,
+}
+
 #[bon]
 impl CubeProgrammer {
     /// Create new instance
-    /// - Load the CubeProgrammer API library (sys crate)
+    /// - Load the CubeProgrammer API library (sys crate) via the [`crate::backend::FfiBackend`]
     /// - Set the verbosity level
-    /// - Set the display callback handler
+    /// - Set the event handler
     /// - Set the loader path
+    /// - Check the version of the installation against [`api_types::CubeProgrammerVersion::SUPPORTED`] according to the version policy (default: [`api_types::VersionPolicy::Warn`])
     #[builder]
     pub fn new(
         cube_programmer_dir: &impl AsRef<std::path::Path>,
         log_verbosity: Option<api_log::Verbosity>,
-        display_callback: Option<Arc<Mutex<dyn crate::DisplayCallback>>>,
+        event_handler: Option<Arc<dyn crate::EventHandler>>,
+        version_policy: Option<api_types::VersionPolicy>,
     ) -> Result<Self, CubeProgrammerError> {
-        use stm32cubeprogrammer_sys::{PATH_API_LIBRARY_RELATIVE, PATH_LOADER_DIR_RELATIVE};
+        let backend = crate::backend::FfiBackend::load(cube_programmer_dir)?;
+
+        Self::with_backend()
+            .backend(Box::new(backend))
+            .maybe_log_verbosity(log_verbosity)
+            .maybe_event_handler(event_handler)
+            .maybe_version_policy(version_policy)
+            .build()
+    }
 
-        let api_path = cube_programmer_dir
-            .as_ref()
-            .join(PATH_API_LIBRARY_RELATIVE)
-            .canonicalize()
-            .map_err(CubeProgrammerError::FileIo)?;
+    /// Create new instance which uses a custom [`Backend`] (e.g. the [`crate::backend::SimulatedBackend`])
+    /// - Set the verbosity level
+    /// - Set the event handler
+    /// - Check the version of the backend against [`api_types::CubeProgrammerVersion::SUPPORTED`] according to the version policy (default: [`api_types::VersionPolicy::Warn`])
+    #[builder(start_fn = with_backend, finish_fn = build)]
+    pub fn from_backend(
+        backend: Box<dyn Backend>,
+        log_verbosity: Option<api_log::Verbosity>,
+        event_handler: Option<Arc<dyn crate::EventHandler>>,
+        version_policy: Option<api_types::VersionPolicy>,
+    ) -> Result<Self, CubeProgrammerError> {
+        let api_version = backend.api_version();
+        let capabilities = backend.capabilities();
+        let version_policy = version_policy.unwrap_or_default();
+
+        match api_version {
+            Some(version) if !version.is_supported() => {
+                if version_policy == api_types::VersionPolicy::Error {
+                    return Err(CubeProgrammerError::UnsupportedVersion { version });
+                }
 
-        let loader_path = cube_programmer_dir
-            .as_ref()
-            .join(PATH_LOADER_DIR_RELATIVE)
-            .canonicalize()
-            .map_err(CubeProgrammerError::FileIo)?;
+                if version_policy == api_types::VersionPolicy::Warn {
+                    warn!(
+                        "STM32CubeProgrammer version {} is not supported. Supported versions: {} to {} (exclusive)",
+                        version,
+                        api_types::CubeProgrammerVersion::SUPPORTED.start,
+                        api_types::CubeProgrammerVersion::SUPPORTED.end
+                    );
+                }
+            }
+            Some(version) => debug!("STM32CubeProgrammer version: {}", version),
+            None => debug!("STM32CubeProgrammer version is unknown"),
+        }
 
-        debug!("API path: {:?}", api_path);
-        debug!("Loader path: {:?}", loader_path);
+        let verbosity = log_verbosity.unwrap_or({
+            debug!("Use default verbosity level");
+            api_log::Verbosity::Level3
+        });
 
-        let library = Self::load_library(&api_path).map_err(CubeProgrammerError::LibLoading)?;
+        debug!("Set verbosity level: {}", verbosity);
+        backend.set_verbosity_level(verbosity);
 
-        let api = unsafe {
-            stm32cubeprogrammer_sys::CubeProgrammer_API::from_library(library)
-                .map_err(CubeProgrammerError::LibLoading)?
-        };
+        backend.set_display_callbacks(Arc::new(api_log::ApiLog));
 
-        // Verify if all API functions are available before proceeding
-        verify_api_struct!(
-            api,
-            setVerbosityLevel,
-            setDisplayCallbacks,
-            setLoadersPath,
-            getStLinkList,
-            deleteInterfaceList,
-            connectStLink,
-            getDeviceGeneralInf,
-            disconnect,
-            startFus,
-            reset,
-            downloadFile,
-            massErase,
-            saveMemoryToFile,
-            sendOptionBytesCmd,
-            readUnprotect,
-            checkDeviceConnection,
-            readMemory,
-            freeLibraryMemory,
-            startWirelessStack,
-            writeCortexRegistres,
-            readCortexReg,
-            firmwareDelete,
-            firmwareUpgrade
-        )?;
+        Ok(Self {
+            shared: Arc::new(Shared {
+                backend,
+                api_state: Mutex::new(ApiState::default()),
+                probe_registry: Mutex::new(HashMap::new()),
+                event_sinks: Arc::new(display::EventSinks::new(event_handler)),
+                api_version,
+                capabilities,
+            }),
+        })
+    }
 
-        if let Some(display_callback) = display_callback {
-            debug!("Set display callback handler");
-            display::set_display_callback_handler(display_callback);
+    /// Version of the loaded STM32CubeProgrammer API. `None` if the version cannot be detected
+    pub fn api_version(&self) -> Option<api_types::CubeProgrammerVersion> {
+        self.shared.api_version
+    }
+
+    /// Functions of the API library which are available and the features they enable
+    /// Methods which need a missing function return [`CubeProgrammerError::ActionNotSupported`]
+    pub fn capabilities(&self) -> &crate::capabilities::Capabilities {
+        &self.shared.capabilities
+    }
+
+    /// Lock the access to the API
+    /// A panic during an API call does not prevent further calls, so a poisoned lock is recovered
+    fn api(&self) -> ApiGuard<'_> {
+        ApiGuard {
+            state: self
+                .shared
+                .api_state
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+            backend: self.shared.backend.as_ref(),
         }
+    }
 
-        unsafe {
-            {
-                let verbosity = log_verbosity.unwrap_or({
-                    debug!("Use default verbosity level");
-                    api_log::Verbosity::Level3
-                });
+    /// Set the handler for the progress and log events of all operations of this CubeProgrammer and its connections
+    /// A previously set handler is replaced
+    pub fn set_event_handler(&self, event_handler: Arc<dyn crate::EventHandler>) {
+        self.shared.event_sinks.replace_handler(Some(event_handler));
+    }
 
-                debug!("Set verbosity level: {}", verbosity);
+    /// Remove the event handler and return it
+    pub fn remove_event_handler(&self) -> Option<Arc<dyn crate::EventHandler>> {
+        self.shared.event_sinks.replace_handler(None)
+    }
 
-                api.setVerbosityLevel(verbosity.into());
-            }
+    /// Subscribe to the events of all operations of this CubeProgrammer and its connections
+    /// The receiver gets all events from the time of the subscription on and can be used as an iterator (e.g. on another thread). Subscribers are independent of the event handler
+    pub fn subscribe(&self) -> std::sync::mpsc::Receiver<crate::ProgrammerEvent> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.shared
+            .event_sinks
+            .subscribe(display::Subscriber::Channel(sender));
+        receiver
+    }
 
-            let display_callbacks = stm32cubeprogrammer_sys::displayCallBacks {
-                initProgressBar: Some(api_log::display_callback_init_progressbar),
-                logMessage: Some(api_log::display_callback_log_message),
-                loadBar: Some(api_log::display_callback_load_bar),
-            };
+    /// Subscribe to the events via an async stream (see [`CubeProgrammer::subscribe`])
+    #[cfg(feature = "async")]
+    pub(crate) fn subscribe_stream(
+        &self,
+    ) -> futures_channel::mpsc::UnboundedReceiver<crate::ProgrammerEvent> {
+        let (sender, receiver) = futures_channel::mpsc::unbounded();
+        self.shared
+            .event_sinks
+            .subscribe(display::Subscriber::Stream(sender));
+        receiver
+    }
 
-            api.setDisplayCallbacks(display_callbacks);
-            api.setLoadersPath(utility::path_to_cstring(loader_path)?.as_ptr());
+    /// Execute an operation: The operation is reported as started and finished, the events of the API are tagged with the probe and the action
+    fn operation<T>(
+        &self,
+        probe: Option<crate::probe::Serial>,
+        action: crate::error::Action,
+        f: impl FnOnce() -> CubeProgrammerResult<T>,
+    ) -> CubeProgrammerResult<T> {
+        display::operation(&self.shared.event_sinks, probe, action, f)
+    }
+
+    fn probe_registry(&self) -> MutexGuard<'_, ProbeRegistry> {
+        self.shared
+            .probe_registry
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Scan for connected probes via the API
+    /// The scan disturbs the current connection of the API, so it is re-established by its [`ConnectedProgrammer`] before the next call (see [`ConnectedProgrammer::api`])
+    fn list_probes(&self) -> CubeProgrammerResult<Vec<crate::probe::Probe>> {
+        let mut api = self.api();
+
+        if let Some((serial, access_port)) = api.state.active_connection.take() {
+            debug!(
+                "Probe scan during the connection via probe with serial number: {} (access port {}). The connection is re-established before its next call",
+                serial, access_port
+            );
         }
 
-        Ok(Self {
-            api,
-            probe_registry: RefCell::new(HashMap::new()),
-        })
+        api.call(|api| api.list_probes())
     }
 
     /// Scan for connected probes and sync the probe registry with the scan result
     /// If a probe is already in use, the related entry is not changed
     fn scan_for_probes(&self) -> CubeProgrammerResult<()> {
-        let mut debug_parameters =
-            std::ptr::null_mut::<stm32cubeprogrammer_sys::debugConnectParameters>();
-        let return_value = unsafe { self.api.getStLinkList(&mut debug_parameters, 0) };
-
-        if return_value < 0 || debug_parameters.is_null() {
-            return Err(CubeProgrammerError::ActionOutputUnexpected {
-                action: crate::error::Action::ListConnectedProbes,
-                unexpected_output: crate::error::UnexpectedOutput::Null,
-            });
-        }
-
-        let slice = unsafe {
-            std::slice::from_raw_parts(
-                debug_parameters as *mut crate::probe::Probe,
-                return_value as _,
-            )
-        };
+        let probes = self.list_probes()?;
 
-        let mut connected_probes = self.probe_registry.borrow_mut();
+        let mut connected_probes = self.probe_registry();
 
-        // Delete all entries where the value is not None -> There is no active connection
-        connected_probes.retain(|_, value| value.is_none());
+        // Delete all entries without an active connection
+        connected_probes.retain(|_, value| !value.is_available());
 
-        for probe in slice {
+        for probe in probes {
             // Only insert if the key is not already present
             connected_probes
                 .entry(probe.serial_number().to_string().into())
-                .or_insert_with(|| Some(probe.clone()));
-        }
-
-        // Free the memory allocated by the API
-        unsafe {
-            self.api.deleteInterfaceList();
+                .or_insert_with(|| RegisteredProbe {
+                    probe,
+                    access_ports_in_use: BTreeMap::new(),
+                });
         }
 
         Ok(())
@@ -202,98 +366,371 @@ impl CubeProgrammer {
 
     /// List available probes. Scans for connected probes internally and returns the serial numbers of the connected probes which are not currently in use
     pub fn list_available_probes(&self) -> CubeProgrammerResult<Vec<crate::probe::Serial>> {
-        self.scan_for_probes()?;
+        self.operation(None, crate::error::Action::ListConnectedProbes, || {
+            self.scan_for_probes()?;
 
-        let connected_probes = self.probe_registry.borrow();
+            let connected_probes = self.probe_registry();
 
-        Ok(connected_probes
-            .values()
-            .filter_map(|probe| {
-                probe
-                    .as_ref()
-                    .map(|probe| probe.serial_number().to_string().into())
-            })
-            .collect())
+            Ok(connected_probes
+                .values()
+                .filter(|x| x.is_available())
+                .map(|x| x.probe.serial_number().to_string().into())
+                .collect())
+        })
+    }
+
+    /// List available probes with detailed information (e.g. the board, the firmware version and the target voltage)
+    /// Scans for connected probes internally like [`CubeProgrammer::list_available_probes`]. The probes are sorted by serial number
+    pub fn list_probes_detailed(&self) -> CubeProgrammerResult<Vec<crate::probe::ProbeInfo>> {
+        self.operation(None, crate::error::Action::ListConnectedProbes, || {
+            self.scan_for_probes()?;
+
+            let mut probes = self
+                .probe_registry()
+                .values()
+                .filter(|x| x.is_available())
+                .map(|x| crate::probe::ProbeInfo::from(&x.probe))
+                .collect::<Vec<_>>();
+
+            probes.sort_by(|a, b| a.serial.cmp(&b.serial));
+            Ok(probes)
+        })
+    }
+
+    /// Take an access port of a probe from the probe registry, so that it cannot be used by another connection
+    /// A shared access port can be taken by further shared connections
+    /// Returns the probe with the default connection parameters of the last scan
+    fn take_probe(
+        &self,
+        probe_serial_number: &crate::probe::Serial,
+        access_port: Option<u32>,
+        shared: bool,
+    ) -> CubeProgrammerResult<crate::probe::Probe> {
+        let mut connected_probes = self.probe_registry();
+
+        let registered = connected_probes
+            .get_mut(probe_serial_number)
+            .ok_or_else(|| CubeProgrammerError::Parameter {
+                action: crate::error::Action::Connect,
+                message: format!("Probe with serial number {} not found", probe_serial_number),
+            })?;
+
+        let access_port_number = registered.probe.0.accessPortNumber;
+        let access_port = match access_port {
+            Some(access_port) if i64::from(access_port) >= i64::from(access_port_number) => {
+                return Err(CubeProgrammerError::Parameter {
+                    action: crate::error::Action::Connect,
+                    message: format!(
+                        "Access port {} not available. Probe with serial number {} reports {} access ports",
+                        access_port, probe_serial_number, access_port_number
+                    ),
+                });
+            }
+            Some(access_port) => access_port,
+            None => registered.probe.access_port(),
+        };
+
+        let usage = match (registered.access_ports_in_use.get(&access_port), shared) {
+            (None, false) => AccessPortUsage::Exclusive,
+            (None, true) => AccessPortUsage::Shared(1),
+            (Some(AccessPortUsage::Shared(count)), true) => AccessPortUsage::Shared(count + 1),
+            (Some(_), _) => {
+                return Err(CubeProgrammerError::Parameter {
+                    action: crate::error::Action::Connect,
+                    message: format!(
+                        "Access port {} of probe with serial number {} already in use",
+                        access_port, probe_serial_number
+                    ),
+                });
+            }
+        };
+
+        registered.access_ports_in_use.insert(access_port, usage);
+
+        Ok(registered.probe.clone())
     }
 
-    /// Insert a probe into the probe registry. Is called in the drop implementation of [`ConnectedProgrammer``]
-    fn insert_probe(&self, probe: &crate::probe::Probe) {
-        let mut connected_probes = self.probe_registry.borrow_mut();
-        connected_probes.insert(probe.serial_number().to_owned().into(), Some(probe.clone()));
+    /// Release the access port of a probe in the probe registry. Is called in the drop implementation of [`ConnectedProgrammer`]
+    fn release_probe(&self, probe: &crate::probe::Probe) {
+        let mut connected_probes = self.probe_registry();
+        let serial: crate::probe::Serial = probe.serial_number().to_owned().into();
+
+        match connected_probes.get_mut(&serial) {
+            Some(registered) => {
+                let access_port = probe.access_port();
+
+                match registered.access_ports_in_use.get(&access_port) {
+                    Some(AccessPortUsage::Shared(count)) if *count > 1 => {
+                        registered
+                            .access_ports_in_use
+                            .insert(access_port, AccessPortUsage::Shared(count - 1));
+                    }
+                    _ => {
+                        registered.access_ports_in_use.remove(&access_port);
+                    }
+                }
+            }
+            None => {
+                connected_probes.insert(
+                    serial,
+                    RegisteredProbe {
+                        probe: probe.clone(),
+                        access_ports_in_use: BTreeMap::new(),
+                    },
+                );
+            }
+        }
     }
 
     /// Connect to a target via a given probe
+    /// Each core of a multi-core device is reached via its own access port ([`crate::probe::ConnectionParameters::access_port`]) and can be connected in parallel
+    /// Shared connections ([`crate::probe::ConnectionParameters::shared`]) need a running ST-LINK server and can use the same access port in parallel
     pub fn connect_to_target(
         &self,
         probe_serial_number: &crate::probe::Serial,
         protocol: &crate::probe::Protocol,
         connection_parameters: &crate::probe::ConnectionParameters,
     ) -> CubeProgrammerResult<ConnectedProgrammer> {
-        let mut connected_probes = self.probe_registry.borrow_mut();
-
-        if let Some(probe) = connected_probes.get_mut(probe_serial_number) {
-            if let Some(inner) = probe.take() {
-                // Try to connect to the target with the probe
-                match api_types::ReturnCode::<0>::from(unsafe {
-                    self.api.connectStLink(*crate::probe::Probe::new(
-                        &inner,
-                        protocol,
-                        connection_parameters,
-                    ))
-                })
-                .check(crate::error::Action::Connect)
-                {
-                    Ok(_) => {
-                        // Try to get the general device information
-                        let general_information = unsafe { self.api.getDeviceGeneralInf() };
-                        if general_information.is_null() {
-                            // Reinsert the probe into the connected_probes HashMap
-                            *probe = Some(inner);
-
-                            unsafe { self.api.disconnect() };
-
-                            return Err(CubeProgrammerError::ActionOutputUnexpected {
-                                action: crate::error::Action::ReadTargetInfo,
-                                unexpected_output: crate::error::UnexpectedOutput::Null,
-                            });
-                        }
-
-                        // We could connect and get the general information
-                        let general_information =
-                            api_types::GeneralInformation::from(unsafe { *general_information });
-
-                        Ok(ConnectedProgrammer {
-                            programmer: self,
-                            probe: inner,
-                            general_information,
-                        })
-                    }
+        self.operation(
+            Some(probe_serial_number.clone()),
+            crate::error::Action::Connect,
+            || {
+                // Take the probe from the registry, so that it cannot be used by another connection
+                let probe = self.take_probe(
+                    probe_serial_number,
+                    connection_parameters.access_port,
+                    connection_parameters.shared,
+                )?;
+
+                let mut connection =
+                    crate::probe::Probe::new(&probe, protocol, connection_parameters);
+
+                match self
+                    .check_shared_mode(probe_serial_number, connection_parameters)
+                    .and_then(|_| {
+                        self.check_target_voltage(probe_serial_number, connection_parameters)
+                    })
+                    .and_then(|_| {
+                        self.connect_probe_with_retries(&mut connection, connection_parameters)
+                    }) {
+                    Ok(general_information) => Ok(ConnectedProgrammer {
+                        programmer: self.clone(),
+                        probe,
+                        connection,
+                        general_information,
+                        reconnect_policy: connection_parameters.reconnect.clone(),
+                        cancellation_token: CancellationToken::new(),
+                    }),
                     Err(e) => {
                         error!(
                             "Cannot connect to target via probe with serial number: {}",
                             probe_serial_number
                         );
 
-                        // Reinsert the probe into the connected_probes HashMap
-                        *probe = Some(inner);
+                        // Release the access port in the probe registry
+                        self.release_probe(&connection);
 
                         Err(e)
                     }
                 }
-            } else {
-                Err(CubeProgrammerError::Parameter {
-                    action: crate::error::Action::Connect,
-                    message: format!(
-                        "Probe with serial number {} already in use",
-                        probe_serial_number
-                    ),
+            },
+        )
+    }
+
+    /// Scan for probes and read the target voltage of the given probe in millivolts
+    fn scan_target_voltage(
+        &self,
+        probe_serial_number: &crate::probe::Serial,
+    ) -> CubeProgrammerResult<Option<u32>> {
+        let probes = self.list_probes()?;
+
+        probes
+            .into_iter()
+            .find(|probe| probe.serial_number() == probe_serial_number.as_str())
+            .map(|probe| probe.target_voltage_mv())
+            .ok_or_else(|| CubeProgrammerError::Parameter {
+                action: crate::error::Action::ReadTargetVoltage,
+                message: format!("Probe with serial number {} not found", probe_serial_number),
+            })
+    }
+
+    /// Refuse a shared connection if the ST-LINK server is not running
+    fn check_shared_mode(
+        &self,
+        probe_serial_number: &crate::probe::Serial,
+        connection_parameters: &crate::probe::ConnectionParameters,
+    ) -> CubeProgrammerResult<()> {
+        if !connection_parameters.shared || self.shared.backend.stlink_server_running() {
+            return Ok(());
+        }
+
+        Err(CubeProgrammerError::StlinkServerNotRunning {
+            probe: probe_serial_number.clone(),
+        })
+    }
+
+    /// Refuse the connection if the target voltage is below the minimum of the connection parameters
+    fn check_target_voltage(
+        &self,
+        probe_serial_number: &crate::probe::Serial,
+        connection_parameters: &crate::probe::ConnectionParameters,
+    ) -> CubeProgrammerResult<()> {
+        let Some(min_voltage_mv) = connection_parameters.min_target_voltage_mv else {
+            return Ok(());
+        };
+
+        match self.scan_target_voltage(probe_serial_number)? {
+            Some(voltage_mv) if voltage_mv < min_voltage_mv => {
+                Err(CubeProgrammerError::TargetVoltageTooLow {
+                    probe: probe_serial_number.clone(),
+                    voltage_mv,
+                    min_voltage_mv,
                 })
             }
+            Some(voltage_mv) => {
+                debug!("Target voltage: {} mV", voltage_mv);
+                Ok(())
+            }
+            None => {
+                warn!(
+                    "Probe with serial number {} reports no valid target voltage",
+                    probe_serial_number
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Connect to the target and retry a failed connection according to the reconnect policy of the connection parameters
+    /// Each attempt starts again at the frequency of the connection parameters
+    fn connect_probe_with_retries(
+        &self,
+        connection: &mut crate::probe::Probe,
+        connection_parameters: &crate::probe::ConnectionParameters,
+    ) -> CubeProgrammerResult<api_types::GeneralInformation> {
+        let initial = connection.clone();
+        let result =
+            self.connect_probe_with_fallback(connection, connection_parameters.frequency_fallback);
+
+        match (result, &connection_parameters.reconnect) {
+            (Err(error), Some(policy)) => {
+                self.retry_connection(policy, initial.connection_mode(), error, |mode| {
+                    *connection = initial.clone();
+                    connection.set_connection_mode(mode);
+                    self.connect_probe_with_fallback(
+                        connection,
+                        connection_parameters.frequency_fallback,
+                    )
+                })
+            }
+            (result, _) => result,
+        }
+    }
+
+    /// Retry a failed or lost connection with the backoff and the escalation of the connection modes of the policy
+    /// Only connection failures are retried. The error of the last attempt is returned if all attempts fail
+    /// Must be called without holding the API lock, so other connections are not blocked during the backoff
+    fn retry_connection<T>(
+        &self,
+        policy: &crate::probe::ReconnectPolicy,
+        connection_mode: crate::probe::ConnectionMode,
+        mut error: CubeProgrammerError,
+        mut connect: impl FnMut(crate::probe::ConnectionMode) -> CubeProgrammerResult<T>,
+    ) -> CubeProgrammerResult<T> {
+        for attempt in 1..=policy.max_attempts {
+            if !error.is_connection_failure() {
+                break;
+            }
+
+            let mode = policy.connection_mode(attempt).unwrap_or(connection_mode);
+            let delay = policy.delay(attempt);
+
+            warn!(
+                "Connection failed: {}. Reconnect attempt {} of {} with connection mode {} in {:?}",
+                error, attempt, policy.max_attempts, mode, delay
+            );
+            display::reconnect_attempt(attempt, policy.max_attempts, mode, delay, &error);
+            std::thread::sleep(delay);
+
+            match connect(mode) {
+                Ok(value) => {
+                    debug!("Reconnected after {} attempts", attempt);
+                    display::reconnected(attempt);
+                    return Ok(value);
+                }
+                Err(e) => error = e,
+            }
+        }
+
+        Err(error)
+    }
+
+    /// Connect to the target and step down through the lower frequencies of the probe while the connection fails, if the fallback is enabled
+    /// The frequency of the successful connection is stored in the connection
+    fn connect_probe_with_fallback(
+        &self,
+        connection: &mut crate::probe::Probe,
+        frequency_fallback: bool,
+    ) -> CubeProgrammerResult<api_types::GeneralInformation> {
+        let lower_frequencies = if frequency_fallback {
+            connection.lower_frequencies()
         } else {
-            Err(CubeProgrammerError::Parameter {
-                action: crate::error::Action::Connect,
-                message: format!("Probe with serial number {} not found", probe_serial_number),
-            })
+            Vec::new()
+        };
+
+        let mut result = self.connect_probe(connection);
+
+        for frequency in lower_frequencies {
+            match &result {
+                Err(e) if e.is_connection_failure() => {
+                    warn!(
+                        "Connection at {} kHz failed: {}. Retry at {} kHz",
+                        connection.frequency_khz(),
+                        e,
+                        frequency
+                    );
+                }
+                _ => break,
+            }
+
+            connection.set_frequency_khz(frequency);
+            result = self.connect_probe(connection);
+        }
+
+        if result.is_ok() {
+            debug!("Connected at {} kHz", connection.frequency_khz());
+        }
+
+        result
+    }
+
+    /// Connect to the target and read the general device information
+    fn connect_probe(
+        &self,
+        connection: &crate::probe::Probe,
+    ) -> CubeProgrammerResult<api_types::GeneralInformation> {
+        let mut api = self.api();
+
+        debug!("Connect via {}", connection);
+
+        // The previous connection of the API is replaced in any case
+        api.state.active_connection = None;
+        api.call(|api| api.connect(connection))?;
+
+        // Try to get the general device information
+        match api.call(|api| api.general_information()) {
+            Ok(general_information) => {
+                display::record(
+                    "device_id",
+                    format_args!("{:#05x}", general_information.device_id),
+                );
+                api.state.active_connection = Some(connection_key(connection));
+                Ok(general_information)
+            }
+            Err(e) => {
+                api.backend.disconnect();
+                Err(e)
+            }
         }
     }
 
@@ -309,101 +746,154 @@ impl CubeProgrammer {
         probe_serial_number: &crate::probe::Serial,
         protocol: &crate::probe::Protocol,
     ) -> CubeProgrammerResult<ConnectedFusProgrammer> {
-        // Connect with hardware reset an normal mode
-        let connected = self.connect_to_target(
-            probe_serial_number,
-            protocol,
-            &crate::probe::ConnectionParameters {
-                frequency: crate::probe::Frequency::Highest,
-                reset_mode: crate::probe::ResetMode::Hardware,
-                connection_mode: crate::probe::ConnectionMode::Normal,
+        self.operation(
+            Some(probe_serial_number.clone()),
+            crate::error::Action::StartFus,
+            || {
+                // Connect with hardware reset an normal mode
+                let connected = self.connect_to_target(
+                    probe_serial_number,
+                    protocol,
+                    &crate::probe::ConnectionParameters {
+                        frequency: crate::probe::Frequency::Highest,
+                        reset_mode: crate::probe::ResetMode::Hardware,
+                        connection_mode: crate::probe::ConnectionMode::Normal,
+                        ..Default::default()
+                    },
+                )?;
+
+                connected.check_fus_support()?;
+
+                // Start the FUS
+                connected.api()?.call(|api| api.start_fus())?;
+
+                // Disconnect
+                connected.disconnect();
+
+                // Reconnect with hot plug
+                let connected = self.connect_to_target(
+                    probe_serial_number,
+                    protocol,
+                    &crate::probe::ConnectionParameters {
+                        frequency: crate::probe::Frequency::Highest,
+                        reset_mode: crate::probe::ResetMode::Hardware,
+                        connection_mode: crate::probe::ConnectionMode::HotPlug,
+                        ..Default::default()
+                    },
+                )?;
+
+                // Read the FUS information
+                let fus_info = connected.read_fus_info()?;
+
+                Ok(ConnectedFusProgrammer {
+                    programmer: connected,
+                    fus_info,
+                })
             },
-        )?;
+        )
+    }
 
-        connected.check_fus_support()?;
+    /// Get a handle to the hardware secure module (HSM) in the given slot
+    pub fn hsm(&self, slot_id: u32) -> Hsm {
+        Hsm {
+            programmer: self.clone(),
+            slot_id,
+        }
+    }
+}
 
-        // Start the FUS
-        api_types::ReturnCode::<1>::from(unsafe { connected.api().startFus() })
-            .check(crate::error::Action::StartFus)?;
+impl std::fmt::Debug for CubeProgrammer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CubeProgrammerApi").finish_non_exhaustive()
+    }
+}
 
-        // Disconnect
-        connected.disconnect();
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        // Reconnect with hot plug
-        let connected = self.connect_to_target(
-            probe_serial_number,
-            protocol,
-            &crate::probe::ConnectionParameters {
-                frequency: crate::probe::Frequency::Highest,
-                reset_mode: crate::probe::ResetMode::Hardware,
-                connection_mode: crate::probe::ConnectionMode::HotPlug,
-            },
-        )?;
+    /// Cancel the running operation or, if no operation is running, the next cancellable operation
+    /// Does not wait for the running operation to return
+    pub fn cancel(&self) {
+        debug!("Cancel operations");
+        let running = self.running();
 
-        // Read the FUS information
-        let fus_info = connected.read_fus_info()?;
+        self.state.cancelled.store(true, Ordering::SeqCst);
 
-        Ok(ConnectedFusProgrammer {
-            programmer: connected,
-            fus_info,
-        })
+        if let Some(shared) = running.as_ref() {
+            shared.backend.set_cancel_flag(true);
+        }
     }
 
-    /// Load the dynamic library with libloading
-    fn load_library(
-        api_library_path: impl AsRef<std::ffi::OsStr>,
-    ) -> Result<libloading::Library, libloading::Error> {
-        #[cfg(windows)]
-        unsafe fn load_inner(
-            path: impl AsRef<std::ffi::OsStr>,
-        ) -> Result<libloading::Library, libloading::Error> {
-            let library: libloading::Library = unsafe {
-                libloading::os::windows::Library::load_with_flags(
-                    path,
-                    libloading::os::windows::LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR
-                        | libloading::os::windows::LOAD_LIBRARY_SEARCH_SYSTEM32
-                        | libloading::os::windows::LOAD_LIBRARY_SEARCH_DEFAULT_DIRS,
-                )?
-                .into()
-            };
+    /// Check if the token is cancelled and the cancelled operation has not finished yet
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Withdraw a cancel which has not reached an operation yet
+    pub fn reset(&self) {
+        self.state.cancelled.store(false, Ordering::SeqCst);
+    }
+
+    fn running(&self) -> MutexGuard<'_, Option<Arc<Shared>>> {
+        self.state
+            .running
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Register an operation on the given API as running. `None` if the token was cancelled before the start, which consumes the cancel
+    fn start(&self, shared: &Arc<Shared>) -> Option<RunningOperation<'_>> {
+        let mut running = self.running();
 
-            Ok(library)
+        if self.state.cancelled.swap(false, Ordering::SeqCst) {
+            return None;
         }
 
-        #[cfg(unix)]
-        unsafe fn load_inner(
-            path: impl AsRef<std::ffi::OsStr>,
-        ) -> Result<libloading::Library, libloading::Error> {
-            use stm32cubeprogrammer_sys::libloading;
+        *running = Some(shared.clone());
+        Some(RunningOperation { token: self })
+    }
+}
 
-            let library: libloading::Library =
-                unsafe { libloading::os::unix::Library::new(path)?.into() };
+impl Drop for RunningOperation<'_> {
+    fn drop(&mut self) {
+        let mut running = self.token.running();
 
-            Ok(library)
+        if let Some(shared) = running.take() {
+            shared.backend.set_cancel_flag(false);
         }
 
-        unsafe { load_inner(api_library_path.as_ref()) }
+        self.token.state.cancelled.store(false, Ordering::SeqCst);
     }
 }
 
-impl std::fmt::Debug for CubeProgrammer {
+impl std::fmt::Debug for CancellationToken {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("CubeProgrammerApi").finish_non_exhaustive()
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
     }
 }
 
-impl Drop for ConnectedProgrammer<'_> {
-    /// Disconnect and re-insert the probe into the probe registry of the api
+impl Drop for ConnectedProgrammer {
+    /// Disconnect and release the access port in the probe registry of the api
     fn drop(&mut self) {
-        unsafe {
-            self.api().disconnect();
+        {
+            let mut api = self.programmer.api();
+
+            // Another connection may have replaced this connection in the meantime
+            if api.state.active_connection == Some(connection_key(&self.connection)) {
+                api.backend.disconnect();
+                api.state.active_connection = None;
+            }
         }
 
-        self.programmer.insert_probe(&self.probe);
+        self.programmer.release_probe(&self.connection);
     }
 }
 
-impl ConnectedProgrammer<'_> {
+impl ConnectedProgrammer {
     /// Disconnect from target
     pub fn disconnect(self) {
         // Consume self -> Drop is called to disconnect
@@ -414,8 +904,126 @@ impl ConnectedProgrammer<'_> {
         &self.general_information
     }
 
-    fn api(&self) -> &stm32cubeprogrammer_sys::CubeProgrammer_API {
-        &self.programmer.api
+    /// Effective parameters of this connection (e.g. frequency, low-power debug and speed mode)
+    pub fn connection_info(&self) -> crate::probe::ConnectionInfo {
+        crate::probe::ConnectionInfo::from(&self.connection)
+    }
+
+    /// Token which cancels the long-running operations of this connection
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation_token.clone()
+    }
+
+    /// Use the given token to cancel the long-running operations of this connection, e.g. to share one token between several connections
+    pub fn set_cancellation_token(&mut self, cancellation_token: CancellationToken) {
+        self.cancellation_token = cancellation_token;
+    }
+
+    /// Check if the probe is shared with other connections and applications via the ST-LINK server
+    pub fn is_shared(&self) -> bool {
+        self.connection.shared()
+    }
+
+    /// Access port of the core which this connection is attached to
+    /// Core register access, halt and reset of this connection operate on this core
+    pub fn access_port(&self) -> u32 {
+        self.connection.access_port()
+    }
+
+    /// Read the target voltage in millivolts via a new probe scan. `None` if the probe reports no valid voltage
+    pub fn target_voltage(&self) -> CubeProgrammerResult<Option<u32>> {
+        self.operation(crate::error::Action::ReadTargetVoltage, || {
+            self.programmer
+                .scan_target_voltage(&self.probe.serial_number().to_string().into())
+        })
+    }
+
+    /// Execute an operation on this connection (see [`CubeProgrammer::operation`])
+    fn operation<T>(
+        &self,
+        action: crate::error::Action,
+        f: impl FnOnce() -> CubeProgrammerResult<T>,
+    ) -> CubeProgrammerResult<T> {
+        self.programmer.operation(
+            Some(self.probe.serial_number().to_string().into()),
+            action,
+            || {
+                display::record(
+                    "device_id",
+                    format_args!("{:#05x}", self.general_information.device_id),
+                );
+                f()
+            },
+        )
+    }
+
+    /// Lock the access to the API and make sure that the connection of this programmer is the current connection of the API
+    /// If the connection cannot be re-established, it is retried according to the reconnect policy. The API lock is released during the retries
+    fn api(&self) -> CubeProgrammerResult<ApiGuard<'_>> {
+        let mut api = self.programmer.api();
+
+        let error = match self.reestablish_connection(&mut api) {
+            Ok(()) => return Ok(api),
+            Err(error) => error,
+        };
+
+        let Some(policy) = &self.reconnect_policy else {
+            return Err(error);
+        };
+
+        drop(api);
+        self.programmer.retry_connection(
+            policy,
+            crate::probe::ConnectionMode::HotPlug,
+            error,
+            |mode| self.connect_with_mode(mode),
+        )?;
+
+        // Another connection may have used the API in the meantime
+        let mut api = self.programmer.api();
+        self.reestablish_connection(&mut api)?;
+
+        Ok(api)
+    }
+
+    /// Connect with hot plug, if the connection of this programmer is not the current connection of the API
+    fn reestablish_connection(&self, api: &mut ApiGuard<'_>) -> CubeProgrammerResult<()> {
+        if api.state.active_connection == Some(connection_key(&self.connection)) {
+            return Ok(());
+        }
+
+        debug!(
+            "Re-establish connection via probe with serial number: {} (access port {})",
+            self.probe.serial_number(),
+            self.connection.access_port()
+        );
+
+        let mut connection = self.connection.clone();
+        connection.set_connection_mode(crate::probe::ConnectionMode::HotPlug);
+
+        api.state.active_connection = None;
+        api.call(|api| api.connect(&connection))?;
+        api.state.active_connection = Some(connection_key(&self.connection));
+
+        Ok(())
+    }
+
+    /// Execute an operation which can be cancelled via the [`CancellationToken`] of this connection
+    /// If the token was cancelled before the start, the operation is not started and [`CubeProgrammerError::Cancelled`] is returned. A cancel during the operation is reported by the backend
+    fn cancellable<T>(
+        &self,
+        action: crate::error::Action,
+        operation: impl FnOnce(&dyn Backend) -> CubeProgrammerResult<T>,
+    ) -> CubeProgrammerResult<T> {
+        let api = self.api()?;
+
+        // Dropped before the API lock, so the next operation does not see the cancel flag
+        let Some(_running) = self.cancellation_token.start(&self.programmer.shared) else {
+            debug!("Action {} was cancelled before the start", action);
+            return Err(CubeProgrammerError::Cancelled { action });
+        };
+
+        api.call(operation)
     }
 
     fn check_fus_support(&self) -> CubeProgrammerResult<()> {
@@ -456,21 +1064,23 @@ impl ConnectedProgrammer<'_> {
         }
 
         /// Offsets of the FUS device info table fields
-        const DEVICE_INFO_TABLE_STATE_OFFSET: u32 = 0;
-        // const RESERVED1_OFFSET: u32 = 4;
-        // const LAST_FUS_ACTIVE_STATE_OFFSET: u32 = 5;
-        // const LAST_WIRELESS_STACK_STATE_OFFSET: u32 = 6;
-        // const CURRENT_WIRELESS_STACK_TYPE_OFFSET: u32 = 7;
-        // const SAFE_BOOT_VERSION_OFFSET: u32 = 8;
-        const FUS_VERSION_OFFSET: u32 = 12;
-        // const FUS_MEMORY_SIZE_OFFSET: u32 = 16;
-        const WIRELESS_STACK_VERSION_OFFSET: u32 = 20;
-        // const WIRELESS_STACK_MEMORY_SIZE_OFFSET: u32 = 24;
-        // const WIRELESS_FIRMWARE_BLE_INFO_OFFSET: u32 = 28;
-        // const WIRELESS_FIRMWARE_THREAD_INFO_OFFSET: u32 = 32;
-        // const RESERVED2_OFFSET: u32 = 36;
-        const UID64_OFFSET: u32 = 40;
-        const DEVICE_ID_OFFSET: u32 = 48;
+        const DEVICE_INFO_TABLE_STATE_OFFSET: usize = 0;
+        // const RESERVED1_OFFSET: usize = 4;
+        // const LAST_FUS_ACTIVE_STATE_OFFSET: usize = 5;
+        // const LAST_WIRELESS_STACK_STATE_OFFSET: usize = 6;
+        // const CURRENT_WIRELESS_STACK_TYPE_OFFSET: usize = 7;
+        // const SAFE_BOOT_VERSION_OFFSET: usize = 8;
+        const FUS_VERSION_OFFSET: usize = 12;
+        // const FUS_MEMORY_SIZE_OFFSET: usize = 16;
+        const WIRELESS_STACK_VERSION_OFFSET: usize = 20;
+        // const WIRELESS_STACK_MEMORY_SIZE_OFFSET: usize = 24;
+        // const WIRELESS_FIRMWARE_BLE_INFO_OFFSET: usize = 28;
+        // const WIRELESS_FIRMWARE_THREAD_INFO_OFFSET: usize = 32;
+        // const RESERVED2_OFFSET: usize = 36;
+        const UID64_OFFSET: usize = 40;
+        const DEVICE_ID_OFFSET: usize = 48;
+        /// Size of the FUS device info table up to the last used field
+        const INFO_TABLE_SIZE: usize = DEVICE_ID_OFFSET + 2;
 
         /// Keyword to check if the FUS device info table is valid
         const FUS_DEVICE_INFO_TABLE_VALIDITY_KEYWORD: u32 = 0xA94656B9;
@@ -483,26 +1093,28 @@ impl ConnectedProgrammer<'_> {
             return Err(CubeProgrammerError::ActionOutputUnexpected {
                 action: crate::error::Action::ReadFusInfo,
                 unexpected_output: crate::error::UnexpectedOutput::Null,
+                messages: Vec::new(),
             });
         }
 
-        let device_info_table_state =
-            self.read_memory::<u32>(info_table_address + DEVICE_INFO_TABLE_STATE_OFFSET, 1)?[0];
-
-        let fus_version = self.read_memory::<u32>(info_table_address + FUS_VERSION_OFFSET, 1)?[0];
+        // Read the info table at once
+        let table = self.read_memory::<u8>(info_table_address, INFO_TABLE_SIZE)?;
+        let field = |offset: usize, size: usize| &table[offset..offset + size];
 
+        let device_info_table_state =
+            bytemuck::pod_read_unaligned::<u32>(field(DEVICE_INFO_TABLE_STATE_OFFSET, 4));
+        let fus_version = bytemuck::pod_read_unaligned::<u32>(field(FUS_VERSION_OFFSET, 4));
         let wireless_stack_version =
-            self.read_memory::<u32>(info_table_address + WIRELESS_STACK_VERSION_OFFSET, 1)?[0];
-
-        let uid64 = self.read_memory::<u64>(info_table_address + UID64_OFFSET, 1)?[0];
-
-        let device_id = self.read_memory::<u16>(info_table_address + DEVICE_ID_OFFSET, 1)?[0];
+            bytemuck::pod_read_unaligned::<u32>(field(WIRELESS_STACK_VERSION_OFFSET, 4));
+        let uid64 = bytemuck::pod_read_unaligned::<u64>(field(UID64_OFFSET, 8));
+        let device_id = bytemuck::pod_read_unaligned::<u16>(field(DEVICE_ID_OFFSET, 2));
 
         if device_info_table_state != FUS_DEVICE_INFO_TABLE_VALIDITY_KEYWORD {
             error!("Read FUS info table is not valid. Return default FUS info");
             return Err(CubeProgrammerError::ActionOutputUnexpected {
                 action: crate::error::Action::ReadFusInfo,
                 unexpected_output: crate::error::UnexpectedOutput::Null,
+                messages: Vec::new(),
             });
         }
 
@@ -511,14 +1123,17 @@ impl ConnectedProgrammer<'_> {
             wireless_stack_version: u32_to_version(wireless_stack_version),
             device_id,
             uid64,
+            // Not part of the info table
+            anti_rollback: crate::fus::AntiRollbackState::Unknown,
         })
     }
 
     /// Reset target
     pub fn reset_target(&self, reset_mode: crate::probe::ResetMode) -> CubeProgrammerResult<()> {
-        self.check_connection()?;
-        api_types::ReturnCode::<0>::from(unsafe { self.api().reset(reset_mode.into()) })
-            .check(crate::error::Action::Reset)
+        self.operation(crate::error::Action::Reset, || {
+            self.check_connection()?;
+            self.api()?.call(|api| api.reset(reset_mode))
+        })
     }
 
     /// Download hex file to target
@@ -528,53 +1143,50 @@ impl ConnectedProgrammer<'_> {
         skip_erase: bool,
         verify: bool,
     ) -> CubeProgrammerResult<()> {
-        // Validate if the given file is a valid hex file if the feature is enabled
-        #[cfg(feature = "ihex")]
-        {
-            // Check if the given file is really a hex file
-            // Unfortunately, the CubeProgrammer API does not check this and simply programs to address 0 if a bin file is passed
-            let file_content = std::fs::read(&file_path).map_err(CubeProgrammerError::FileIo)?;
-            let file_content =
-                std::str::from_utf8(&file_content).map_err(|_| CubeProgrammerError::Parameter {
-                    action: crate::error::Action::DownloadFile,
-                    message: "Invalid intelhex file".to_string(),
-                })?;
+        self.operation(crate::error::Action::DownloadFile, || {
+            display::record("file", file_path.as_ref().display());
 
-            let reader = ihex::Reader::new_with_options(
-                file_content,
-                ihex::ReaderOptions {
-                    stop_after_first_error: true,
-                    stop_after_eof: true,
-                },
-            );
+            // Validate if the given file is a valid hex file if the feature is enabled
+            #[cfg(feature = "ihex")]
+            {
+                // Check if the given file is really a hex file
+                // Unfortunately, the CubeProgrammer API does not check this and simply programs to address 0 if a bin file is passed
+                let file_content =
+                    std::fs::read(&file_path).map_err(CubeProgrammerError::FileIo)?;
+                let file_content = std::str::from_utf8(&file_content).map_err(|_| {
+                    CubeProgrammerError::Parameter {
+                        action: crate::error::Action::DownloadFile,
+                        message: "Invalid intelhex file".to_string(),
+                    }
+                })?;
 
-            for record in reader {
-                match record {
-                    Ok(_) => {}
-                    Err(e) => {
-                        return Err(CubeProgrammerError::Parameter {
-                            action: crate::error::Action::DownloadFile,
-                            message: format!("Invalid intelhex file: {}", e),
-                        });
+                let reader = ihex::Reader::new_with_options(
+                    file_content,
+                    ihex::ReaderOptions {
+                        stop_after_first_error: true,
+                        stop_after_eof: true,
+                    },
+                );
+
+                for record in reader {
+                    match record {
+                        Ok(_) => {}
+                        Err(e) => {
+                            return Err(CubeProgrammerError::Parameter {
+                                action: crate::error::Action::DownloadFile,
+                                message: format!("Invalid intelhex file: {}", e),
+                            });
+                        }
                     }
                 }
             }
-        }
-
-        self.check_connection()?;
 
-        let file_path = utility::path_to_widestring(file_path);
+            self.check_connection()?;
 
-        api_types::ReturnCode::<0>::from(unsafe {
-            self.api().downloadFile(
-                file_path?.as_ptr(),
-                0,
-                if skip_erase { 1 } else { 0 },
-                if verify { 1 } else { 0 },
-                std::ptr::null(),
-            )
+            self.cancellable(crate::error::Action::DownloadFile, |api| {
+                api.download_file(file_path.as_ref(), 0, skip_erase, verify)
+            })
         })
-        .check(crate::error::Action::DownloadFile)
     }
 
     /// Download binary file to target
@@ -585,28 +1197,25 @@ impl ConnectedProgrammer<'_> {
         skip_erase: bool,
         verify: bool,
     ) -> CubeProgrammerResult<()> {
-        self.check_connection()?;
-
-        let file_path = utility::path_to_widestring(file_path);
-
-        api_types::ReturnCode::<0>::from(unsafe {
-            self.api().downloadFile(
-                file_path?.as_ptr(),
-                start_address,
-                if skip_erase { 1 } else { 0 },
-                if verify { 1 } else { 0 },
-                std::ptr::null(),
-            )
+        self.operation(crate::error::Action::DownloadFile, || {
+            display::record("file", file_path.as_ref().display());
+            display::record("address", format_args!("{:#010x}", start_address));
+
+            self.check_connection()?;
+
+            self.cancellable(crate::error::Action::DownloadFile, |api| {
+                api.download_file(file_path.as_ref(), start_address, skip_erase, verify)
+            })
         })
-        .check(crate::error::Action::DownloadFile)
     }
 
     /// Perform mass erase
     pub fn mass_erase(&self) -> CubeProgrammerResult<()> {
-        self.check_connection()?;
+        self.operation(crate::error::Action::MassErase, || {
+            self.check_connection()?;
 
-        api_types::ReturnCode::<0>::from(unsafe { self.api().massErase(std::ptr::null_mut()) })
-            .check(crate::error::Action::MassErase)
+            self.cancellable(crate::error::Action::MassErase, |api| api.mass_erase())
+        })
     }
 
     /// Save memory to file
@@ -617,55 +1226,233 @@ impl ConnectedProgrammer<'_> {
         start_address: u32,
         size_bytes: u32,
     ) -> CubeProgrammerResult<()> {
-        self.check_connection()?;
-
-        api_types::ReturnCode::<0>::from(unsafe {
-            self.api().saveMemoryToFile(
-                i32::try_from(start_address).map_err(|x| CubeProgrammerError::Parameter {
-                    action: crate::error::Action::SaveMemory,
-                    message: format!("Start address exceeds max value: {}", x),
-                })?,
-                i32::try_from(size_bytes).map_err(|x| CubeProgrammerError::Parameter {
-                    action: crate::error::Action::SaveMemory,
-                    message: format!("Size exceeds max value: {}", x),
-                })?,
-                utility::path_to_widestring(file_path)?.as_ptr(),
-            )
+        self.operation(crate::error::Action::SaveMemory, || {
+            display::record("file", file_path.as_ref().display());
+            display::record("address", format_args!("{:#010x}", start_address));
+            display::record("size", size_bytes);
+
+            self.check_connection()?;
+
+            self.cancellable(crate::error::Action::SaveMemory, |api| {
+                api.save_memory_to_file(file_path.as_ref(), start_address, size_bytes)
+            })
         })
-        .check(crate::error::Action::SaveMemory)
     }
 
     /// Enable roud out protection level 1 (0xBB)
+    /// The option byte launch resets the target, so the connection is established again afterwards
     pub fn enable_read_out_protection(&self) -> CubeProgrammerResult<()> {
         /// Command according to Example 3 of the CubeProgrammer API documentation
         const COMMAND_ENABLE_ROP_LEVEL_1: &str = "-ob rdp=0xbb";
 
-        self.check_connection()?;
+        self.operation(crate::error::Action::EnableReadOutProtection, || {
+            self.check_connection()?;
+
+            self.api()?
+                .call(|api| api.send_option_bytes_command(COMMAND_ENABLE_ROP_LEVEL_1))?;
 
-        api_types::ReturnCode::<0>::from(unsafe {
-            self.api().sendOptionBytesCmd(
-                utility::string_to_cstring(COMMAND_ENABLE_ROP_LEVEL_1)?.as_ptr()
-                    as *mut std::ffi::c_char,
-            )
+            self.reconnect()
         })
-        .check(crate::error::Action::EnableReadOutProtection)
     }
 
     /// Disable read out protection
     /// Attention: This command will eOrase the device memory
     pub fn disable_read_out_protection(&self) -> CubeProgrammerResult<()> {
-        self.check_connection()?;
-        api_types::ReturnCode::<0>::from(unsafe { self.api().readUnprotect() })
-            .check(crate::error::Action::DisableReadOutProtection)?;
-        Ok(())
+        self.operation(crate::error::Action::DisableReadOutProtection, || {
+            self.check_connection()?;
+            self.api()?.call(|api| api.read_unprotect())
+        })
+    }
+
+    /// Read the TrustZone state (TZEN option bit) of the target
+    ///
+    /// Only devices with a TZEN option bit are supported (e.g. stm32l5, stm32u5). For other devices, [`CubeProgrammerError::ActionNotSupported`] is returned
+    pub fn trustzone_state(&self) -> CubeProgrammerResult<api_types::TrustZoneState> {
+        self.operation(crate::error::Action::ReadOptionBytes, || {
+            self.check_connection()?;
+
+            match self.read_option_byte(OPTION_BYTE_TZEN)? {
+                Some(0) => Ok(api_types::TrustZoneState::Disabled),
+                Some(_) => Ok(api_types::TrustZoneState::Enabled),
+                None => Err(CubeProgrammerError::ActionNotSupported {
+                    action: crate::error::Action::ReadOptionBytes,
+                    message: format!(
+                        "Connection target {} does not have a TZEN option bit",
+                        self.general_information.name
+                    ),
+                }),
+            }
+        })
+    }
+
+    /// Disable the TrustZone (TZEN=0) of the target via a TrustZone regression
+    /// The TZEN option bit can only be cleared during a read out protection regression from level 1 to level 0. If the target is at level 0 or 0.5, the read out protection is raised to level 1 first
+    /// Attention: This command will erase the device memory and therefore needs to be confirmed via [`crate::Confirmation::Confirmed`]
+    ///
+    /// Only devices with a TZEN option bit are supported (e.g. stm32l5, stm32u5)
+    /// Returns the TrustZone state which is read back after reconnecting to the reset target
+    pub fn trustzone_regression(
+        &self,
+        confirmation: crate::Confirmation,
+    ) -> CubeProgrammerResult<api_types::TrustZoneState> {
+        /// Read out protection level 0
+        const RDP_LEVEL_0: u32 = 0xAA;
+        /// Read out protection level 0.5 (only available if TZEN=1)
+        const RDP_LEVEL_0_5: u32 = 0x55;
+        /// Read out protection level 2 (irreversible)
+        const RDP_LEVEL_2: u32 = 0xCC;
+
+        confirmation.check(crate::error::Action::TrustZoneRegression)?;
+
+        self.operation(crate::error::Action::TrustZoneRegression, || {
+            if self.trustzone_state()? == api_types::TrustZoneState::Disabled {
+                debug!("TrustZone is already disabled");
+                return Ok(api_types::TrustZoneState::Disabled);
+            }
+
+            match self.read_option_byte(OPTION_BYTE_RDP)? {
+                Some(RDP_LEVEL_2) => {
+                    return Err(CubeProgrammerError::ActionNotSupported {
+                        action: crate::error::Action::TrustZoneRegression,
+                        message: "Read out protection level 2 is active".to_string(),
+                    })
+                }
+                Some(RDP_LEVEL_0) | Some(RDP_LEVEL_0_5) => {
+                    debug!("Raise read out protection to level 1 before the TrustZone regression");
+                    // Reconnects after the option byte launch, so the regression does not run on a lost connection
+                    self.enable_read_out_protection()?;
+                }
+                _ => {}
+            }
+
+            self.api()?.call(|api| api.tzen_regression())?;
+
+            // The regression resets the target, so the connection is lost
+            self.reconnect()?;
+
+            self.trustzone_state()
+        })
+    }
+
+    /// Run the Secure Secret Provisioning (SSP) on a stm32mp1 target
+    /// The progress is reported via the [`crate::EventHandler`] like for all other operations
+    /// Attention: The provisioning closes the device and is irreversible. Therefore it needs to be confirmed via [`crate::Confirmation::Confirmed`]
+    ///
+    /// Note: The CubeProgrammer API only supports the SSP via USB DFU and UART bootloader interfaces
+    pub fn program_ssp(
+        &self,
+        parameters: &crate::ssp::Parameters,
+        confirmation: crate::Confirmation,
+    ) -> CubeProgrammerResult<crate::ssp::Outcome> {
+        confirmation.check(crate::error::Action::ProgramSsp)?;
+
+        self.operation(crate::error::Action::ProgramSsp, || {
+            // Fail early if one of the files does not exist, as the API only reports an unknown error
+            let mut files = vec![&parameters.ssp_file, &parameters.rsse_file];
+            files.extend(&parameters.rsse_extension_file);
+            if let crate::ssp::License::File(license_file) = &parameters.license {
+                files.push(license_file);
+            }
+
+            for file in files {
+                std::fs::metadata(file).map_err(CubeProgrammerError::FileIo)?;
+            }
+
+            // Without HSM, the slot id needs to be 0. With HSM, the license file needs to be empty
+            let (license_file, hsm_slot_id) = match &parameters.license {
+                crate::ssp::License::File(path) => (Some(path.as_path()), 0),
+                crate::ssp::License::Hsm { slot_id } => (
+                    None,
+                    i32::try_from(*slot_id).map_err(|x| CubeProgrammerError::Parameter {
+                        action: crate::error::Action::ProgramSsp,
+                        message: format!("HSM slot id exceeds max value: {}", x),
+                    })?,
+                ),
+            };
+
+            // The API takes a single RSSe binary, which contains the extension
+            let rsse_file = match &parameters.rsse_extension_file {
+                Some(extension_file) => Some(crate::utility::TemporaryFile::create(
+                    "rsse",
+                    &[
+                        std::fs::read(&parameters.rsse_file)
+                            .map_err(CubeProgrammerError::FileIo)?,
+                        std::fs::read(extension_file).map_err(CubeProgrammerError::FileIo)?,
+                    ]
+                    .concat(),
+                )?),
+                None => None,
+            };
+
+            self.check_connection()?;
+
+            let start = std::time::Instant::now();
+
+            self.api()?.call(|api| {
+                api.program_ssp(
+                    &parameters.ssp_file,
+                    license_file,
+                    rsse_file
+                        .as_ref()
+                        .map_or(parameters.rsse_file.as_path(), |x| x.path()),
+                    hsm_slot_id,
+                )
+            })?;
+
+            Ok(crate::ssp::Outcome {
+                parameters: parameters.clone(),
+                duration: start.elapsed(),
+            })
+        })
+    }
+
+    /// Read the value of an option byte field (e.g. `RDP`) via the option bytes interface
+    /// Returns `None` if the target does not have an option byte field with the given name
+    fn read_option_byte(&self, name: &str) -> CubeProgrammerResult<Option<u32>> {
+        Ok(self
+            .api()?
+            .call(|api| api.option_bits())?
+            .into_iter()
+            .find(|bit| bit.name.eq_ignore_ascii_case(name))
+            .map(|bit| bit.value))
     }
 
     /// Check connection to target
-    /// Consumes self and and only returns self if the connection is still maintained
-    /// If the connection is lost, the user is forced to reconnect
+    /// If the connection is lost, it is re-established according to the reconnect policy. Without a policy, the user is forced to reconnect
     fn check_connection(&self) -> CubeProgrammerResult<()> {
-        api_types::ReturnCode::<1>::from(unsafe { self.api().checkDeviceConnection() })
-            .check(crate::error::Action::CheckConnection)
+        // A failed re-establish of the connection is already retried according to the reconnect policy
+        let result = self.api()?.call(|api| api.check_connection());
+        self.retry_with_policy(result)
+    }
+
+    /// Connect again via hot plug after an operation which resets the target (e.g. the TrustZone regression or an option byte launch)
+    /// A failed connection is retried according to the reconnect policy
+    fn reconnect(&self) -> CubeProgrammerResult<()> {
+        debug!("Reconnect after the reset of the target");
+
+        let result = self.connect_with_mode(crate::probe::ConnectionMode::HotPlug);
+        self.retry_with_policy(result)
+    }
+
+    /// Retry a failed connection according to the reconnect policy, if the connection has one
+    /// Like the re-establish of the connection, the attempts use hot plug, so the running target is not reset, unless the policy escalates the connection mode
+    fn retry_with_policy(&self, result: CubeProgrammerResult<()>) -> CubeProgrammerResult<()> {
+        match (result, &self.reconnect_policy) {
+            (Err(error), Some(policy)) => self.programmer.retry_connection(
+                policy,
+                crate::probe::ConnectionMode::HotPlug,
+                error,
+                |mode| self.connect_with_mode(mode),
+            ),
+            (result, _) => result,
+        }
+    }
+
+    /// Connect with the parameters of this connection, but with the given connection mode
+    fn connect_with_mode(&self, mode: crate::probe::ConnectionMode) -> CubeProgrammerResult<()> {
+        let mut connection = self.connection.clone();
+        connection.set_connection_mode(mode);
+        self.programmer.connect_probe(&connection).map(|_| ())
     }
 
     /// Read memory as struct
@@ -683,48 +1470,37 @@ impl ConnectedProgrammer<'_> {
         address: u32,
         count: usize,
     ) -> CubeProgrammerResult<Vec<T>> {
-        let size = u32::try_from(std::mem::size_of::<T>() * count).map_err(|x| {
-            CubeProgrammerError::Parameter {
-                action: crate::error::Action::ReadMemory,
-                message: format!("Size exceeds max value: {}", x),
-            }
-        })?;
-
-        let mut data = std::ptr::null_mut();
+        self.operation(crate::error::Action::ReadMemory, || {
+            display::record("address", format_args!("{:#010x}", address));
+            display::record("size", std::mem::size_of::<T>() * count);
 
-        api_types::ReturnCode::<0>::from(unsafe {
-            self.api().readMemory(address, &mut data, size)
-        })
-        .check(crate::error::Action::ReadMemory)?;
-
-        if data.is_null() {
-            return Err(CubeProgrammerError::ActionOutputUnexpected {
-                action: crate::error::Action::ReadMemory,
-                unexpected_output: crate::error::UnexpectedOutput::Null,
-            });
-        }
-
-        let pod_data: &[T] =
-            bytemuck::try_cast_slice(unsafe { std::slice::from_raw_parts(data, size as _) })
-                .map_err(|_| CubeProgrammerError::ActionOutputUnexpected {
+            let size = u32::try_from(std::mem::size_of::<T>() * count).map_err(|x| {
+                CubeProgrammerError::Parameter {
                     action: crate::error::Action::ReadMemory,
-                    unexpected_output: crate::error::UnexpectedOutput::SliceConversion,
-                })?;
+                    message: format!("Size exceeds max value: {}", x),
+                }
+            })?;
 
-        let pod_data = pod_data.to_vec();
+            self.check_connection()?;
 
-        unsafe {
-            self.api().freeLibraryMemory(data as *mut std::ffi::c_void);
-        }
+            let bytes = self.cancellable(crate::error::Action::ReadMemory, |api| {
+                api.read_memory(address, size)
+            })?;
 
-        if pod_data.len() != count {
-            return Err(CubeProgrammerError::ActionOutputUnexpected {
-                action: crate::error::Action::ReadMemory,
-                unexpected_output: crate::error::UnexpectedOutput::SliceLength,
-            });
-        }
+            if bytes.len() != size as usize {
+                return Err(CubeProgrammerError::ActionOutputUnexpected {
+                    action: crate::error::Action::ReadMemory,
+                    unexpected_output: crate::error::UnexpectedOutput::SliceLength,
+                    messages: Vec::new(),
+                });
+            }
 
-        Ok(pod_data)
+            // The returned bytes are not necessarily aligned for `T`
+            Ok(bytes
+                .chunks_exact(std::mem::size_of::<T>())
+                .map(bytemuck::pod_read_unaligned)
+                .collect())
+        })
     }
 
     /// Write memory as struct
@@ -742,31 +1518,25 @@ impl ConnectedProgrammer<'_> {
         address: u32,
         data: &[T],
     ) -> CubeProgrammerResult<()> {
-        let size = u32::try_from(std::mem::size_of_val(data)).map_err(|x| {
-            CubeProgrammerError::Parameter {
-                action: crate::error::Action::WriteMemory,
-                message: format!("Size exceeds max value: {}", x),
-            }
-        })?;
+        self.operation(crate::error::Action::WriteMemory, || {
+            display::record("address", format_args!("{:#010x}", address));
+            display::record("size", std::mem::size_of_val(data));
 
-        let mut bytes = data
-            .iter()
-            .flat_map(|x| bytemuck::bytes_of(x).to_vec())
-            .collect::<Vec<_>>();
+            self.check_connection()?;
 
-        api_types::ReturnCode::<0>::from(unsafe {
-            self.api()
-                .writeMemory(address, bytes.as_mut_ptr() as *mut i8, size)
+            self.cancellable(crate::error::Action::WriteMemory, |api| {
+                api.write_memory(address, bytemuck::cast_slice(data))
+            })
         })
-        .check(crate::error::Action::WriteMemory)
     }
 
     /// Start the wireless stack
     pub fn start_wireless_stack(&self) -> CubeProgrammerResult<()> {
-        self.check_fus_support()?;
+        self.operation(crate::error::Action::StartWirelessStack, || {
+            self.check_fus_support()?;
 
-        api_types::ReturnCode::<1>::from(unsafe { self.api().startWirelessStack() })
-            .check(crate::error::Action::StartWirelessStack)
+            self.api()?.call(|api| api.start_wireless_stack())
+        })
     }
 
     /// Write [`crate::api_types::CoreRegister`]
@@ -775,12 +1545,12 @@ impl ConnectedProgrammer<'_> {
         register: crate::api_types::CoreRegister,
         value: u32,
     ) -> CubeProgrammerResult<()> {
-        self.check_connection()?;
+        self.operation(crate::error::Action::WriteCoreRegister, || {
+            self.check_connection()?;
 
-        api_types::ReturnCode::<0>::from(unsafe {
-            self.api().writeCortexRegistres(register.into(), value)
+            self.api()?
+                .call(|api| api.write_core_register(register, value))
         })
-        .check(crate::error::Action::WriteCoreRegister)
     }
 
     /// Read [`crate::api_types::CoreRegister`]
@@ -788,27 +1558,68 @@ impl ConnectedProgrammer<'_> {
         &self,
         register: crate::api_types::CoreRegister,
     ) -> CubeProgrammerResult<u32> {
-        self.check_connection()?;
+        self.operation(crate::error::Action::ReadCoreRegister, || {
+            self.check_connection()?;
+
+            self.api()?.call(|api| api.read_core_register(register))
+        })
+    }
 
-        let mut value = 0;
+    /// Read the 64 bit unique device identifier (UID64)
+    pub fn uid64(&self) -> CubeProgrammerResult<crate::uid::Uid64> {
+        self.operation(crate::error::Action::ReadUid64, || {
+            self.check_connection()?;
 
-        api_types::ReturnCode::<0>::from(unsafe {
-            self.api().readCortexReg(register.into(), &mut value)
+            Ok(crate::uid::Uid64(self.api()?.call(|api| api.uid64())?))
         })
-        .check(crate::error::Action::ReadCoreRegister)?;
+    }
 
-        Ok(value)
+    /// Read the 96 bit unique device identifier from the system memory
+    /// The address of the identifier is looked up via the device id of the target (see [`api_types::GeneralInformation::device_id`])
+    pub fn unique_id_96(&self) -> CubeProgrammerResult<crate::uid::Uid96> {
+        self.operation(crate::error::Action::ReadUid96, || {
+            let address = crate::uid::uid96_base_address(self.general_information.device_id)
+                .ok_or_else(|| CubeProgrammerError::ActionNotSupported {
+                    action: crate::error::Action::ReadUid96,
+                    message: format!(
+                        "Unknown 96 bit UID address for target {} (device id: 0x{:03X})",
+                        self.general_information.name, self.general_information.device_id
+                    ),
+                })?;
+
+            // The connection is checked by the memory read
+            let words = self.read_memory::<u32>(address, 3)?;
+
+            Ok(crate::uid::Uid96([words[0], words[1], words[2]]))
+        })
     }
 }
 
-impl ConnectedFusProgrammer<'_> {
+impl ConnectedFusProgrammer {
     pub fn fus_info(&self) -> &crate::fus::Information {
         &self.fus_info
     }
 
+    /// Token which cancels the long-running operations of this connection (see [`ConnectedProgrammer::cancellation_token`])
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.programmer.cancellation_token()
+    }
+
+    /// Use the given token to cancel the long-running operations of this connection (see [`ConnectedProgrammer::set_cancellation_token`])
+    pub fn set_cancellation_token(&mut self, cancellation_token: CancellationToken) {
+        self.programmer.set_cancellation_token(cancellation_token);
+    }
+
     pub fn delete_wireless_stack(&self) -> CubeProgrammerResult<()> {
-        api_types::ReturnCode::<1>::from(unsafe { self.programmer.api().firmwareDelete() })
-            .check(crate::error::Action::DeleteWirelessStack)
+        self.programmer
+            .operation(crate::error::Action::DeleteWirelessStack, || {
+                self.programmer.check_connection()?;
+
+                self.programmer
+                    .cancellable(crate::error::Action::DeleteWirelessStack, |api| {
+                        api.firmware_delete()
+                    })
+            })
     }
 
     pub fn upgrade_wireless_stack(
@@ -819,25 +1630,127 @@ impl ConnectedFusProgrammer<'_> {
         verify: bool,
         start_stack_after_update: bool,
     ) -> CubeProgrammerResult<()> {
-        self.programmer.check_connection()?;
-
-        api_types::ReturnCode::<1>::from(unsafe {
-            self.programmer.api().firmwareUpgrade(
-                utility::path_to_widestring(file_path)?.as_ptr(),
-                start_address,
-                if first_install { 1 } else { 0 },
-                if verify { 1 } else { 0 },
-                if start_stack_after_update { 1 } else { 0 },
-            )
-        })
-        .check(crate::error::Action::UpgradeWirelessStack)
+        self.programmer
+            .operation(crate::error::Action::UpgradeWirelessStack, || {
+                display::record("file", file_path.as_ref().display());
+                display::record("address", format_args!("{:#010x}", start_address));
+
+                self.programmer.check_connection()?;
+
+                self.programmer
+                    .cancellable(crate::error::Action::UpgradeWirelessStack, |api| {
+                        api.firmware_upgrade(
+                            file_path.as_ref(),
+                            start_address,
+                            first_install,
+                            start_stack_after_update,
+                            verify,
+                        )
+                    })
+            })
     }
 
     pub fn start_wireless_stack(&self) -> CubeProgrammerResult<()> {
         self.programmer.start_wireless_stack()
     }
 
+    /// Activate the anti-rollback of the wireless stack. Afterwards, it is not possible to install an older wireless stack version
+    /// Attention: This operation is irreversible and therefore needs to be confirmed via [`crate::Confirmation::Confirmed`]
+    ///
+    /// The FUS information is read back from the FUS info table after the activation and returned with the anti-rollback
+    /// state [`crate::fus::AntiRollbackState::Active`]
+    pub fn activate_anti_rollback(
+        &mut self,
+        confirmation: crate::Confirmation,
+    ) -> CubeProgrammerResult<&crate::fus::Information> {
+        confirmation.check(crate::error::Action::ActivateAntiRollback)?;
+
+        self.programmer
+            .operation(crate::error::Action::ActivateAntiRollback, || {
+                self.programmer.check_connection()?;
+
+                self.programmer.api()?.call(|api| api.anti_rollback())?;
+
+                self.fus_info = crate::fus::Information {
+                    anti_rollback: crate::fus::AntiRollbackState::Active,
+                    ..self.programmer.read_fus_info()?
+                };
+
+                Ok(&self.fus_info)
+            })
+    }
+
     pub fn disconnect(self) {
         self.programmer.disconnect()
     }
 }
+
+impl Hsm {
+    /// Slot id of the HSM
+    pub fn slot_id(&self) -> u32 {
+        self.slot_id
+    }
+
+    /// Read the status of the HSM. The HSM is accessed via the smart card reader and no target connection is needed
+    pub fn status(&self) -> CubeProgrammerResult<crate::hsm::HsmStatus> {
+        let slot_id = self.slot_id_ffi(crate::error::Action::ReadHsmStatus)?;
+        self.programmer
+            .operation(None, crate::error::Action::ReadHsmStatus, || {
+                let api = self.programmer.api();
+
+                let firmware_id = api.call(|api| api.hsm_firmware_id(slot_id))?;
+                let counter = api.call(|api| api.hsm_counter(slot_id))?;
+                let state = api.call(|api| api.hsm_state(slot_id))?;
+                let version = api.call(|api| api.hsm_version(slot_id))?;
+                let r#type = api.call(|api| api.hsm_type(slot_id))?;
+
+                drop(api);
+
+                Ok(crate::hsm::HsmStatus {
+                    slot_id: self.slot_id,
+                    firmware_id,
+                    counter,
+                    // Parsing cannot fail because of the default variant
+                    state: state.parse().unwrap(),
+                    version: version.trim().parse().map_err(|x| {
+                        CubeProgrammerError::TypeConversion {
+                            message: format!("Cannot convert HSM version \"{}\": {:?}", version, x),
+                            source: crate::error::TypeConversionError::VersionError,
+                        }
+                    })?,
+                    r#type: r#type.parse().unwrap(),
+                })
+            })
+    }
+
+    /// Export the license of the HSM for the connected target to a binary file
+    /// The connection needs to be established via the same [`CubeProgrammer`] which created this handle
+    pub fn export_license(
+        &self,
+        connected: &ConnectedProgrammer,
+        file_path: impl AsRef<std::path::Path>,
+    ) -> CubeProgrammerResult<()> {
+        let slot_id = self.slot_id_ffi(crate::error::Action::ExportHsmLicense)?;
+
+        if !Arc::ptr_eq(&self.programmer.shared, &connected.programmer.shared) {
+            return Err(CubeProgrammerError::Parameter {
+                action: crate::error::Action::ExportHsmLicense,
+                message: "The connection is not established via the programmer of the HSM"
+                    .to_string(),
+            });
+        }
+
+        connected.operation(crate::error::Action::ExportHsmLicense, || {
+            connected
+                .api()?
+                .call(|api| api.hsm_license(slot_id, file_path.as_ref()))
+        })
+    }
+
+    fn slot_id_ffi(&self, action: crate::error::Action) -> CubeProgrammerResult<i32> {
+        i32::try_from(self.slot_id).map_err(|x| CubeProgrammerError::Parameter {
+            action,
+            message: format!("HSM slot id exceeds max value: {}", x),
+        })
+    }
+}
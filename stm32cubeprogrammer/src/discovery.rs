@@ -0,0 +1,331 @@
+//! Discovery of STM32CubeProgrammer installations
+//!
+//! The following locations are searched:
+//! - The default installation directory in the home directory (`~/STMicroelectronics/STM32Cube/STM32CubeProgrammer`)
+//! - The installation directories (`/opt/st` and `~/st` on Linux, `C:\Program Files\STMicroelectronics\STM32Cube` and `C:\ST` on Windows) including the bundles of STM32CubeIDE installations
+//! - The directories of the `PATH` environment variable which contain the `STM32_Programmer_CLI`
+//!
+//! The version of an installation is detected by calling `STM32_Programmer_CLI --version`.
+//!
+//! Shared probe connections ([`crate::probe::ConnectionParameters::shared`]) need the ST-LINK server, which is installed separately or together with STM32CubeIDE.
+//! A running ST-LINK server is detected via its TCP port ([`stlink_server_running`]).
+
+use crate::api_types::CubeProgrammerVersion;
+use bon::bon;
+use log::{debug, warn};
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock, PoisonError},
+    time::Instant,
+};
+use stm32cubeprogrammer_sys::{PATH_API_LIBRARY_RELATIVE, PATH_LOADER_DIR_RELATIVE};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(windows)]
+const CLI_NAME: &str = "STM32_Programmer_CLI.exe";
+#[cfg(not(windows))]
+const CLI_NAME: &str = "STM32_Programmer_CLI";
+
+/// TCP port on which the ST-LINK server listens for connections on the local host
+pub const STLINK_SERVER_PORT: u16 = 7184;
+
+/// Timeout for the connection attempt to the ST-LINK server
+const STLINK_SERVER_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// Timeout for `STM32_Programmer_CLI --version`. The CLI is killed if it does not exit in time
+const VERSION_DETECTION_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Installation directory of STM32CubeProgrammer relative to the home directory
+const HOME_INSTALLATION_RELATIVE: &str = "STMicroelectronics/STM32Cube/STM32CubeProgrammer";
+
+/// Where an installation was found
+/// The order defines the preference of installations with the same version
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, strum::Display)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub enum InstallationSource {
+    /// Installation directory in the home directory
+    HomeDirectory,
+    /// Installation in one of the installation directories
+    InstallationDirectory,
+    /// STM32CubeProgrammer bundled with STM32CubeIDE
+    CubeIde,
+    /// `STM32_Programmer_CLI` in the `PATH`
+    Path,
+}
+
+/// STM32CubeProgrammer installation which can be passed to [`crate::CubeProgrammer::builder`]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
+pub struct Installation {
+    /// Root directory of the installation
+    pub path: PathBuf,
+    /// `None` if the version cannot be detected
+    pub version: Option<CubeProgrammerVersion>,
+    pub source: InstallationSource,
+}
+
+impl Installation {
+    /// Check if the directory contains a STM32CubeProgrammer installation and detect its version
+    pub fn from_dir(path: impl AsRef<Path>, source: InstallationSource) -> Option<Self> {
+        let path = path.as_ref();
+
+        if !path.join(PATH_API_LIBRARY_RELATIVE).is_file()
+            || !path.join(PATH_LOADER_DIR_RELATIVE).is_dir()
+        {
+            return None;
+        }
+
+        let path = path.canonicalize().ok()?;
+        let version = detect_version(&path);
+        debug!("Found installation {:?} with version {:?}", path, version);
+
+        Some(Self {
+            path,
+            version,
+            source,
+        })
+    }
+}
+
+/// Result of the search for STM32CubeProgrammer installations
+#[derive(Debug, Clone, Default)]
+pub struct Discovery {
+    installations: Vec<Installation>,
+}
+
+#[bon]
+impl Discovery {
+    /// Search for installations. The locations default to the ones of the current user and system
+    ///
+    /// # Arguments
+    /// home_dir: Home directory
+    /// installation_dirs: Directories which contain installations of STM32CubeProgrammer or STM32CubeIDE (e.g. `/opt/st`)
+    /// path: Search path in the format of the `PATH` environment variable
+    #[builder(finish_fn = search)]
+    pub fn new(
+        home_dir: Option<PathBuf>,
+        installation_dirs: Option<Vec<PathBuf>>,
+        path: Option<OsString>,
+    ) -> Self {
+        let home_dir = home_dir.or_else(default_home_dir);
+        let installation_dirs =
+            installation_dirs.unwrap_or_else(|| default_installation_dirs(home_dir.as_deref()));
+        let path = path.or_else(|| std::env::var_os("PATH"));
+
+        let mut candidates = Vec::new();
+
+        if let Some(home_dir) = &home_dir {
+            candidates.push((
+                home_dir.join(HOME_INSTALLATION_RELATIVE),
+                InstallationSource::HomeDirectory,
+            ));
+        }
+
+        for installation_dir in &installation_dirs {
+            for entry in read_dir(installation_dir) {
+                candidates.push((entry.clone(), InstallationSource::InstallationDirectory));
+
+                // STM32CubeIDE bundles STM32CubeProgrammer as plugin (Windows: `STM32CubeIDE_x.y.z/STM32CubeIDE/plugins`)
+                for plugins in [entry.join("plugins"), entry.join("STM32CubeIDE/plugins")] {
+                    candidates.extend(
+                        read_dir(&plugins)
+                            .into_iter()
+                            .filter(|x| is_cube_programmer_plugin(x))
+                            .map(|x| (x.join("tools"), InstallationSource::CubeIde)),
+                    );
+                }
+            }
+        }
+
+        if let Some(path) = &path {
+            candidates.extend(
+                std::env::split_paths(path)
+                    .filter(|x| x.join(CLI_NAME).is_file())
+                    .filter_map(|x| Some((x.parent()?.to_path_buf(), InstallationSource::Path))),
+            );
+        }
+
+        let mut installations: Vec<Installation> = Vec::new();
+
+        for (candidate, source) in candidates {
+            let Some(installation) = Installation::from_dir(&candidate, source) else {
+                continue;
+            };
+
+            // The same installation is often found multiple times (e.g. via the PATH)
+            if !installations.iter().any(|x| x.path == installation.path) {
+                installations.push(installation);
+            }
+        }
+
+        // The newest version first, installations with an unknown version last
+        installations.sort_by(|a, b| {
+            b.version
+                .cmp(&a.version)
+                .then_with(|| a.source.cmp(&b.source))
+        });
+
+        Self { installations }
+    }
+}
+
+impl Discovery {
+    /// Found installations, sorted by preference
+    pub fn installations(&self) -> &[Installation] {
+        &self.installations
+    }
+
+    /// Preferred installation: The newest version. Standalone installations are preferred over the ones bundled with STM32CubeIDE
+    pub fn preferred(&self) -> Option<&Installation> {
+        self.installations.first()
+    }
+}
+
+/// Search the default locations for the preferred installation
+pub fn preferred_installation() -> Option<Installation> {
+    Discovery::builder().search().preferred().cloned()
+}
+
+/// Check if the ST-LINK server is running on the local host, which is needed for shared probe connections
+pub fn stlink_server_running() -> bool {
+    let address = std::net::SocketAddr::from(([127, 0, 0, 1], STLINK_SERVER_PORT));
+    let running = std::net::TcpStream::connect_timeout(&address, STLINK_SERVER_TIMEOUT).is_ok();
+    debug!("ST-LINK server running: {}", running);
+    running
+}
+
+fn default_home_dir() -> Option<PathBuf> {
+    #[cfg(windows)]
+    let home = std::env::var_os("USERPROFILE");
+    #[cfg(not(windows))]
+    let home = std::env::var_os("HOME");
+
+    home.map(PathBuf::from)
+}
+
+#[cfg(not(windows))]
+fn default_installation_dirs(home_dir: Option<&Path>) -> Vec<PathBuf> {
+    let mut dirs = vec![PathBuf::from("/opt/st")];
+    dirs.extend(home_dir.map(|x| x.join("st")));
+    dirs
+}
+
+#[cfg(windows)]
+fn default_installation_dirs(_home_dir: Option<&Path>) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    dirs.extend(std::env::var_os("ProgramFiles").map(|x| {
+        PathBuf::from(x)
+            .join("STMicroelectronics")
+            .join("STM32Cube")
+    }));
+    dirs.push(PathBuf::from("C:\\ST"));
+    dirs
+}
+
+/// Entries of a directory. Unreadable directories are skipped
+fn read_dir(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut entries = entries
+        .filter_map(|x| x.ok())
+        .map(|x| x.path())
+        .filter(|x| x.is_dir())
+        .collect::<Vec<_>>();
+    entries.sort();
+    entries
+}
+
+/// e.g. `com.st.stm32cube.ide.mcu.externaltools.cubeprogrammer.linux64_2.2.0.202409170845`
+fn is_cube_programmer_plugin(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|x| x.to_str())
+        .is_some_and(|x| x.contains(".cubeprogrammer."))
+}
+
+/// Detect the version via the `STM32_Programmer_CLI` of the installation
+/// The result is cached per path, so the CLI is only started once per installation
+pub(crate) fn detect_version(path: &Path) -> Option<CubeProgrammerVersion> {
+    static VERSIONS: OnceLock<Mutex<HashMap<PathBuf, Option<CubeProgrammerVersion>>>> =
+        OnceLock::new();
+
+    let versions = VERSIONS.get_or_init(Default::default);
+
+    if let Some(version) = versions
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(path)
+    {
+        return *version;
+    }
+
+    let version = run_version_command(path);
+
+    versions
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(path.to_path_buf(), version);
+
+    version
+}
+
+/// Run `STM32_Programmer_CLI --version` and parse its output. A CLI which does not exit within [`VERSION_DETECTION_TIMEOUT`] is killed
+fn run_version_command(path: &Path) -> Option<CubeProgrammerVersion> {
+    let mut child = std::process::Command::new(path.join(PATH_LOADER_DIR_RELATIVE).join(CLI_NAME))
+        .arg("--version")
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .ok()?;
+
+    // The output is read on its own thread, so a full pipe does not block the CLI
+    let mut stdout = child.stdout.take()?;
+    let reader = std::thread::spawn(move || {
+        let mut output = String::new();
+        stdout.read_to_string(&mut output).map(|_| output)
+    });
+
+    let start = Instant::now();
+
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if start.elapsed() < VERSION_DETECTION_TIMEOUT => {
+                std::thread::sleep(std::time::Duration::from_millis(10))
+            }
+            result => {
+                warn!(
+                    "Cannot detect the version of {:?}: {}",
+                    path,
+                    result.err().map_or_else(
+                        || format!("No exit within {:?}", VERSION_DETECTION_TIMEOUT),
+                        |x| x.to_string()
+                    )
+                );
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+        }
+    }
+
+    parse_version(&reader.join().ok()?.ok()?)
+}
+
+/// Parse the version from the output of `STM32_Programmer_CLI --version` e.g. `STM32CubeProgrammer version: 2.18.0`
+fn parse_version(output: &str) -> Option<CubeProgrammerVersion> {
+    output
+        .lines()
+        .filter(|x| x.contains("STM32CubeProgrammer"))
+        .flat_map(|x| x.split_whitespace())
+        .find_map(|x| x.parse().ok())
+}
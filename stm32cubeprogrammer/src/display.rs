@@ -1,22 +1,344 @@
-use crate::LogMessageType;
+//! Progress and log messages of the CubeProgrammer API as typed events
+//!
+//! The API reports progress and log messages via global C callbacks. While an operation is executed, the operation and the probe of the connection are tracked per thread.
+//! The callbacks are mapped to a [`ProgrammerEvent`] which is tagged with this context and forwarded to the [`EventHandler`] and the subscribers of the [`crate::CubeProgrammer`] which executes the operation.
+//! This allows multiple CubeProgrammer instances and parallel sessions in the same process.
 
-use std::fmt::Debug;
-use std::sync::{Arc, Mutex, OnceLock};
+use crate::{
+    error::{Action, CubeProgrammerError, CubeProgrammerResult},
+    probe::{ConnectionMode, Serial},
+    LogMessageType,
+};
 
-static DISPLAY_CALLBACK_HANDLER: OnceLock<Arc<Mutex<dyn DisplayCallback>>> = OnceLock::new();
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::{Duration, Instant};
 
-/// Trait for display callback
-/// A library user can implement this trait to receive log messages and update progress bar
-pub trait DisplayCallback: Send + Sync + Debug {
-    fn init_progressbar(&self);
-    fn log_message(&self, message_type: LogMessageType, message: &str);
-    fn update_progressbar(&self, current_number: u64, total_number: u64);
+/// Result of a finished operation
+#[derive(Debug, Clone, PartialEq)]
+pub enum OperationResult {
+    Succeeded,
+    Cancelled,
+    Failed { message: String },
 }
 
-pub(crate) fn set_display_callback_handler(handler: Arc<Mutex<dyn DisplayCallback>>) {
-    DISPLAY_CALLBACK_HANDLER.set(handler).unwrap();
+impl<T> From<&CubeProgrammerResult<T>> for OperationResult {
+    fn from(value: &CubeProgrammerResult<T>) -> Self {
+        match value {
+            Ok(_) => Self::Succeeded,
+            Err(CubeProgrammerError::Cancelled { .. }) => Self::Cancelled,
+            Err(error) => Self::Failed {
+                message: error.to_string(),
+            },
+        }
+    }
 }
 
-pub(crate) fn get_display_callback_handler() -> Option<&'static Arc<Mutex<dyn DisplayCallback>>> {
-    DISPLAY_CALLBACK_HANDLER.get()
+/// Progress, log message or state of an operation
+#[derive(Debug, Clone)]
+pub enum EventKind {
+    /// The operation is started
+    OperationStarted,
+    /// A progress bar is started
+    ProgressStarted,
+    /// Progress of the operation
+    /// The unit of `current` and `total` is defined by the API (bytes for the download of a file). The rate and the estimated remaining time are calculated from the start of the progress bar
+    Progress {
+        current: u64,
+        total: u64,
+        bytes_per_sec: Option<f64>,
+        eta: Option<Duration>,
+    },
+    /// Log message of the API
+    Log {
+        message_type: LogMessageType,
+        message: String,
+    },
+    /// A failed or lost connection is retried according to the [`crate::probe::ReconnectPolicy`]
+    ReconnectAttempt {
+        /// Number of the attempt, starting at 1
+        attempt: u32,
+        max_attempts: u32,
+        connection_mode: ConnectionMode,
+        /// Delay before the attempt
+        delay: Duration,
+        /// Error of the previous attempt
+        error: String,
+    },
+    /// The connection is re-established
+    Reconnected {
+        /// Number of the successful attempt
+        attempt: u32,
+    },
+    /// The operation is finished
+    OperationFinished {
+        duration: Duration,
+        result: OperationResult,
+    },
+}
+
+/// Event which is reported during an operation
+#[derive(Debug, Clone)]
+pub struct ProgrammerEvent {
+    /// Serial of the probe of the connection. `None` for operations without a connection (e.g. listing the probes)
+    pub probe: Option<Serial>,
+    /// Operation which reports the event
+    pub action: Action,
+    pub kind: EventKind,
+}
+
+/// Handler for the events of a [`crate::CubeProgrammer`]
+/// The handler is called on the thread which executes the operation. It must not call the CubeProgrammer, as the API is locked during the operation
+///
+/// The trait is implemented for closures, e.g. `Arc::new(|event: &ProgrammerEvent| println!("{:?}", event))`
+pub trait EventHandler: Send + Sync {
+    fn handle_event(&self, event: &ProgrammerEvent);
+}
+
+impl<F: Fn(&ProgrammerEvent) + Send + Sync> EventHandler for F {
+    fn handle_event(&self, event: &ProgrammerEvent) {
+        self(event)
+    }
+}
+
+/// Receiver of the events, which is removed as soon as it is closed
+pub(crate) enum Subscriber {
+    Channel(std::sync::mpsc::Sender<ProgrammerEvent>),
+    #[cfg(feature = "async")]
+    Stream(futures_channel::mpsc::UnboundedSender<ProgrammerEvent>),
+}
+
+impl Subscriber {
+    /// Send the event. Returns false if the receiver is closed
+    fn send(&self, event: &ProgrammerEvent) -> bool {
+        match self {
+            Self::Channel(sender) => sender.send(event.clone()).is_ok(),
+            #[cfg(feature = "async")]
+            Self::Stream(sender) => sender.unbounded_send(event.clone()).is_ok(),
+        }
+    }
+}
+
+/// Handler and subscribers of a CubeProgrammer, which can be replaced or removed at runtime
+#[derive(Default)]
+pub(crate) struct EventSinks {
+    handler: RwLock<Option<Arc<dyn EventHandler>>>,
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+impl EventSinks {
+    pub(crate) fn new(handler: Option<Arc<dyn EventHandler>>) -> Self {
+        Self {
+            handler: RwLock::new(handler),
+            subscribers: Default::default(),
+        }
+    }
+
+    /// Set the handler and return the previous one
+    pub(crate) fn replace_handler(
+        &self,
+        handler: Option<Arc<dyn EventHandler>>,
+    ) -> Option<Arc<dyn EventHandler>> {
+        std::mem::replace(
+            &mut *self.handler.write().unwrap_or_else(PoisonError::into_inner),
+            handler,
+        )
+    }
+
+    pub(crate) fn subscribe(&self, subscriber: Subscriber) {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(subscriber);
+    }
+
+    fn send(&self, event: &ProgrammerEvent) {
+        let handler = self
+            .handler
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+
+        if let Some(handler) = handler {
+            handler.handle_event(event);
+        }
+
+        self.subscribers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|x| x.send(event));
+    }
+}
+
+/// Context of the operation which is executed on the current thread
+struct OperationContext {
+    sinks: Arc<EventSinks>,
+    probe: Option<Serial>,
+    action: Action,
+    /// Start time and start value of the current progress bar
+    progress_start: Option<(Instant, u64)>,
+}
+
+thread_local! {
+    static OPERATION: RefCell<Option<OperationContext>> = const { RefCell::new(None) };
+}
+
+/// Scope of an operation on the current thread. The context is cleared when the scope is dropped (also if the operation panics)
+struct OperationScope;
+
+impl Drop for OperationScope {
+    fn drop(&mut self) {
+        OPERATION.with(|operation| operation.borrow_mut().take());
+    }
+}
+
+/// Execute an operation and report its events to the given sinks
+/// Nested operations (e.g. reading memory while reading the FUS information) are reported as part of the outer operation
+pub(crate) fn operation<T>(
+    sinks: &Arc<EventSinks>,
+    probe: Option<Serial>,
+    action: Action,
+    f: impl FnOnce() -> CubeProgrammerResult<T>,
+) -> CubeProgrammerResult<T> {
+    let outer = OPERATION.with(|operation| {
+        let mut operation = operation.borrow_mut();
+
+        if operation.is_some() {
+            return false;
+        }
+
+        *operation = Some(OperationContext {
+            sinks: sinks.clone(),
+            probe: probe.clone(),
+            action,
+            progress_start: None,
+        });
+
+        true
+    });
+
+    if !outer {
+        return f();
+    }
+
+    let _scope = OperationScope;
+    let start = Instant::now();
+
+    #[cfg(feature = "tracing")]
+    let span = tracing::info_span!(
+        "operation",
+        action = %action,
+        probe = tracing::field::Empty,
+        device_id = tracing::field::Empty,
+        address = tracing::field::Empty,
+        size = tracing::field::Empty,
+        file = tracing::field::Empty,
+    );
+    #[cfg(feature = "tracing")]
+    let _span = span.enter();
+    #[cfg(feature = "tracing")]
+    if let Some(probe) = &probe {
+        span.record("probe", tracing::field::display(probe));
+    }
+
+    dispatch(EventKind::OperationStarted);
+
+    let result = f();
+
+    dispatch(EventKind::OperationFinished {
+        duration: start.elapsed(),
+        result: OperationResult::from(&result),
+    });
+
+    result
+}
+
+/// Record a field of the span of the current operation (feature `tracing`): `device_id`, `address`, `size` or `file`
+#[allow(unused_variables)]
+pub(crate) fn record(field: &'static str, value: impl std::fmt::Display) {
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record(field, tracing::field::display(value));
+}
+
+/// A progress bar is started by the API
+pub(crate) fn progress_started() {
+    with_context(|operation| operation.progress_start = Some((Instant::now(), 0)));
+    dispatch(EventKind::ProgressStarted);
+}
+
+/// Progress is reported by the API
+pub(crate) fn progress(current: u64, total: u64) {
+    let rate = with_context(|operation| {
+        let (start, start_value) = *operation
+            .progress_start
+            .get_or_insert((Instant::now(), current));
+
+        let elapsed = start.elapsed().as_secs_f64();
+        let done = current.saturating_sub(start_value);
+
+        (elapsed > 0.0 && done > 0).then(|| done as f64 / elapsed)
+    })
+    .flatten();
+
+    let eta = rate.map(|rate| Duration::from_secs_f64(total.saturating_sub(current) as f64 / rate));
+
+    dispatch(EventKind::Progress {
+        current,
+        total,
+        bytes_per_sec: rate,
+        eta,
+    });
+}
+
+/// A message is logged by the API
+pub(crate) fn log(message_type: LogMessageType, message: String) {
+    dispatch(EventKind::Log {
+        message_type,
+        message,
+    });
+}
+
+/// A failed or lost connection is retried
+pub(crate) fn reconnect_attempt(
+    attempt: u32,
+    max_attempts: u32,
+    connection_mode: ConnectionMode,
+    delay: Duration,
+    error: &CubeProgrammerError,
+) {
+    dispatch(EventKind::ReconnectAttempt {
+        attempt,
+        max_attempts,
+        connection_mode,
+        delay,
+        error: error.to_string(),
+    });
+}
+
+/// The connection is re-established
+pub(crate) fn reconnected(attempt: u32) {
+    dispatch(EventKind::Reconnected { attempt });
+}
+
+fn with_context<T>(f: impl FnOnce(&mut OperationContext) -> T) -> Option<T> {
+    OPERATION.with(|operation| operation.borrow_mut().as_mut().map(f))
+}
+
+/// Forward an event to the sinks of the operation which is executed on the current thread
+/// Events outside of an operation are dropped
+fn dispatch(kind: EventKind) {
+    let Some((sinks, event)) = with_context(|operation| {
+        (
+            operation.sinks.clone(),
+            ProgrammerEvent {
+                probe: operation.probe.clone(),
+                action: operation.action,
+                kind,
+            },
+        )
+    }) else {
+        return;
+    };
+
+    sinks.send(&event);
 }
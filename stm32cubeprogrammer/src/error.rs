@@ -1,9 +1,13 @@
 use derive_more::{Display, Error};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 pub type CubeProgrammerResult<T> = std::result::Result<T, CubeProgrammerError>;
 
 /// Add additional context why a type conversion failed
-#[derive(Debug, Error, Display)]
+#[derive(Debug, Clone, Copy, PartialEq, Error, Display)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub enum TypeConversionError {
     Utf8Error,
     Utf16Error,
@@ -12,7 +16,9 @@ pub enum TypeConversionError {
     VersionError,
 }
 
-#[derive(Debug, Error, Display)]
+#[derive(Debug, Clone, Copy, PartialEq, Error, Display)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub enum Action {
     Connect,
     ReadTargetInfo,
@@ -30,12 +36,24 @@ pub enum Action {
     UpgradeWirelessStack,
     DeleteWirelessStack,
     StartWirelessStack,
+    ActivateAntiRollback,
     ListConnectedProbes,
     WriteCoreRegister,
     ReadCoreRegister,
+    ReadUid64,
+    ReadUid96,
+    ReadOptionBytes,
+    WriteOptionBytes,
+    TrustZoneRegression,
+    ProgramSsp,
+    ReadHsmStatus,
+    ExportHsmLicense,
+    ReadTargetVoltage,
 }
 
-#[derive(Debug, Error, Display)]
+#[derive(Debug, Clone, Copy, PartialEq, Error, Display)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "camelCase"))]
 pub enum UnexpectedOutput {
     Null,
     SliceConversion,
@@ -44,16 +62,30 @@ pub enum UnexpectedOutput {
 
 #[derive(Debug, Error, Display)]
 pub enum CubeProgrammerError {
-    #[display("Action {} failed with return code: {}", action, return_code)]
+    /// The messages are the errors and warnings which the API logged during the failed call (see [`CubeProgrammerError::messages`])
+    #[display(
+        "Action {} failed with return code: {}{}",
+        action,
+        return_code,
+        MessagesDisplay(messages)
+    )]
     ActionFailed {
         action: Action,
         return_code: crate::api_types::ErrorCode,
+        messages: Vec<String>,
     },
 
-    #[display("Action {} returns unexpected output: {}", action, unexpected_output)]
+    /// The messages are the errors and warnings which the API logged during the failed call (see [`CubeProgrammerError::messages`])
+    #[display(
+        "Action {} returns unexpected output: {}{}",
+        action,
+        unexpected_output,
+        MessagesDisplay(messages)
+    )]
     ActionOutputUnexpected {
         action: Action,
         unexpected_output: UnexpectedOutput,
+        messages: Vec<String>,
     },
 
     #[display("Action {} not supported: {}", action, message)]
@@ -62,6 +94,38 @@ pub enum CubeProgrammerError {
         message: String,
     },
 
+    #[display(
+        "STM32CubeProgrammer version {} is not supported. Supported versions: {} to {} (exclusive)",
+        version,
+        crate::api_types::CubeProgrammerVersion::SUPPORTED.start,
+        crate::api_types::CubeProgrammerVersion::SUPPORTED.end
+    )]
+    UnsupportedVersion {
+        version: crate::api_types::CubeProgrammerVersion,
+    },
+
+    #[display(
+        "Target voltage of probe {} is {} mV, which is below the minimum of {} mV. Is the target powered?",
+        probe,
+        voltage_mv,
+        min_voltage_mv
+    )]
+    TargetVoltageTooLow {
+        probe: crate::probe::Serial,
+        voltage_mv: u32,
+        min_voltage_mv: u32,
+    },
+
+    #[display("Shared connection via probe {} needs a running ST-LINK server", probe)]
+    StlinkServerNotRunning {
+        probe: crate::probe::Serial,
+    },
+
+    #[display("Action {} was cancelled", action)]
+    Cancelled {
+        action: Action,
+    },
+
     #[display("Parameter error: {}", message)]
     Parameter {
         action: Action,
@@ -78,9 +142,71 @@ pub enum CubeProgrammerError {
 
     FileIo(std::io::Error),
 
+    #[display("Cannot spawn the worker thread of the async API: {}", _0)]
+    WorkerSpawn(std::io::Error),
+
+    #[display("Worker thread of the async API stopped")]
+    WorkerStopped,
+
     LibLoading(stm32cubeprogrammer_sys::libloading::Error),
 
     MissingDllSymbol {
         message: String,
     },
 }
+
+impl CubeProgrammerError {
+    /// Attach the error and warning messages which the API logged during the call to the errors of the API output
+    /// Other errors are not caused by the API and are returned unchanged
+    pub(crate) fn with_api_messages(mut self) -> Self {
+        let captured = crate::api_log::take_captured_messages();
+
+        match &mut self {
+            Self::ActionFailed { messages, .. } | Self::ActionOutputUnexpected { messages, .. }
+                if messages.is_empty() =>
+            {
+                *messages = captured;
+            }
+            _ => {}
+        }
+
+        self
+    }
+
+    /// Check if the error is caused by a failed or lost connection to the target (e.g. no response at the selected frequency), which may succeed on a retry
+    pub fn is_connection_failure(&self) -> bool {
+        matches!(
+            self,
+            Self::ActionFailed {
+                action: Action::Connect | Action::CheckConnection,
+                ..
+            } | Self::ActionOutputUnexpected {
+                action: Action::ReadTargetInfo,
+                ..
+            }
+        )
+    }
+
+    /// Error and warning messages which the API logged during the failed call
+    pub fn messages(&self) -> &[String] {
+        match self {
+            Self::ActionFailed { messages, .. } | Self::ActionOutputUnexpected { messages, .. } => {
+                messages
+            }
+            _ => &[],
+        }
+    }
+}
+
+/// Display the API messages of an error as context
+struct MessagesDisplay<'a>(&'a [String]);
+
+impl std::fmt::Display for MessagesDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.0.is_empty() {
+            write!(f, " ({})", self.0.join("; "))?;
+        }
+
+        Ok(())
+    }
+}
@@ -63,16 +63,37 @@
 //! More examples can be found in the `tests` directory.
 //!
 //! # Supported features:
+//! - Listing the connected probes with board, firmware version, target voltage and supported frequencies ([`probe::ProbeInfo`])
 //! - Downloading files as hex or bin
 //! - Reading and writing memory
 //!     - Uses the [`bytemuck::Pod`](https://docs.rs/bytemuck/1.21.0/bytemuck/trait.Pod.html) trait for reading and writing data from/to memory
 //! - Reading and writing of core registers
+//! - Shared probe connections via the ST-LINK server, so that e.g. a debug session of STM32CubeIDE and this crate can use the same probe. The ST-LINK server is installed separately or together with STM32CubeIDE and is detected via [`discovery::stlink_server_running`]
+//! - Debug support in low-power modes and selection of the speed mode of the probe. The effective parameters of a connection are reported as [`probe::ConnectionInfo`]
+//! - Automatic fallback to the lower frequencies of the probe if a connection fails ([`probe::ConnectionParameters::frequency_fallback`])
+//! - Retries of failed connections and transparent re-establishment of lost connections with backoff and escalation of the connection mode ([`probe::ReconnectPolicy`]). Each attempt is reported as [`EventKind::ReconnectAttempt`]
+//! - Selection of the access port to connect to a specific core of multi-core devices (e.g. stm32h745, stm32wl5x and stm32mp1). The connections to the cores of one probe can be used in parallel
+//! - Cancelling long-running operations (e.g. a wireless stack upgrade) from another thread via the [`CancellationToken`]
+//! - Reading the unique device identifiers (UID64 and 96 bit UID)
 //! - Resetting the target
 //! - Enabling and disabling readout protection (Level B)
+//! - Reading the TrustZone state and TrustZone regression (only for devices with a TZEN option bit e.g. stm32l5 and stm32u5)
 //! - Reset target
 //! - Mass erase
+//! - Secure Secret Provisioning (only for stm32mp1)
+//! - Querying the status of a hardware secure module (HSM) and exporting its license
 //! - FUS operations (only for stm32wb55xx)
-//! - Log messages of the CubeProgrammer DLL are forwarded via the [`display::DisplayCallback`] trait
+//!     - Activating the anti-rollback of the wireless stack
+//! - Start, progress (with rate and estimated remaining time), log messages and result of each operation are reported as [`ProgrammerEvent`]s, tagged with the probe and the operation, to the [`EventHandler`] and the subscribed channels of each [`CubeProgrammer`]
+//! - Error and warning messages which the CubeProgrammer DLL logs during a failed call are attached to the returned [`error::CubeProgrammerError`] (see [`error::CubeProgrammerError::messages`])
+//! - Discovery of STM32CubeProgrammer installations (standalone and bundled with STM32CubeIDE) and their versions via the [`discovery`] module
+//! - Only the functions which are needed to connect to a target are required in the CubeProgrammer DLL. The available functions and features are reported as [`capabilities::Capabilities`], methods which need a missing function return [`error::CubeProgrammerError::ActionNotSupported`]
+//! - Detection of the loaded STM32CubeProgrammer version ([`CubeProgrammer::api_version`]). Unsupported versions are reported as configured via [`api_types::VersionPolicy`] and API functions which are missing in older versions return [`error::CubeProgrammerError::ActionNotSupported`]
+//! - All calls to the CubeProgrammer DLL go through the [`backend::Backend`] trait. The [`backend::SimulatedBackend`] simulates a target in-process for testing without hardware
+//! - Recording of all API calls to a JSON-lines trace and replaying a trace (feature `recording`)
+//! - [`CubeProgrammer`] and its connections can be shared and moved between threads. The access to the API is serialized, as the DLL only has one global connection
+//! - Async API which runs the blocking calls on a dedicated worker thread and delivers progress as a stream of events (feature `async`)
+//! - Tracing spans for the operations with the probe, device id, address, size and file as fields. The messages of the CubeProgrammer DLL are emitted as events inside the spans (feature `tracing`)
 //!
 //! If there is a feature missing, feel free to open an issue or a pull request. :smile:
 //!
@@ -84,6 +105,8 @@
 //! ```
 //! A list of the expected environment variables can be found in the `test_common.rs` file.
 //!
+//! Tests which use the [`backend::SimulatedBackend`] run without a STM32CubeProgrammer installation and without hardware.
+//!
 //! # Other crates similar to this one
 //! When I was looking for a rust API for the STM32CubeProgrammer DLL, I found [this](https://github.com/wervin/stm32cubeprog-rs) crate.
 //! Playing around with it, I got interested in the topic and decided to try writing my own version. :rocket:
@@ -100,17 +123,33 @@ pub mod api_log;
 pub use api_log::{LogMessageType, Verbosity};
 
 pub mod api_types;
-pub use api_types::{fus, probe, CoreRegister, GeneralInformation};
+pub use api_types::{
+    fus, hsm, probe, ssp, uid, Confirmation, CoreRegister, GeneralInformation, TrustZoneState,
+};
+
+pub mod backend;
+pub use backend::Backend;
 
 pub mod display;
-pub use display::DisplayCallback;
+pub use display::{EventHandler, EventKind, OperationResult, ProgrammerEvent};
 
 pub mod cube_programmer;
-pub use cube_programmer::{ConnectedFusProgrammer, ConnectedProgrammer, CubeProgrammer};
+pub use cube_programmer::{
+    CancellationToken, ConnectedFusProgrammer, ConnectedProgrammer, CubeProgrammer, Hsm,
+};
 
+pub mod capabilities;
+pub mod discovery;
 pub mod error;
 pub mod utility;
 
+#[cfg(feature = "async")]
+pub mod async_programmer;
+#[cfg(feature = "async")]
+pub use async_programmer::{
+    AsyncConnectedFusProgrammer, AsyncConnectedProgrammer, AsyncCubeProgrammer,
+};
+
 // Re-export of the `bytemuck` crate -> needed for reading/writing of structs from/to memory
 pub use bytemuck;
 
@@ -1,6 +1,7 @@
 use core::str;
 use std::ffi::{c_char, CString};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
 
 use crate::error::{CubeProgrammerError, CubeProgrammerResult, TypeConversionError};
 
@@ -78,18 +79,6 @@ pub(crate) fn string_to_widestring(s: &str) -> CubeProgrammerResult<widestring::
     })
 }
 
-/// Convert a wide cstring to a string
-pub(crate) fn widestring_to_string(
-    wide_string: &widestring::WideCString,
-) -> CubeProgrammerResult<String> {
-    wide_string
-        .to_string()
-        .map_err(|x| CubeProgrammerError::TypeConversion {
-            message: format!("Cannot convert widestring to string: {:?}", x),
-            source: TypeConversionError::Utf16Error,
-        })
-}
-
 /// Convert a c_char slice to a null-terminated string
 pub(crate) fn c_char_slice_to_string(slice: &[c_char]) -> CubeProgrammerResult<&str> {
     str::from_utf8(bytemuck::cast_slice(slice)).map_err(|x| CubeProgrammerError::TypeConversion {
@@ -98,6 +87,42 @@ pub(crate) fn c_char_slice_to_string(slice: &[c_char]) -> CubeProgrammerResult<&
     })
 }
 
+/// Convert a c_char slice to an owned string without the null terminators. Invalid strings are converted to an empty string
+#[cfg(feature = "recording")]
+pub(crate) fn c_char_slice_to_owned_string(slice: &[c_char]) -> String {
+    c_char_slice_to_string(slice)
+        .unwrap_or_default()
+        .trim_matches('\0')
+        .to_string()
+}
+
+/// Copy a string to a null-terminated c_char slice. Strings which are too long are truncated
+pub(crate) fn copy_to_c_char_slice(destination: &mut [c_char], value: &str) {
+    // Keep the last element as null terminator
+    let length = value.len().min(destination.len().saturating_sub(1));
+
+    for (destination, source) in destination.iter_mut().zip(&value.as_bytes()[..length]) {
+        *destination = *source as c_char;
+    }
+}
+
+/// Iterate over a C array of pointers with a given length. Null pointers are skipped
+///
+/// # Safety
+/// The caller has to make sure that `ptr` points to `len` valid pointers and that the pointed-to data outlives `'a`
+pub(crate) unsafe fn iter_ptr_array<'a, T: 'a>(
+    ptr: *const *mut T,
+    len: u32,
+) -> impl Iterator<Item = &'a T> {
+    let slice: &[*mut T] = if ptr.is_null() || len == 0 {
+        &[]
+    } else {
+        unsafe { std::slice::from_raw_parts(ptr, len as _) }
+    };
+
+    slice.iter().filter_map(|x| unsafe { x.as_ref() })
+}
+
 /// Convert a string to a cstring
 pub(crate) fn string_to_cstring(s: &str) -> CubeProgrammerResult<CString> {
     CString::new(s).map_err(|x| CubeProgrammerError::TypeConversion {
@@ -106,6 +131,36 @@ pub(crate) fn string_to_cstring(s: &str) -> CubeProgrammerResult<CString> {
     })
 }
 
+/// File in the temporary directory which is removed when dropped
+pub(crate) struct TemporaryFile(PathBuf);
+
+impl TemporaryFile {
+    /// Create a file with the given content. The name is unique within the process
+    pub(crate) fn create(name: &str, content: &[u8]) -> CubeProgrammerResult<Self> {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "stm32cubeprogrammer-{}-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            name
+        ));
+        std::fs::write(&path, content).map_err(CubeProgrammerError::FileIo)?;
+
+        Ok(Self(path))
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TemporaryFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
 /// Check if the target supports the FUS
 pub(crate) fn target_supports_fus(target_name: &str) -> bool {
     // TODO: Add support for wb1x
@@ -0,0 +1,179 @@
+//! Tests of the async API which use the simulated backend
+#![cfg(feature = "async")]
+
+use futures::{FutureExt, StreamExt};
+use stm32cubeprogrammer::{
+    backend::{simulated::SimulatedTarget, SimulatedBackend},
+    error::{Action, CubeProgrammerError},
+    probe::{ConnectionParameters, Protocol, ResetMode},
+    AsyncCubeProgrammer, Confirmation, CoreRegister, EventKind,
+};
+
+fn init_programmer(target: SimulatedTarget) -> (AsyncCubeProgrammer, SimulatedBackend) {
+    let backend = SimulatedBackend::new(target);
+
+    let programmer = AsyncCubeProgrammer::with_backend()
+        .backend(Box::new(backend.clone()))
+        .build()
+        .unwrap();
+
+    (programmer, backend)
+}
+
+/// Write the content to a temporary file with the given name
+fn temp_file(name: &str, content: &[u8]) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "stm32cubeprogrammer-async-{}-{}",
+        std::process::id(),
+        name
+    ));
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+#[test_log::test(tokio::test)]
+async fn download_with_progress_events() {
+    let (programmer, backend) = init_programmer(SimulatedTarget::stm32wb55());
+    let mut events = programmer.events();
+
+    let probes = programmer.list_available_probes().await.unwrap();
+    let connected = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+        .await
+        .unwrap();
+    assert_eq!(connected.general_information().device_id, 0x495);
+
+    let flash_address = stm32cubeprogrammer_sys::FLASH_BASE_ADDRESS;
+
+    // Vector table with stack pointer and reset handler
+    let firmware = [0x2000_4000u32.to_le_bytes(), 0x0800_0101u32.to_le_bytes()].concat();
+    let file = temp_file("firmware.bin", &firmware);
+
+    connected
+        .download_bin_file(&file, flash_address, false, true)
+        .await
+        .unwrap();
+    assert_eq!(backend.flash()[..firmware.len()], firmware);
+    std::fs::remove_file(file).unwrap();
+
+    // The events are sent on the worker thread before the future resolves
+    let mut received = Vec::new();
+    while let Some(Some(event)) = events.next().now_or_never() {
+        received.push(event);
+    }
+
+    // The events are tagged with the probe and the operation
+    assert!(received
+        .iter()
+        .all(|x| x.probe.is_none() || x.probe == Some(probes[0].clone())));
+    let download = received
+        .iter()
+        .filter(|x| x.action == Action::DownloadFile)
+        .collect::<Vec<_>>();
+    assert!(download
+        .iter()
+        .any(|x| matches!(x.kind, EventKind::ProgressStarted)));
+    assert!(download
+        .iter()
+        .any(|x| matches!(x.kind, EventKind::Progress { current, total, .. } if current == total)));
+
+    connected.reset_target(ResetMode::Hardware).await.unwrap();
+    assert_eq!(
+        connected
+            .read_core_register(CoreRegister::PC)
+            .await
+            .unwrap(),
+        0x0800_0100
+    );
+
+    connected.mass_erase().await.unwrap();
+    assert!(backend.flash().iter().all(|x| *x == 0xFF));
+
+    connected.disconnect().await;
+    assert_eq!(backend.connected_probe(), None);
+}
+
+#[test_log::test(tokio::test)]
+async fn read_and_write_memory() {
+    let (programmer, _) = init_programmer(SimulatedTarget::stm32wb55());
+
+    let probes = programmer.list_available_probes().await.unwrap();
+    let connected = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+        .await
+        .unwrap();
+
+    let address = stm32cubeprogrammer_sys::SRAM_BASE_ADDRESS;
+
+    // Operations are executed in the order in which they are queued
+    let write = connected.write_memory::<u32>(address, &[0x12345678, 0x9ABCDEF0]);
+    let read = connected.read_memory::<u32>(address, 2);
+    drop(connected);
+
+    assert!(write.await.is_ok());
+    assert_eq!(read.await.unwrap(), [0x12345678, 0x9ABCDEF0]);
+
+    // The connection is closed on the worker thread and the probe is available again
+    assert_eq!(programmer.list_available_probes().await.unwrap(), probes);
+}
+
+#[test_log::test(tokio::test)]
+async fn fus_operations() {
+    let (programmer, backend) = init_programmer(SimulatedTarget::stm32wb55());
+
+    let probes = programmer.list_available_probes().await.unwrap();
+    let connected = programmer
+        .connect_to_target_fus(&probes[0], &Protocol::Swd)
+        .await
+        .unwrap();
+
+    assert_eq!(connected.fus_info().await.unwrap().device_id, 0x495);
+
+    assert!(matches!(
+        connected
+            .activate_anti_rollback(Confirmation::NotConfirmed)
+            .await,
+        Err(CubeProgrammerError::Parameter {
+            action: Action::ActivateAntiRollback,
+            ..
+        })
+    ));
+
+    connected
+        .activate_anti_rollback(Confirmation::Confirmed)
+        .await
+        .unwrap();
+    assert!(backend.anti_rollback_active());
+
+    let stack = temp_file("stack.bin", &[0x5A; 0x100]);
+    connected
+        .upgrade_wireless_stack(&stack, 0x080C_0000, false, true, false)
+        .await
+        .unwrap();
+    std::fs::remove_file(stack).unwrap();
+
+    connected.disconnect().await;
+
+    let connected = programmer
+        .connect_to_target_fus(&probes[0], &Protocol::Swd)
+        .await
+        .unwrap();
+    assert_eq!(
+        connected.fus_info().await.unwrap().wireless_stack_version,
+        SimulatedTarget::stm32wb55()
+            .fus
+            .unwrap()
+            .upgrade_wireless_stack_version
+    );
+}
+
+#[test_log::test(tokio::test)]
+async fn futures_can_be_spawned() {
+    let (programmer, _) = init_programmer(SimulatedTarget::stm32wb55());
+
+    let probes = tokio::spawn(programmer.list_available_probes())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(probes.len(), 1);
+}
@@ -0,0 +1,146 @@
+//! Tests of the discovery of STM32CubeProgrammer installations in a temporary directory tree
+#![cfg(unix)]
+
+use std::{os::unix::fs::PermissionsExt, path::Path};
+use stm32cubeprogrammer::{
+    api_types::CubeProgrammerVersion,
+    discovery::{Discovery, Installation, InstallationSource},
+};
+use stm32cubeprogrammer_sys::{PATH_API_LIBRARY_RELATIVE, PATH_LOADER_DIR_RELATIVE};
+
+/// Create an installation with a `STM32_Programmer_CLI` which prints the given output for `--version`
+fn create_installation(path: &Path, version_output: &str) {
+    let api_library = path.join(PATH_API_LIBRARY_RELATIVE);
+    std::fs::create_dir_all(api_library.parent().unwrap()).unwrap();
+    std::fs::write(api_library, []).unwrap();
+
+    let cli = path
+        .join(PATH_LOADER_DIR_RELATIVE)
+        .join("STM32_Programmer_CLI");
+    std::fs::create_dir_all(cli.parent().unwrap()).unwrap();
+    std::fs::write(&cli, format!("#!/bin/sh\necho \"{}\"\n", version_output)).unwrap();
+    std::fs::set_permissions(&cli, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test_log::test]
+fn find_installations() {
+    let root = std::env::temp_dir().join(format!(
+        "stm32cubeprogrammer-discovery-{}",
+        std::process::id()
+    ));
+
+    let home = root.join("home");
+    let opt = root.join("opt");
+
+    let home_installation = home.join("STMicroelectronics/STM32Cube/STM32CubeProgrammer");
+    create_installation(
+        &home_installation,
+        "      STM32CubeProgrammer version: 2.17.0      ",
+    );
+
+    let cube_ide_installation = opt.join(
+        "stm32cubeide_1.16.1/plugins/com.st.stm32cube.ide.mcu.externaltools.cubeprogrammer.linux64_2.2.0.202409170845/tools",
+    );
+    create_installation(&cube_ide_installation, "STM32CubeProgrammer v2.18.0");
+
+    // Unknown version and a directory which is no installation
+    let other_installation = root.join("other");
+    create_installation(&other_installation, "");
+    std::fs::create_dir_all(opt.join("stm32cubemx")).unwrap();
+
+    // The home installation is also in the PATH
+    let path = std::env::join_paths([
+        home_installation.join(PATH_LOADER_DIR_RELATIVE),
+        other_installation.join(PATH_LOADER_DIR_RELATIVE),
+        root.join("missing"),
+    ])
+    .unwrap();
+
+    let discovery = Discovery::builder()
+        .home_dir(home)
+        .installation_dirs(vec![opt])
+        .path(path)
+        .search();
+
+    let installations = discovery
+        .installations()
+        .iter()
+        .map(|x| (x.path.clone(), x.version, x.source))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        installations,
+        [
+            (
+                cube_ide_installation.canonicalize().unwrap(),
+                Some(CubeProgrammerVersion::new(2, 18, 0)),
+                InstallationSource::CubeIde
+            ),
+            (
+                home_installation.canonicalize().unwrap(),
+                Some(CubeProgrammerVersion::new(2, 17, 0)),
+                InstallationSource::HomeDirectory
+            ),
+            (
+                other_installation.canonicalize().unwrap(),
+                None,
+                InstallationSource::Path
+            ),
+        ]
+    );
+
+    assert_eq!(
+        discovery.preferred().unwrap().path,
+        cube_ide_installation.canonicalize().unwrap()
+    );
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test_log::test]
+fn hanging_version_detection() {
+    let root = std::env::temp_dir().join(format!(
+        "stm32cubeprogrammer-discovery-hanging-{}",
+        std::process::id()
+    ));
+
+    // The CLI does not exit and is killed after the timeout
+    create_installation(&root, "STM32CubeProgrammer v2.18.0\"; sleep 60; echo \"");
+    let start = std::time::Instant::now();
+    let installation = Installation::from_dir(&root, InstallationSource::Path).unwrap();
+    assert_eq!(installation.version, None);
+    assert!(start.elapsed() < std::time::Duration::from_secs(30));
+
+    // The result is cached per path
+    create_installation(&root, "STM32CubeProgrammer v2.18.0");
+    let installation = Installation::from_dir(&root, InstallationSource::Path).unwrap();
+    assert_eq!(installation.version, None);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test_log::test]
+fn no_installations() {
+    let discovery = Discovery::builder()
+        .home_dir(std::env::temp_dir().join("stm32cubeprogrammer-discovery-missing"))
+        .installation_dirs(vec![])
+        .path("".into())
+        .search();
+
+    assert!(discovery.installations().is_empty());
+    assert!(discovery.preferred().is_none());
+}
+
+#[test]
+fn parse_version() {
+    assert_eq!(
+        "2.18.0".parse::<CubeProgrammerVersion>().unwrap(),
+        CubeProgrammerVersion::new(2, 18, 0)
+    );
+    assert_eq!(
+        "v2.9".parse::<CubeProgrammerVersion>().unwrap(),
+        CubeProgrammerVersion::new(2, 9, 0)
+    );
+    assert!("2.x.0".parse::<CubeProgrammerVersion>().is_err());
+    assert!(CubeProgrammerVersion::new(2, 18, 0) > CubeProgrammerVersion::new(2, 9, 1));
+}
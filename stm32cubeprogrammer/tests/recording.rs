@@ -0,0 +1,302 @@
+//! Tests which record the calls to the simulated backend and replay the trace
+#![cfg(feature = "recording")]
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use stm32cubeprogrammer::{
+    api_log::{DisplayCallbacks, LogMessageType},
+    api_types::{CubeProgrammerVersion, VersionPolicy},
+    backend::{
+        recording::{Header, Record, RecordedError, RecordedResult},
+        simulated::SimulatedTarget,
+        Backend, RecordingBackend, ReplayBackend, SimulatedBackend,
+    },
+    capabilities::{Capabilities, Feature, REQUIRED_SYMBOLS},
+    error::{Action, CubeProgrammerError},
+    probe::{ConnectionParameters, Protocol, ResetMode},
+    CoreRegister, CubeProgrammer,
+};
+
+/// Path of a temporary file with the given name
+fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!(
+        "stm32cubeprogrammer-recording-{}-{}",
+        std::process::id(),
+        name
+    ))
+}
+
+/// Program a firmware and read back the state of the target
+fn session(programmer: &CubeProgrammer, firmware: &std::path::Path) -> (Vec<u8>, u32, bool) {
+    let probes = programmer.list_available_probes().unwrap();
+    let connected = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+        .unwrap();
+
+    let flash_address = stm32cubeprogrammer_sys::FLASH_BASE_ADDRESS;
+    connected
+        .download_bin_file(firmware, flash_address, false, true)
+        .unwrap();
+    connected.reset_target(ResetMode::Hardware).unwrap();
+
+    let flash = connected.read_memory::<u8>(flash_address, 8).unwrap();
+    let pc = connected.read_core_register(CoreRegister::PC).unwrap();
+
+    // Flash memory can only be programmed if it is erased
+    let write_failed = matches!(
+        connected.write_memory::<u8>(flash_address, &[0xAB]),
+        Err(CubeProgrammerError::ActionFailed {
+            action: Action::WriteMemory,
+            ..
+        })
+    );
+
+    (flash, pc, write_failed)
+}
+
+#[test_log::test]
+fn record_and_replay() {
+    let firmware = temp_path("firmware.bin");
+    std::fs::write(
+        &firmware,
+        [0x2000_4000u32.to_le_bytes(), 0x0800_0101u32.to_le_bytes()].concat(),
+    )
+    .unwrap();
+
+    let trace = temp_path("trace.jsonl");
+
+    // Record
+    let recorded = {
+        let backend =
+            RecordingBackend::create(SimulatedBackend::new(SimulatedTarget::stm32wb55()), &trace)
+                .unwrap();
+        let programmer = CubeProgrammer::with_backend()
+            .backend(Box::new(backend))
+            .build()
+            .unwrap();
+
+        session(&programmer, &firmware)
+    };
+
+    assert_eq!(recorded.1, 0x0800_0100);
+    assert!(recorded.2);
+
+    // The first line is the header
+    let records = std::fs::read_to_string(&trace)
+        .unwrap()
+        .lines()
+        .skip(1)
+        .map(|line| serde_json::from_str::<Record>(line).unwrap())
+        .collect::<Vec<_>>();
+
+    let download = records
+        .iter()
+        .find(|x| x.function == "downloadFile")
+        .unwrap();
+    assert!(!download.messages.is_empty());
+
+    let write = records
+        .iter()
+        .find(|x| x.function == "writeMemory")
+        .unwrap();
+    assert!(matches!(write.result, RecordedResult::Err(_)));
+
+    // Replay without the firmware file and without the simulation
+    std::fs::remove_file(&firmware).unwrap();
+
+    let replay = ReplayBackend::open(&trace).unwrap();
+    let programmer = CubeProgrammer::with_backend()
+        .backend(Box::new(replay))
+        .build()
+        .unwrap();
+
+    assert_eq!(session(&programmer, &firmware), recorded);
+
+    // The trace is exhausted, further calls fail
+    assert!(matches!(
+        programmer.list_available_probes(),
+        Err(CubeProgrammerError::Parameter {
+            action: Action::ListConnectedProbes,
+            ..
+        })
+    ));
+
+    std::fs::remove_file(trace).unwrap();
+}
+
+/// Display callbacks which count the log messages
+#[derive(Default)]
+struct CountingCallbacks(AtomicUsize);
+
+impl DisplayCallbacks for CountingCallbacks {
+    fn init_progress_bar(&self) {}
+
+    fn log_message(&self, _message_type: LogMessageType, _message: &str) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn load_bar(&self, _current: i32, _total: i32) {}
+}
+
+#[test_log::test]
+fn record_messages_per_instance() {
+    let first_trace = temp_path("first.jsonl");
+    let second_trace = temp_path("second.jsonl");
+
+    let first = RecordingBackend::create(
+        SimulatedBackend::new(SimulatedTarget::stm32wb55()),
+        &first_trace,
+    )
+    .unwrap();
+    let second = RecordingBackend::create(
+        SimulatedBackend::new(SimulatedTarget::stm32wb55()),
+        &second_trace,
+    )
+    .unwrap();
+
+    let first_callbacks = Arc::new(CountingCallbacks::default());
+    let second_callbacks = Arc::new(CountingCallbacks::default());
+    first.set_display_callbacks(first_callbacks.clone());
+    second.set_display_callbacks(second_callbacks.clone());
+
+    let probes = first.list_probes().unwrap();
+    first.connect(&probes[0]).unwrap();
+    first.mass_erase().unwrap();
+
+    // The messages of the first backend are neither forwarded to nor recorded by the second backend
+    assert!(first_callbacks.0.load(Ordering::Relaxed) > 0);
+    assert_eq!(second_callbacks.0.load(Ordering::Relaxed), 0);
+
+    second.list_probes().unwrap();
+
+    let records = |trace: &std::path::Path| {
+        std::fs::read_to_string(trace)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|line| serde_json::from_str::<Record>(line).unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    let first_records = records(&first_trace);
+    let mass_erase = first_records
+        .iter()
+        .find(|x| x.function == "massErase")
+        .unwrap();
+    assert!(!mass_erase.messages.is_empty());
+    assert!(records(&second_trace).iter().all(|x| x.messages.is_empty()));
+
+    std::fs::remove_file(first_trace).unwrap();
+    std::fs::remove_file(second_trace).unwrap();
+}
+
+#[test_log::test]
+fn replay_mismatch() {
+    let trace = r#"
+{"function":"getStLinkList","arguments":null,"result":{"ok":[]}}
+{"function":"readMemory","arguments":{"address":536870912,"size":4},"result":{"ok":"78563412"}}
+"#;
+
+    let replay = ReplayBackend::from_reader(trace.as_bytes()).unwrap();
+    assert!(replay.list_probes().unwrap().is_empty());
+
+    // Calls with other arguments than recorded fail and do not consume the record
+    assert!(matches!(
+        replay.read_memory(stm32cubeprogrammer_sys::SRAM_BASE_ADDRESS, 8),
+        Err(CubeProgrammerError::Parameter {
+            action: Action::ReadMemory,
+            ..
+        })
+    ));
+    assert_eq!(
+        replay
+            .read_memory(stm32cubeprogrammer_sys::SRAM_BASE_ADDRESS, 4)
+            .unwrap(),
+        [0x78, 0x56, 0x34, 0x12]
+    );
+    assert_eq!(replay.remaining(), 0);
+
+    assert!(ReplayBackend::from_reader("{invalid".as_bytes()).is_err());
+}
+
+#[test_log::test]
+fn replay_version_and_capabilities() {
+    let old_version = CubeProgrammerVersion::new(2, 5, 0);
+    let trace = temp_path("header.jsonl");
+
+    drop(
+        RecordingBackend::create(
+            SimulatedBackend::new(SimulatedTarget::stm32wb55()).with_api_version(Some(old_version)),
+            &trace,
+        )
+        .unwrap(),
+    );
+
+    let replay = ReplayBackend::open(&trace).unwrap();
+    assert_eq!(replay.api_version(), Some(old_version));
+    assert_eq!(replay.capabilities(), Capabilities::all());
+    assert_eq!(replay.remaining(), 0);
+    std::fs::remove_file(trace).unwrap();
+
+    // The version gating behaves like during the recording
+    assert!(matches!(
+        CubeProgrammer::with_backend()
+            .backend(Box::new(replay))
+            .version_policy(VersionPolicy::Error)
+            .build(),
+        Err(CubeProgrammerError::UnsupportedVersion { version }) if version == old_version
+    ));
+
+    // Capabilities of a library without the optional functions
+    let replay = ReplayBackend::new(Vec::new()).with_header(Header {
+        api_version: None,
+        symbols: Some(REQUIRED_SYMBOLS.iter().map(|x| x.to_string()).collect()),
+    });
+    let programmer = CubeProgrammer::with_backend()
+        .backend(Box::new(replay))
+        .build()
+        .unwrap();
+    assert!(programmer.capabilities().features().is_empty());
+    assert!(!programmer.capabilities().supports(Feature::MemoryAccess));
+
+    // Traces without header are replayed with all functions
+    let trace = r#"{"function":"getStLinkList","arguments":null,"result":{"ok":[]}}"#;
+    let replay = ReplayBackend::from_reader(trace.as_bytes()).unwrap();
+    assert_eq!(replay.api_version(), None);
+    assert_eq!(replay.capabilities(), Capabilities::all());
+    assert_eq!(replay.remaining(), 1);
+}
+
+#[test]
+fn replay_error_kinds() {
+    let replay = |error: CubeProgrammerError| {
+        let recorded = RecordedError::from(&error);
+        let json = serde_json::to_string(&recorded).unwrap();
+        CubeProgrammerError::from(serde_json::from_str::<RecordedError>(&json).unwrap())
+    };
+
+    assert!(matches!(
+        replay(CubeProgrammerError::WorkerSpawn(std::io::Error::other(
+            "No resources"
+        ))),
+        CubeProgrammerError::WorkerSpawn(error) if error.to_string() == "No resources"
+    ));
+    assert!(matches!(
+        replay(CubeProgrammerError::WorkerStopped),
+        CubeProgrammerError::WorkerStopped
+    ));
+    assert!(matches!(
+        replay(CubeProgrammerError::LibLoading(
+            stm32cubeprogrammer_sys::libloading::Error::IncompatibleSize
+        )),
+        CubeProgrammerError::LibLoading(_)
+    ));
+    assert!(matches!(
+        replay(CubeProgrammerError::MissingDllSymbol {
+            message: "getUID64".to_string()
+        }),
+        CubeProgrammerError::MissingDllSymbol { message } if message == "getUID64"
+    ));
+}
@@ -0,0 +1,1394 @@
+//! Tests which use the simulated backend and therefore run without a STM32CubeProgrammer installation and without hardware
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use stm32cubeprogrammer::{
+    api_types::{CubeProgrammerVersion, ErrorCode, VersionPolicy},
+    backend::{
+        simulated::{SimulatedProbe, SimulatedTarget},
+        SimulatedBackend,
+    },
+    error::{Action, CubeProgrammerError},
+    fus::AntiRollbackState,
+    hsm::{HsmState, HsmStatus, HsmType},
+    probe::{
+        ConnectionInfo, ConnectionMode, ConnectionParameters, Frequency, LowPowerDebug, ProbeInfo,
+        Protocol, ReconnectPolicy, ResetMode, SpeedMode,
+    },
+    ssp, CancellationToken, Confirmation, ConnectedFusProgrammer, ConnectedProgrammer,
+    CoreRegister, CubeProgrammer, EventKind, Hsm, OperationResult, ProgrammerEvent, TrustZoneState,
+};
+
+fn init_programmer(target: SimulatedTarget) -> (CubeProgrammer, SimulatedBackend) {
+    let backend = SimulatedBackend::new(target);
+
+    let programmer = CubeProgrammer::with_backend()
+        .backend(Box::new(backend.clone()))
+        .build()
+        .unwrap();
+
+    (programmer, backend)
+}
+
+/// Write the content to a temporary file with the given name
+fn temp_file(name: &str, content: &[u8]) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "stm32cubeprogrammer-simulated-{}-{}",
+        std::process::id(),
+        name
+    ));
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+#[test_log::test]
+fn connect_and_disconnect() {
+    let (programmer, backend) = init_programmer(SimulatedTarget::stm32wb55());
+
+    let probes = programmer.list_available_probes().unwrap();
+    assert_eq!(probes.len(), 1);
+
+    let connected = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+        .unwrap();
+
+    assert_eq!(connected.general_information().device_id, 0x495);
+    assert_eq!(connected.general_information().name, "STM32WB5x");
+    assert!(connected.general_information().fus_support);
+    assert_eq!(
+        backend.connected_probe().as_deref(),
+        Some(SimulatedProbe::default().serial.as_str())
+    );
+
+    // The probe is in use and therefore not available
+    assert!(programmer.list_available_probes().unwrap().is_empty());
+
+    connected.disconnect();
+
+    assert_eq!(backend.connected_probe(), None);
+    assert_eq!(programmer.list_available_probes().unwrap().len(), 1);
+}
+
+#[test_log::test]
+fn read_and_write_sram_and_core_registers() {
+    let (programmer, _backend) = init_programmer(SimulatedTarget::stm32wb55());
+    let probes = programmer.list_available_probes().unwrap();
+    let connected = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+        .unwrap();
+
+    let address = stm32cubeprogrammer_sys::SRAM_BASE_ADDRESS + 1;
+    connected
+        .write_memory::<u32>(address, &[0x12345678, 0x9ABCDEF0])
+        .unwrap();
+    assert_eq!(
+        connected.read_memory::<u32>(address, 2).unwrap(),
+        [0x12345678, 0x9ABCDEF0]
+    );
+
+    connected
+        .write_core_register(CoreRegister::R0, 0xCAFEBABE)
+        .unwrap();
+    assert_eq!(
+        connected.read_core_register(CoreRegister::R0).unwrap(),
+        0xCAFEBABE
+    );
+
+    // Reading outside of the memory map fails
+    assert!(connected.read_memory::<u8>(0x6000_0000, 4).is_err());
+}
+
+#[test_log::test]
+fn download_and_erase_flash() {
+    let (programmer, backend) = init_programmer(SimulatedTarget::stm32wb55());
+    let probes = programmer.list_available_probes().unwrap();
+    let connected = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+        .unwrap();
+
+    let flash_address = stm32cubeprogrammer_sys::FLASH_BASE_ADDRESS;
+
+    // Vector table with stack pointer and reset handler
+    let firmware = [0x2000_4000u32.to_le_bytes(), 0x0800_0101u32.to_le_bytes()].concat();
+    let file = temp_file("firmware.bin", &firmware);
+
+    connected
+        .download_bin_file(&file, flash_address, false, true)
+        .unwrap();
+    assert_eq!(backend.flash()[..firmware.len()], firmware);
+
+    // Flash memory can only be programmed if it is erased
+    let error = connected
+        .write_memory::<u8>(flash_address, &[0xAB])
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        CubeProgrammerError::ActionFailed {
+            action: Action::WriteMemory,
+            ..
+        }
+    ));
+
+    // The reason logged by the API is attached to the error
+    assert_eq!(
+        error.messages(),
+        ["Flash memory at address 0x08000000 is not erased"]
+    );
+    assert!(error
+        .to_string()
+        .ends_with("(Flash memory at address 0x08000000 is not erased)"));
+
+    // A reset loads the stack pointer and program counter from the vector table
+    connected
+        .reset_target(stm32cubeprogrammer::probe::ResetMode::Hardware)
+        .unwrap();
+    assert_eq!(
+        connected.read_core_register(CoreRegister::SP).unwrap(),
+        0x2000_4000
+    );
+    assert_eq!(
+        connected.read_core_register(CoreRegister::PC).unwrap(),
+        0x0800_0100
+    );
+
+    connected.mass_erase().unwrap();
+    assert!(backend.flash().iter().all(|x| *x == 0xFF));
+
+    let dump = temp_file("dump.bin", &[]);
+    connected.save_memory(&dump, flash_address, 16).unwrap();
+    assert_eq!(std::fs::read(&dump).unwrap(), [0xFF; 16]);
+
+    std::fs::remove_file(file).unwrap();
+    std::fs::remove_file(dump).unwrap();
+}
+
+#[test_log::test]
+fn read_out_protection() {
+    let (programmer, backend) = init_programmer(SimulatedTarget::stm32wb55());
+    let probes = programmer.list_available_probes().unwrap();
+    let connected = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+        .unwrap();
+
+    let flash_address = stm32cubeprogrammer_sys::FLASH_BASE_ADDRESS;
+    connected
+        .write_memory::<u32>(flash_address, &[0x12345678])
+        .unwrap();
+
+    // The option byte launch resets the target and the connection is established again
+    connected.enable_read_out_protection().unwrap();
+    assert_eq!(backend.option_bit("RDP"), Some(0xBB));
+    assert!(backend.connected_probe().is_some());
+    assert!(connected.read_memory::<u32>(flash_address, 1).is_err());
+
+    // The regression erases the flash memory
+    connected.disable_read_out_protection().unwrap();
+    assert_eq!(backend.option_bit("RDP"), Some(0xAA));
+    assert_eq!(
+        connected.read_memory::<u32>(flash_address, 1).unwrap(),
+        [0xFFFF_FFFF]
+    );
+}
+
+#[test_log::test]
+fn program_ssp() {
+    let (programmer, _backend) = init_programmer(SimulatedTarget::stm32wb55());
+    let probes = programmer.list_available_probes().unwrap();
+
+    let connected = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+        .unwrap();
+    let events = programmer.subscribe();
+
+    let ssp_file = temp_file("secrets.ssp", &[0x11; 0x40]);
+    let rsse_file = temp_file("tf-a-ssp.stm32", &[0x22; 0x100]);
+    let rsse_extension_file = temp_file("rsse-ext.bin", &[0x33; 0x20]);
+    let license_file = temp_file("license.bin", &[0x44; 0x10]);
+
+    let mut parameters = ssp::Parameters {
+        ssp_file: ssp_file.clone(),
+        rsse_file: rsse_file.clone(),
+        rsse_extension_file: Some(rsse_extension_file.clone()),
+        license: ssp::License::File(license_file.clone()),
+    };
+
+    // Irreversible operations need to be confirmed
+    assert!(matches!(
+        connected.program_ssp(&parameters, Confirmation::NotConfirmed),
+        Err(CubeProgrammerError::Parameter {
+            action: Action::ProgramSsp,
+            ..
+        })
+    ));
+
+    // Missing files are reported before the API is called
+    parameters.rsse_extension_file = Some(temp_file("missing", &[]).with_extension("bin"));
+    assert!(matches!(
+        connected.program_ssp(&parameters, Confirmation::Confirmed),
+        Err(CubeProgrammerError::FileIo(_))
+    ));
+
+    parameters.license = ssp::License::Hsm { slot_id: u32::MAX };
+    parameters.rsse_extension_file = Some(rsse_extension_file.clone());
+    assert!(matches!(
+        connected.program_ssp(&parameters, Confirmation::Confirmed),
+        Err(CubeProgrammerError::Parameter {
+            action: Action::ProgramSsp,
+            ..
+        })
+    ));
+    assert!(events
+        .try_iter()
+        .all(|x| !matches!(x.kind, EventKind::Log { .. })));
+
+    // The API gets a single binary with the RSSe and the extension. The SSP is not supported via the debug interface
+    parameters.license = ssp::License::Hsm { slot_id: 1 };
+    let error = connected
+        .program_ssp(&parameters, Confirmation::Confirmed)
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        CubeProgrammerError::ActionFailed {
+            action: Action::ProgramSsp,
+            return_code: ErrorCode::UnsupportedInterface,
+            ..
+        }
+    ));
+    assert_eq!(
+        error.messages(),
+        ["SSP is only supported via USB DFU and UART"]
+    );
+
+    let log_messages = |events: &std::sync::mpsc::Receiver<ProgrammerEvent>| {
+        events
+            .try_iter()
+            .filter_map(|x| match x.kind {
+                EventKind::Log { message, .. } => Some(message),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    assert!(log_messages(&events).contains(&"RSSe binary with 288 bytes loaded".to_string()));
+
+    // Without extension, the RSSe binary is passed as is
+    parameters.rsse_extension_file = None;
+    parameters.license = ssp::License::File(license_file.clone());
+    assert!(connected
+        .program_ssp(&parameters, Confirmation::Confirmed)
+        .is_err());
+    assert!(log_messages(&events).contains(&"RSSe binary with 256 bytes loaded".to_string()));
+
+    for file in [ssp_file, rsse_file, rsse_extension_file, license_file] {
+        std::fs::remove_file(file).unwrap();
+    }
+}
+
+#[test_log::test]
+fn fus_info_and_anti_rollback() {
+    let (programmer, backend) = init_programmer(SimulatedTarget::stm32wb55());
+    let probes = programmer.list_available_probes().unwrap();
+
+    let mut connected = programmer
+        .connect_to_target_fus(&probes[0], &Protocol::Swd)
+        .unwrap();
+
+    let fus = SimulatedTarget::stm32wb55().fus.unwrap();
+    assert_eq!(connected.fus_info().fus_version, fus.fus_version);
+    assert_eq!(
+        connected.fus_info().wireless_stack_version,
+        fus.wireless_stack_version
+    );
+    assert_eq!(connected.fus_info().device_id, 0x495);
+    assert_eq!(
+        connected.fus_info().anti_rollback,
+        AntiRollbackState::Unknown
+    );
+
+    // Irreversible operations need to be confirmed
+    assert!(connected
+        .activate_anti_rollback(Confirmation::NotConfirmed)
+        .is_err());
+    assert!(!backend.anti_rollback_active());
+
+    // The state is reported after a successful activation
+    assert_eq!(
+        connected
+            .activate_anti_rollback(Confirmation::Confirmed)
+            .unwrap()
+            .anti_rollback,
+        AntiRollbackState::Active
+    );
+    assert!(backend.anti_rollback_active());
+
+    let stack = temp_file("stack.bin", &[0x5A; 0x100]);
+    connected
+        .upgrade_wireless_stack(&stack, 0x080C_0000, false, true, false)
+        .unwrap();
+    std::fs::remove_file(stack).unwrap();
+
+    connected.disconnect();
+
+    let connected = programmer
+        .connect_to_target_fus(&probes[0], &Protocol::Swd)
+        .unwrap();
+    assert_eq!(
+        connected.fus_info().wireless_stack_version,
+        fus.upgrade_wireless_stack_version
+    );
+}
+
+#[test_log::test]
+fn trustzone_regression() {
+    let (programmer, backend) = init_programmer(SimulatedTarget::stm32u585());
+    let probes = programmer.list_available_probes().unwrap();
+    let connected = programmer
+        .connect_to_target(
+            &probes[0],
+            &Protocol::Swd,
+            &ConnectionParameters {
+                connection_mode: ConnectionMode::UnderReset,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+    assert_eq!(
+        connected.trustzone_state().unwrap(),
+        TrustZoneState::Enabled
+    );
+    assert!(connected.start_wireless_stack().is_err());
+
+    // Irreversible operations need to be confirmed
+    assert!(connected
+        .trustzone_regression(Confirmation::NotConfirmed)
+        .is_err());
+    assert_eq!(backend.option_bit("TZEN"), Some(1));
+
+    // The read out protection is raised to level 1 first. Both the option byte launch and the regression reset the target,
+    // so the connection is established again after each of them
+    let events = programmer.subscribe();
+    assert_eq!(backend.option_bit("RDP"), Some(0xAA));
+    assert_eq!(
+        connected
+            .trustzone_regression(Confirmation::Confirmed)
+            .unwrap(),
+        TrustZoneState::Disabled
+    );
+    assert_eq!(backend.option_bit("TZEN"), Some(0));
+    assert_eq!(backend.option_bit("RDP"), Some(0xAA));
+    assert!(backend.connected_probe().is_some());
+    assert_eq!(
+        events
+            .try_iter()
+            .filter(|x| matches!(x.kind, EventKind::OperationFinished { .. }))
+            .count(),
+        1
+    );
+
+    // The connection is usable after the regression
+    assert_eq!(
+        connected.trustzone_state().unwrap(),
+        TrustZoneState::Disabled
+    );
+    assert_eq!(
+        connected
+            .trustzone_regression(Confirmation::Confirmed)
+            .unwrap(),
+        TrustZoneState::Disabled
+    );
+}
+
+#[test_log::test]
+fn hsm_status_and_license() {
+    let backend = SimulatedBackend::new(SimulatedTarget::stm32u585())
+        .with_hsm(HsmStatus {
+            slot_id: 1,
+            firmware_id: "SFI-FW-1".to_string(),
+            counter: 1,
+            state: HsmState::Operational,
+            version: 2,
+            r#type: HsmType::Sfi,
+        })
+        .with_hsm(HsmStatus {
+            slot_id: 2,
+            firmware_id: "SMU-FW-1".to_string(),
+            counter: 0,
+            state: HsmState::Other("LOCKED_STATE".to_string()),
+            version: 1,
+            r#type: HsmType::Other("XYZ".to_string()),
+        });
+    let programmer = CubeProgrammer::with_backend()
+        .backend(Box::new(backend.clone()))
+        .build()
+        .unwrap();
+
+    // The status is read without a target connection
+    let hsm = programmer.hsm(1);
+    let status = hsm.status().unwrap();
+    assert_eq!(status.slot_id, 1);
+    assert_eq!(status.firmware_id, "SFI-FW-1");
+    assert_eq!(status.counter, 1);
+    assert_eq!(status.state, HsmState::Operational);
+    assert_eq!(status.version, 2);
+    assert_eq!(status.r#type, HsmType::Sfi);
+
+    // Unknown strings are kept
+    let status = programmer.hsm(2).status().unwrap();
+    assert_eq!(status.state, HsmState::Other("LOCKED_STATE".to_string()));
+    assert_eq!(status.r#type, HsmType::Other("XYZ".to_string()));
+
+    // The slot id is passed as i32 to the API
+    assert!(matches!(
+        programmer.hsm(u32::MAX).status(),
+        Err(CubeProgrammerError::Parameter {
+            action: Action::ReadHsmStatus,
+            ..
+        })
+    ));
+    assert!(matches!(
+        programmer.hsm(3).status(),
+        Err(CubeProgrammerError::ActionOutputUnexpected {
+            action: Action::ReadHsmStatus,
+            ..
+        })
+    ));
+
+    let probes = programmer.list_available_probes().unwrap();
+    let connected = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+        .unwrap();
+
+    let file = std::env::temp_dir().join(format!(
+        "stm32cubeprogrammer-simulated-{}-license.bin",
+        std::process::id()
+    ));
+
+    // The license contains the firmware id of the HSM and the UID64 of the target
+    hsm.export_license(&connected, &file).unwrap();
+    let mut expected = b"SFI-FW-1".to_vec();
+    expected.extend_from_slice(&SimulatedTarget::stm32u585().uid64.to_le_bytes());
+    assert_eq!(std::fs::read(&file).unwrap(), expected);
+    std::fs::remove_file(&file).unwrap();
+
+    // Each license decrements the counter
+    assert_eq!(hsm.status().unwrap().counter, 0);
+    assert!(matches!(
+        hsm.export_license(&connected, &file),
+        Err(CubeProgrammerError::ActionFailed {
+            action: Action::ExportHsmLicense,
+            return_code: ErrorCode::SecurityError,
+            ..
+        })
+    ));
+    assert!(!file.exists());
+
+    assert!(matches!(
+        programmer.hsm(u32::MAX).export_license(&connected, &file),
+        Err(CubeProgrammerError::Parameter {
+            action: Action::ExportHsmLicense,
+            ..
+        })
+    ));
+
+    // The connection needs to be established via the programmer of the HSM
+    let (other_programmer, _other_backend) = init_programmer(SimulatedTarget::stm32u585());
+    assert!(matches!(
+        other_programmer.hsm(1).export_license(&connected, &file),
+        Err(CubeProgrammerError::Parameter {
+            action: Action::ExportHsmLicense,
+            ..
+        })
+    ));
+}
+
+#[test_log::test]
+fn unique_id_96_of_unknown_device() {
+    let (programmer, _backend) = init_programmer(SimulatedTarget {
+        device_id: 0x999,
+        ..SimulatedTarget::stm32wb55()
+    });
+    let probes = programmer.list_available_probes().unwrap();
+    let connected = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+        .unwrap();
+
+    let events = programmer.subscribe();
+    assert!(matches!(
+        connected.unique_id_96(),
+        Err(CubeProgrammerError::ActionNotSupported {
+            action: Action::ReadUid96,
+            ..
+        })
+    ));
+
+    // The unsupported device is reported like any other failed operation
+    let events = events.try_iter().collect::<Vec<_>>();
+    assert!(matches!(
+        events.first().map(|x| (x.action, &x.kind)),
+        Some((Action::ReadUid96, EventKind::OperationStarted))
+    ));
+    assert!(matches!(
+        events.last().map(|x| (x.action, &x.kind)),
+        Some((
+            Action::ReadUid96,
+            EventKind::OperationFinished {
+                result: OperationResult::Failed { .. },
+                ..
+            }
+        ))
+    ));
+}
+
+#[test]
+fn handles_are_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    assert_send_sync::<CubeProgrammer>();
+    assert_send_sync::<ConnectedProgrammer>();
+    assert_send_sync::<ConnectedFusProgrammer>();
+    assert_send_sync::<Hsm>();
+}
+
+#[test_log::test]
+fn connection_in_worker_thread() {
+    let (programmer, backend) = init_programmer(SimulatedTarget::stm32wb55());
+    let probes = programmer.list_available_probes().unwrap();
+    let connected = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+        .unwrap();
+
+    let address = stm32cubeprogrammer_sys::SRAM_BASE_ADDRESS;
+    let worker = std::thread::spawn(move || {
+        connected
+            .write_memory::<u32>(address, &[0xDEADBEEF])
+            .unwrap();
+        connected
+    });
+
+    let connected = worker.join().unwrap();
+    assert_eq!(
+        connected.read_memory::<u32>(address, 1).unwrap(),
+        [0xDEADBEEF]
+    );
+
+    // A clone of the programmer shares the probe registry
+    let other = programmer.clone();
+    assert!(other.list_available_probes().unwrap().is_empty());
+
+    connected.disconnect();
+    assert_eq!(backend.connected_probe(), None);
+    assert_eq!(other.list_available_probes().unwrap().len(), 1);
+}
+
+#[test_log::test]
+fn connections_share_the_api() {
+    let backend = SimulatedBackend::new(SimulatedTarget::stm32wb55()).with_probes(vec![
+        SimulatedProbe {
+            serial: "PROBE1".to_string(),
+            ..Default::default()
+        },
+        SimulatedProbe {
+            serial: "PROBE2".to_string(),
+            ..Default::default()
+        },
+    ]);
+    let programmer = CubeProgrammer::with_backend()
+        .backend(Box::new(backend.clone()))
+        .build()
+        .unwrap();
+
+    assert_eq!(programmer.list_available_probes().unwrap().len(), 2);
+    let serial = |x: &str| x.parse::<stm32cubeprogrammer::probe::Serial>().unwrap();
+
+    let first = programmer
+        .connect_to_target(
+            &serial("PROBE1"),
+            &Protocol::Swd,
+            &ConnectionParameters::default(),
+        )
+        .unwrap();
+    let second = programmer
+        .connect_to_target(
+            &serial("PROBE2"),
+            &Protocol::Swd,
+            &ConnectionParameters::default(),
+        )
+        .unwrap();
+    assert_eq!(backend.connected_probe().as_deref(), Some("PROBE2"));
+
+    // The API only has one connection: Using the first connection re-establishes it
+    std::thread::scope(|scope| {
+        scope.spawn(|| first.read_core_register(CoreRegister::R0).unwrap());
+    });
+    assert_eq!(backend.connected_probe().as_deref(), Some("PROBE1"));
+
+    // Dropping the second connection does not disconnect the first connection
+    second.disconnect();
+    assert_eq!(backend.connected_probe().as_deref(), Some("PROBE1"));
+
+    first.disconnect();
+    assert_eq!(backend.connected_probe(), None);
+    assert_eq!(programmer.list_available_probes().unwrap().len(), 2);
+}
+
+#[test_log::test]
+fn cancel_operations() {
+    let backend = SimulatedBackend::new(SimulatedTarget::stm32wb55());
+    let programmer = CubeProgrammer::with_backend()
+        .backend(Box::new(backend.clone()))
+        .build()
+        .unwrap();
+
+    // Cancel with the first progress update
+    let token = CancellationToken::new();
+    programmer.set_event_handler(Arc::new({
+        let token = token.clone();
+        move |event: &ProgrammerEvent| {
+            if matches!(event.kind, EventKind::Progress { .. }) {
+                token.cancel();
+            }
+        }
+    }));
+    let events = programmer.subscribe();
+
+    let probes = programmer.list_available_probes().unwrap();
+    let mut connected = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+        .unwrap();
+    connected.set_cancellation_token(token.clone());
+
+    // The download is cancelled after the first progress update
+    let file = temp_file("cancel.bin", &[0x5A; 0x100]);
+    assert!(matches!(
+        connected.download_bin_file(
+            &file,
+            stm32cubeprogrammer_sys::FLASH_BASE_ADDRESS,
+            false,
+            true
+        ),
+        Err(CubeProgrammerError::Cancelled {
+            action: Action::DownloadFile
+        })
+    ));
+    assert!(programmer.remove_event_handler().is_some());
+
+    // The token and the cancel flag of the API are reset after the cancelled operation
+    assert!(!token.is_cancelled());
+    connected
+        .download_bin_file(
+            &file,
+            stm32cubeprogrammer_sys::FLASH_BASE_ADDRESS,
+            false,
+            true,
+        )
+        .unwrap();
+    std::fs::remove_file(file).unwrap();
+
+    // A cancel without a running operation cancels the next operation before it starts
+    token.cancel();
+    assert!(matches!(
+        connected.mass_erase(),
+        Err(CubeProgrammerError::Cancelled {
+            action: Action::MassErase
+        })
+    ));
+    assert_eq!(backend.flash()[0], 0x5A);
+    assert!(!token.is_cancelled());
+
+    // The token of another connection does not cancel the operations of this connection
+    CancellationToken::new().cancel();
+    connected.mass_erase().unwrap();
+    assert!(backend.flash().iter().all(|x| *x == 0xFF));
+
+    connected.disconnect();
+    assert_eq!(programmer.list_available_probes().unwrap().len(), 1);
+
+    // Each operation is reported with its result
+    let finished = events
+        .try_iter()
+        .filter_map(|x| match x.kind {
+            EventKind::OperationFinished { result, .. } => Some((x.action, result)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        finished,
+        [
+            (Action::ListConnectedProbes, OperationResult::Succeeded),
+            (Action::Connect, OperationResult::Succeeded),
+            (Action::DownloadFile, OperationResult::Cancelled),
+            (Action::DownloadFile, OperationResult::Succeeded),
+            (Action::MassErase, OperationResult::Cancelled),
+            (Action::MassErase, OperationResult::Succeeded),
+            (Action::ListConnectedProbes, OperationResult::Succeeded),
+        ]
+    );
+}
+
+/// Collect the events of the programmer
+fn collect_events(programmer: &CubeProgrammer) -> Arc<Mutex<Vec<ProgrammerEvent>>> {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    programmer.set_event_handler(Arc::new({
+        let events = events.clone();
+        move |event: &ProgrammerEvent| events.lock().unwrap().push(event.clone())
+    }));
+    events
+}
+
+#[test_log::test]
+fn events_of_parallel_programmers() {
+    let file = temp_file("events.bin", &[0x5A; 0x100]);
+
+    let sessions = ["PROBE1", "PROBE2"].map(|serial| {
+        let backend =
+            SimulatedBackend::new(SimulatedTarget::stm32wb55()).with_probes(vec![SimulatedProbe {
+                serial: serial.to_string(),
+                ..Default::default()
+            }]);
+        let programmer = CubeProgrammer::with_backend()
+            .backend(Box::new(backend))
+            .build()
+            .unwrap();
+        let events = collect_events(&programmer);
+        (programmer, events)
+    });
+
+    std::thread::scope(|scope| {
+        for (programmer, _) in &sessions {
+            scope.spawn(|| {
+                let probes = programmer.list_available_probes().unwrap();
+                let connected = programmer
+                    .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+                    .unwrap();
+                connected
+                    .download_bin_file(
+                        &file,
+                        stm32cubeprogrammer_sys::FLASH_BASE_ADDRESS,
+                        false,
+                        true,
+                    )
+                    .unwrap();
+            });
+        }
+    });
+    std::fs::remove_file(file).unwrap();
+
+    for ((_, events), serial) in sessions.iter().zip(["PROBE1", "PROBE2"]) {
+        let events = events.lock().unwrap();
+
+        // Each programmer only receives the events of its own operations
+        assert!(events
+            .iter()
+            .filter_map(|x| x.probe.as_ref())
+            .all(|x| x.to_string() == serial));
+
+        let download = events
+            .iter()
+            .filter(|x| x.action == Action::DownloadFile)
+            .collect::<Vec<_>>();
+        assert!(download
+            .iter()
+            .any(|x| matches!(x.kind, EventKind::ProgressStarted)));
+        assert!(download.iter().any(
+            |x| matches!(x.kind, EventKind::Progress { current, total, .. } if current == total)
+        ));
+    }
+
+    // Replace the handler at runtime
+    let (programmer, first) = &sessions[0];
+    let second = collect_events(programmer);
+    first.lock().unwrap().clear();
+
+    programmer.list_available_probes().unwrap();
+    assert!(first.lock().unwrap().is_empty());
+
+    // Without a handler, the events are dropped
+    assert!(programmer.remove_event_handler().is_some());
+    let count = second.lock().unwrap().len();
+    let probes = programmer.list_available_probes().unwrap();
+    programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+        .unwrap()
+        .mass_erase()
+        .unwrap();
+    assert_eq!(second.lock().unwrap().len(), count);
+}
+
+#[test_log::test]
+fn api_version_gating() {
+    let (programmer, _) = init_programmer(SimulatedTarget::stm32wb55());
+    assert_eq!(
+        programmer.api_version(),
+        Some(CubeProgrammerVersion::new(2, 18, 0))
+    );
+
+    // Unsupported versions are only logged by default
+    let old_version = CubeProgrammerVersion::new(2, 5, 0);
+    let programmer = CubeProgrammer::with_backend()
+        .backend(Box::new(
+            SimulatedBackend::new(SimulatedTarget::stm32wb55()).with_api_version(Some(old_version)),
+        ))
+        .build()
+        .unwrap();
+
+    assert_eq!(programmer.api_version(), Some(old_version));
+    assert_eq!(programmer.list_available_probes().unwrap().len(), 1);
+
+    // With the error policy, the construction fails like the one of `CubeProgrammer::new`
+    assert!(matches!(
+        CubeProgrammer::with_backend()
+            .backend(Box::new(
+                SimulatedBackend::new(SimulatedTarget::stm32wb55())
+                    .with_api_version(Some(old_version)),
+            ))
+            .version_policy(VersionPolicy::Error)
+            .build(),
+        Err(CubeProgrammerError::UnsupportedVersion { version }) if version == old_version
+    ));
+
+    // Unknown versions are not restricted
+    let programmer = CubeProgrammer::with_backend()
+        .backend(Box::new(
+            SimulatedBackend::new(SimulatedTarget::stm32wb55()).with_api_version(None),
+        ))
+        .version_policy(VersionPolicy::Error)
+        .build()
+        .unwrap();
+
+    assert_eq!(programmer.api_version(), None);
+    assert_eq!(programmer.list_available_probes().unwrap().len(), 1);
+}
+
+#[test_log::test]
+fn list_probes_detailed() {
+    let programmer = CubeProgrammer::with_backend()
+        .backend(Box::new(
+            SimulatedBackend::new(SimulatedTarget::stm32wb55()).with_probes(vec![
+                SimulatedProbe {
+                    serial: "PROBE2".to_string(),
+                    board: "NUCLEO-WB55RG".to_string(),
+                    is_old_firmware: true,
+                    target_voltage: "3.27".to_string(),
+                    ..Default::default()
+                },
+                SimulatedProbe {
+                    serial: "PROBE1".to_string(),
+                    target_voltage: "".to_string(),
+                    ..Default::default()
+                },
+            ]),
+        ))
+        .build()
+        .unwrap();
+
+    let probes = programmer.list_probes_detailed().unwrap();
+    assert_eq!(
+        probes,
+        [
+            ProbeInfo {
+                serial: "PROBE1".parse().unwrap(),
+                board: SimulatedProbe::default().board,
+                firmware_version: SimulatedProbe::default().firmware_version,
+                is_old_firmware: false,
+                target_voltage_mv: None,
+                access_port_number: 1,
+                is_bridge: false,
+                swd_frequencies_khz: vec![24000, 8000, 3300, 1000, 200, 50, 5],
+                jtag_frequencies_khz: vec![21333, 16000, 12000, 8000, 1777, 750],
+            },
+            ProbeInfo {
+                serial: "PROBE2".parse().unwrap(),
+                board: "NUCLEO-WB55RG".to_string(),
+                firmware_version: SimulatedProbe::default().firmware_version,
+                is_old_firmware: true,
+                target_voltage_mv: Some(3270),
+                access_port_number: 1,
+                is_bridge: false,
+                swd_frequencies_khz: vec![24000, 8000, 3300, 1000, 200, 50, 5],
+                jtag_frequencies_khz: vec![21333, 16000, 12000, 8000, 1777, 750],
+            },
+        ]
+    );
+
+    // Probes in use are not listed
+    let _connected = programmer
+        .connect_to_target(
+            &probes[0].serial,
+            &Protocol::Swd,
+            &ConnectionParameters::default(),
+        )
+        .unwrap();
+    assert_eq!(
+        programmer
+            .list_probes_detailed()
+            .unwrap()
+            .iter()
+            .map(|x| x.serial.to_string())
+            .collect::<Vec<_>>(),
+        ["PROBE2"]
+    );
+}
+
+#[test_log::test]
+fn target_voltage_guard() {
+    let (programmer, backend) = init_programmer(SimulatedTarget::stm32wb55());
+    let serial = SimulatedProbe::default().serial;
+    let probes = programmer.list_available_probes().unwrap();
+
+    let connection_parameters = ConnectionParameters {
+        min_target_voltage_mv: Some(1700),
+        ..Default::default()
+    };
+
+    // Unpowered target
+    backend.set_target_voltage(&serial, "0.00");
+    assert!(matches!(
+        programmer.connect_to_target(&probes[0], &Protocol::Swd, &connection_parameters),
+        Err(CubeProgrammerError::TargetVoltageTooLow {
+            voltage_mv: 0,
+            min_voltage_mv: 1700,
+            ..
+        })
+    ));
+    assert_eq!(backend.connected_probe(), None);
+
+    // The probe is still available after the refused connection
+    backend.set_target_voltage(&serial, "3.30");
+    let connected = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &connection_parameters)
+        .unwrap();
+    assert_eq!(connected.target_voltage().unwrap(), Some(3300));
+
+    backend.set_target_voltage(&serial, "1.81");
+    assert_eq!(connected.target_voltage().unwrap(), Some(1810));
+
+    // The probe scan ends the connection of the API, which is re-established before the next call of the connection
+    assert_eq!(backend.connected_probe(), None);
+    connected
+        .write_core_register(CoreRegister::R0, 0x1234)
+        .unwrap();
+    assert_eq!(backend.connected_probe(), Some(serial.clone()));
+
+    // The same applies to the probe scan of the programmer
+    assert!(programmer.list_available_probes().unwrap().is_empty());
+    assert_eq!(backend.connected_probe(), None);
+    assert_eq!(
+        connected.read_core_register(CoreRegister::R0).unwrap(),
+        0x1234
+    );
+    assert_eq!(backend.connected_probe(), Some(serial));
+}
+
+#[test]
+fn access_port_selection() {
+    let (programmer, _backend) = init_programmer(SimulatedTarget {
+        access_ports: 2,
+        ..SimulatedTarget::stm32wb55()
+    });
+    let probes = programmer.list_available_probes().unwrap();
+
+    let connection_parameters = |access_port| ConnectionParameters {
+        access_port: Some(access_port),
+        ..Default::default()
+    };
+
+    let first_core = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &connection_parameters(0))
+        .unwrap();
+    let second_core = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &connection_parameters(1))
+        .unwrap();
+    assert_eq!(first_core.access_port(), 0);
+    assert_eq!(second_core.access_port(), 1);
+
+    // Each core has its own registers
+    first_core
+        .write_core_register(CoreRegister::R0, 0x1111_1111)
+        .unwrap();
+    second_core
+        .write_core_register(CoreRegister::R0, 0x2222_2222)
+        .unwrap();
+    assert_eq!(
+        first_core.read_core_register(CoreRegister::R0).unwrap(),
+        0x1111_1111
+    );
+    assert_eq!(
+        second_core.read_core_register(CoreRegister::R0).unwrap(),
+        0x2222_2222
+    );
+
+    // An access port is usable for one connection at a time
+    assert!(matches!(
+        programmer.connect_to_target(&probes[0], &Protocol::Swd, &connection_parameters(1)),
+        Err(CubeProgrammerError::Parameter { .. })
+    ));
+
+    // The probe reports two access ports
+    assert!(matches!(
+        programmer.connect_to_target(&probes[0], &Protocol::Swd, &connection_parameters(2)),
+        Err(CubeProgrammerError::Parameter { .. })
+    ));
+
+    // The probe is available again after all connections are closed
+    drop(second_core);
+    assert!(programmer.list_available_probes().unwrap().is_empty());
+    drop(first_core);
+    assert_eq!(programmer.list_available_probes().unwrap(), probes);
+}
+
+#[test]
+fn shared_connections() {
+    let (programmer, backend) = init_programmer(SimulatedTarget::stm32wb55());
+    let probes = programmer.list_available_probes().unwrap();
+
+    let shared = ConnectionParameters {
+        shared: true,
+        ..Default::default()
+    };
+
+    // Shared connections need the ST-LINK server
+    assert!(matches!(
+        programmer.connect_to_target(&probes[0], &Protocol::Swd, &shared),
+        Err(CubeProgrammerError::StlinkServerNotRunning { .. })
+    ));
+
+    backend.set_stlink_server_running(true);
+
+    let first = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &shared)
+        .unwrap();
+    let second = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &shared)
+        .unwrap();
+    assert!(first.is_shared());
+    assert!(second.is_shared());
+
+    first.write_core_register(CoreRegister::R0, 0x1234).unwrap();
+    assert_eq!(second.read_core_register(CoreRegister::R0).unwrap(), 0x1234);
+
+    // An exclusive connection is refused while the probe is shared
+    assert!(matches!(
+        programmer.connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default()),
+        Err(CubeProgrammerError::Parameter { .. })
+    ));
+
+    // The probe is available again after all shared connections are closed
+    drop(first);
+    assert!(programmer.list_available_probes().unwrap().is_empty());
+    second.read_core_register(CoreRegister::R0).unwrap();
+    drop(second);
+
+    let exclusive = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+        .unwrap();
+    assert!(!exclusive.is_shared());
+
+    // A shared connection is refused while the probe is used exclusively
+    assert!(matches!(
+        programmer.connect_to_target(&probes[0], &Protocol::Swd, &shared),
+        Err(CubeProgrammerError::Parameter { .. })
+    ));
+}
+
+#[test]
+fn connection_info() {
+    let (programmer, _backend) = init_programmer(SimulatedTarget::stm32wb55());
+    let probes = programmer.list_available_probes().unwrap();
+
+    // Defaults of the probe
+    let connected = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+        .unwrap();
+    let connection_info = connected.connection_info();
+    assert_eq!(connection_info.low_power_debug, LowPowerDebug::Disabled);
+    assert_eq!(connection_info.speed_mode, SpeedMode::Reliable);
+    drop(connected);
+
+    let connected = programmer
+        .connect_to_target(
+            &probes[0],
+            &Protocol::Swd,
+            &ConnectionParameters {
+                frequency: Frequency::Medium,
+                low_power_debug: Some(LowPowerDebug::Enabled),
+                speed_mode: Some(SpeedMode::Fast),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+    assert_eq!(
+        connected.connection_info(),
+        ConnectionInfo {
+            serial: probes[0].clone(),
+            protocol: Protocol::Swd,
+            connection_mode: ConnectionMode::Normal,
+            reset_mode: ResetMode::Hardware,
+            frequency_khz: 3300,
+            access_port: 0,
+            shared: false,
+            low_power_debug: LowPowerDebug::Enabled,
+            speed_mode: SpeedMode::Fast,
+        }
+    );
+}
+
+#[test]
+fn frequency_fallback() {
+    let backend =
+        SimulatedBackend::new(SimulatedTarget::stm32wb55()).with_probes(vec![SimulatedProbe {
+            max_frequency_khz: Some(1000),
+            ..Default::default()
+        }]);
+    let programmer = CubeProgrammer::with_backend()
+        .backend(Box::new(backend))
+        .build()
+        .unwrap();
+    let probes = programmer.list_available_probes().unwrap();
+
+    // Without fallback the connection at the highest frequency fails
+    let error = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+        .err()
+        .unwrap();
+    assert!(error.is_connection_failure());
+
+    // The fallback steps down to the highest working frequency
+    let fallback = ConnectionParameters {
+        frequency_fallback: true,
+        ..Default::default()
+    };
+    let connected = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &fallback)
+        .unwrap();
+    assert_eq!(connected.connection_info().frequency_khz, 1000);
+
+    // The used frequency is kept for reconnects
+    connected.reset_target(ResetMode::Hardware).unwrap();
+    assert_eq!(connected.connection_info().frequency_khz, 1000);
+    drop(connected);
+
+    // The fallback steps down from a custom frequency
+    let connected = programmer
+        .connect_to_target(
+            &probes[0],
+            &Protocol::Swd,
+            &ConnectionParameters {
+                frequency: Frequency::Custom(5000),
+                ..fallback.clone()
+            },
+        )
+        .unwrap();
+    assert_eq!(connected.connection_info().frequency_khz, 1000);
+    drop(connected);
+
+    // All frequencies fail and the probe is still available afterwards
+    let backend =
+        SimulatedBackend::new(SimulatedTarget::stm32wb55()).with_probes(vec![SimulatedProbe {
+            max_frequency_khz: Some(1),
+            ..Default::default()
+        }]);
+    let programmer = CubeProgrammer::with_backend()
+        .backend(Box::new(backend))
+        .build()
+        .unwrap();
+    let probes = programmer.list_available_probes().unwrap();
+
+    assert!(programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &fallback)
+        .err()
+        .unwrap()
+        .is_connection_failure());
+    assert_eq!(programmer.list_available_probes().unwrap(), probes);
+}
+
+#[test]
+fn reconnect_policy() {
+    let (programmer, backend) = init_programmer(SimulatedTarget::stm32wb55());
+    let probes = programmer.list_available_probes().unwrap();
+    let events = programmer.subscribe();
+
+    let policy = ReconnectPolicy {
+        backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(2),
+        ..Default::default()
+    };
+    assert_eq!(policy.delay(1), Duration::from_millis(1));
+    assert_eq!(policy.delay(3), Duration::from_millis(2));
+    assert_eq!(
+        policy.connection_mode(5),
+        Some(ConnectionMode::HardwareResetPulse)
+    );
+
+    let connection_parameters = ConnectionParameters {
+        reconnect: Some(policy.clone()),
+        ..Default::default()
+    };
+
+    let reconnect_attempts = |events: &std::sync::mpsc::Receiver<ProgrammerEvent>| {
+        events
+            .try_iter()
+            .filter_map(|x| match x.kind {
+                EventKind::ReconnectAttempt {
+                    attempt,
+                    connection_mode,
+                    ..
+                } => Some((attempt, connection_mode)),
+                EventKind::Reconnected { attempt } => Some((attempt, ConnectionMode::Normal)),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // The connection escalates from hot-plug to under-reset
+    backend.fail_connections(2);
+    let connected = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &connection_parameters)
+        .unwrap();
+    assert_eq!(
+        reconnect_attempts(&events),
+        [
+            (1, ConnectionMode::HotPlug),
+            (2, ConnectionMode::UnderReset),
+            (2, ConnectionMode::Normal)
+        ]
+    );
+    assert_eq!(
+        connected.connection_info().connection_mode,
+        ConnectionMode::UnderReset
+    );
+
+    // A lost connection is re-established transparently
+    connected
+        .write_core_register(CoreRegister::R0, 0xCAFE)
+        .unwrap();
+    backend.drop_connection();
+    backend.fail_connections(1);
+    connected.read_core_register(CoreRegister::R0).unwrap();
+    assert_eq!(
+        reconnect_attempts(&events),
+        [
+            (1, ConnectionMode::HotPlug),
+            (2, ConnectionMode::UnderReset),
+            (2, ConnectionMode::Normal)
+        ]
+    );
+
+    // Memory accesses re-establish a lost connection as well
+    backend.drop_connection();
+    connected
+        .write_memory::<u32>(stm32cubeprogrammer_sys::SRAM_BASE_ADDRESS, &[0x1234_5678])
+        .unwrap();
+    assert_eq!(
+        reconnect_attempts(&events),
+        [(1, ConnectionMode::HotPlug), (1, ConnectionMode::Normal)]
+    );
+    backend.drop_connection();
+    assert_eq!(
+        connected
+            .read_memory::<u32>(stm32cubeprogrammer_sys::SRAM_BASE_ADDRESS, 1)
+            .unwrap(),
+        [0x1234_5678]
+    );
+    assert_eq!(reconnect_attempts(&events).len(), 2);
+    drop(connected);
+
+    // Without escalation, a lost connection is re-established via hot-plug and not via the mode of the connection, so the target is not reset
+    let connected = programmer
+        .connect_to_target(
+            &probes[0],
+            &Protocol::Swd,
+            &ConnectionParameters {
+                connection_mode: ConnectionMode::UnderReset,
+                reconnect: Some(ReconnectPolicy {
+                    escalation: Vec::new(),
+                    ..policy.clone()
+                }),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    backend.drop_connection();
+    backend.fail_connections(1);
+    connected.read_core_register(CoreRegister::R0).unwrap();
+    assert_eq!(
+        reconnect_attempts(&events),
+        [
+            (1, ConnectionMode::HotPlug),
+            (2, ConnectionMode::HotPlug),
+            (2, ConnectionMode::Normal)
+        ]
+    );
+    drop(connected);
+
+    // All attempts fail
+    backend.fail_connections(4);
+    assert!(programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &connection_parameters)
+        .err()
+        .unwrap()
+        .is_connection_failure());
+    assert_eq!(reconnect_attempts(&events).len(), 3);
+    assert_eq!(programmer.list_available_probes().unwrap(), probes);
+
+    // Without policy, a lost connection is reported as error
+    let connected = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+        .unwrap();
+    backend.drop_connection();
+    assert!(connected
+        .read_core_register(CoreRegister::R0)
+        .err()
+        .unwrap()
+        .is_connection_failure());
+}
+
+#[test]
+fn reconnect_policy_on_connection_switch() {
+    let (programmer, backend) = init_programmer(SimulatedTarget {
+        access_ports: 2,
+        ..SimulatedTarget::stm32wb55()
+    });
+    let probes = programmer.list_available_probes().unwrap();
+    let events = programmer.subscribe();
+
+    let connection_parameters = |access_port| ConnectionParameters {
+        access_port: Some(access_port),
+        reconnect: Some(ReconnectPolicy {
+            backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let first_core = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &connection_parameters(0))
+        .unwrap();
+    let second_core = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &connection_parameters(1))
+        .unwrap();
+
+    // The switch back to the first core fails once and is retried according to the policy
+    backend.fail_connections(1);
+    first_core
+        .write_core_register(CoreRegister::R0, 0x1111_1111)
+        .unwrap();
+    assert_eq!(
+        events
+            .try_iter()
+            .filter(|x| matches!(x.kind, EventKind::ReconnectAttempt { .. }))
+            .count(),
+        1
+    );
+
+    second_core
+        .write_core_register(CoreRegister::R0, 0x2222_2222)
+        .unwrap();
+    assert_eq!(
+        first_core.read_core_register(CoreRegister::R0).unwrap(),
+        0x1111_1111
+    );
+}
@@ -45,11 +45,11 @@ pub fn init_programmer() -> CubeProgrammer {
         .unwrap()
 }
 
-pub fn connect_to_target<'a>(
-    programmer: &'a CubeProgrammer,
+pub fn connect_to_target(
+    programmer: &CubeProgrammer,
     protocol: &stm32cubeprogrammer::probe::Protocol,
     connection_parameters: &stm32cubeprogrammer::probe::ConnectionParameters,
-) -> ConnectedProgrammer<'a> {
+) -> ConnectedProgrammer {
     let probes = programmer
         .list_available_probes()
         .expect("Failed to list available probes");
@@ -64,10 +64,10 @@ pub fn connect_to_target<'a>(
     connected
 }
 
-pub fn connect_to_target_fus<'a>(
-    programmer: &'a CubeProgrammer,
+pub fn connect_to_target_fus(
+    programmer: &CubeProgrammer,
     protocol: &stm32cubeprogrammer::probe::Protocol,
-) -> ConnectedFusProgrammer<'a> {
+) -> ConnectedFusProgrammer {
     connect_to_target(programmer, protocol, &Default::default());
 
     let probes = programmer
@@ -0,0 +1,83 @@
+//! Tests of the tracing integration which use the simulated backend
+#![cfg(feature = "tracing")]
+
+use std::sync::{Arc, Mutex};
+use stm32cubeprogrammer::{
+    backend::{simulated::SimulatedTarget, SimulatedBackend},
+    probe::{ConnectionParameters, Protocol},
+    CubeProgrammer,
+};
+use tracing_subscriber::{fmt::MakeWriter, util::SubscriberInitExt};
+
+/// Writer which collects the formatted output of the subscriber
+#[derive(Debug, Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for Output {
+    type Writer = Output;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+#[test]
+fn api_messages_are_emitted_inside_the_operation_span() {
+    let output = Output::default();
+    let _subscriber = tracing_subscriber::fmt()
+        .with_writer(output.clone())
+        .with_max_level(tracing_subscriber::filter::LevelFilter::TRACE)
+        .finish()
+        .set_default();
+
+    let programmer = CubeProgrammer::with_backend()
+        .backend(Box::new(
+            SimulatedBackend::new(SimulatedTarget::stm32wb55()),
+        ))
+        .build()
+        .unwrap();
+
+    let probes = programmer.list_available_probes().unwrap();
+    let connected = programmer
+        .connect_to_target(&probes[0], &Protocol::Swd, &ConnectionParameters::default())
+        .unwrap();
+
+    // Flash memory can only be programmed if it is erased
+    let flash_address = stm32cubeprogrammer_sys::FLASH_BASE_ADDRESS;
+    connected
+        .write_memory::<u8>(flash_address, &[0x5A])
+        .unwrap();
+    assert!(connected
+        .write_memory::<u8>(flash_address, &[0xAB])
+        .is_err());
+
+    let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+    let line = output
+        .lines()
+        .find(|x| x.contains("Flash memory at address 0x08000000 is not erased"))
+        .unwrap();
+
+    for field in [
+        "ERROR",
+        "operation{",
+        "action=WriteMemory",
+        &format!("probe={}", probes[0]),
+        "device_id=0x495",
+        "address=0x08000000",
+        "size=1",
+        "message_type=Error",
+    ] {
+        assert!(line.contains(field), "{} not found in: {}", field, line);
+    }
+}
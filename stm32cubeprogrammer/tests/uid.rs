@@ -0,0 +1,28 @@
+#![cfg(feature = "hardware_tests")]
+
+#[path = "./test_common.rs"]
+mod test_common;
+
+#[test_log::test]
+/// Test reading the 96 bit unique device identifier and the UID64 of the target
+fn read_unique_ids() {
+    let programmer = test_common::init_programmer();
+    let target_programmer = test_common::connect_to_target(
+        &programmer,
+        &stm32cubeprogrammer::probe::Protocol::Swd,
+        &Default::default(),
+    );
+
+    let uid96 = target_programmer
+        .unique_id_96()
+        .expect("Failed to read 96 bit UID");
+    log::info!("UID96: {}", uid96);
+
+    // An erased or unreadable system memory would return all ones
+    assert_ne!(uid96.0, [u32::MAX; 3]);
+
+    let uid64 = target_programmer.uid64().expect("Failed to read UID64");
+    log::info!("UID64: {}", uid64);
+
+    // Drop also handles the disconnect
+}